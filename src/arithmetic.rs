@@ -0,0 +1,471 @@
+// Integer arithmetic for $(( )), (( )) and let.
+// The expression is evaluated while it is parsed, with the C operator precedence.
+use crate::variables::Variables;
+
+pub type Result<T> = std::result::Result<T, String>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+// Longest first, so "<<=" is not read as "<" "<="
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "++", "--", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~",
+    "?", ":", "=", ",", "(", ")",
+];
+
+const ASSIGNMENTS: &[&str] = &[
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+// Binary operators from the lowest precedence to the highest, all left associative
+const LEVELS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+// Variables can hold expressions themselves, "a=b+1"
+const MAX_DEPTH: usize = 64;
+
+// 42, 0x2a, 052 and 16#2a
+pub fn parse_number(text: &str) -> Option<i64> {
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        (
+            base.parse().ok().filter(|base| (2..=64).contains(base))?,
+            digits,
+        )
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, hex)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // Bases above 36 use both cases, then @ and _
+        let digit = match c {
+            '0'..='9' => c as i64 - '0' as i64,
+            'a'..='z' => c as i64 - 'a' as i64 + 10,
+            'A'..='Z' if base > 36 => c as i64 - 'A' as i64 + 36,
+            'A'..='Z' => c as i64 - 'A' as i64 + 10,
+            '@' => 62,
+            '_' => 63,
+            _ => return None,
+        };
+        if digit >= base {
+            return None;
+        }
+        value = value.wrapping_mul(base).wrapping_add(digit);
+    }
+    Some(value)
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = expr;
+    loop {
+        rest = rest.trim_start();
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => break,
+        };
+        if c.is_ascii_alphanumeric() || c == '_' {
+            // Numbers can have a base, 16#ff
+            let number = c.is_ascii_digit();
+            let len = rest
+                .find(|c: char| {
+                    !(c.is_ascii_alphanumeric() || c == '_' || (number && matches!(c, '#' | '@')))
+                })
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            if number {
+                let number = parse_number(word)
+                    .ok_or_else(|| format!("{}: value too great for base", word))?;
+                tokens.push(Token::Number(number));
+            } else {
+                tokens.push(Token::Name(word.to_string()));
+            }
+            rest = &rest[len..];
+            continue;
+        }
+        match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            Some(op) => {
+                tokens.push(Token::Op(op));
+                rest = &rest[op.len()..];
+            }
+            None => {
+                return Err(format!(
+                    "syntax error: invalid arithmetic operator (error token is \"{}\")",
+                    rest
+                ))
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    variables: &'a mut Variables,
+    depth: usize,
+    // More than 0 in the branch of && || or ?: that is not taken, nothing is
+    // assigned and errors like division by zero are ignored there
+    skip: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<()> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(token) => format!(
+                "syntax error: `{}' expected (error token is \"{}\")",
+                op,
+                token_text(token)
+            ),
+            None => format!("syntax error: `{}' expected", op),
+        })
+    }
+
+    fn value_of(&mut self, name: &str) -> Result<i64> {
+        let text = self.variables.get(name).unwrap_or("").trim().to_string();
+        if text.is_empty() {
+            return Ok(0);
+        }
+        if let Some(value) = parse_number(&text) {
+            return Ok(value);
+        }
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("{}: expression recursion level exceeded", name));
+        }
+        evaluate_at_depth(self.variables, &text, self.depth + 1)
+    }
+
+    fn assign(&mut self, name: &str, value: i64) -> Result<i64> {
+        if self.skip == 0 {
+            self.variables.set(name, &value.to_string())?;
+        }
+        Ok(value)
+    }
+
+    // expr, expr
+    fn comma(&mut self) -> Result<i64> {
+        let mut value = self.assignment()?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            value = self.assignment()?;
+        }
+        Ok(value)
+    }
+
+    // name = expr, name += expr ...
+    fn assignment(&mut self) -> Result<i64> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if ASSIGNMENTS.contains(op) {
+                let (name, op) = (name.clone(), *op);
+                self.pos += 2;
+                let value = self.assignment()?;
+                let value = if op == "=" {
+                    value
+                } else {
+                    let current = self.value_of(&name)?;
+                    self.binary(&op[..op.len() - 1], current, value)?
+                };
+                return self.assign(&name, value);
+            }
+        }
+        self.ternary()
+    }
+
+    // cond ? a : b
+    fn ternary(&mut self) -> Result<i64> {
+        let condition = self.binary_level(0)?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.skipped(condition == 0, Self::comma)?;
+        self.expect(":")?;
+        let otherwise = self.skipped(condition != 0, Self::ternary)?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    fn skipped(&mut self, skip: bool, f: fn(&mut Self) -> Result<i64>) -> Result<i64> {
+        if skip {
+            self.skip += 1;
+        }
+        let value = f(self);
+        if skip {
+            self.skip -= 1;
+        }
+        value
+    }
+
+    fn binary_level(&mut self, level: usize) -> Result<i64> {
+        if level == LEVELS.len() {
+            return self.power();
+        }
+        let mut value = self.binary_level(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| LEVELS[level].contains(op)) {
+            self.pos += 1;
+            // && and || don't evaluate the right side when the left decides
+            let skip = match op {
+                "&&" => value == 0,
+                "||" => value != 0,
+                _ => false,
+            };
+            if skip {
+                self.skip += 1;
+            }
+            let right = self.binary_level(level + 1);
+            if skip {
+                self.skip -= 1;
+            }
+            value = self.binary(op, value, right?)?;
+        }
+        Ok(value)
+    }
+
+    fn binary(&self, op: &str, left: i64, right: i64) -> Result<i64> {
+        let value = match op {
+            "||" => (left != 0 || right != 0) as i64,
+            "&&" => (left != 0 && right != 0) as i64,
+            "|" => left | right,
+            "^" => left ^ right,
+            "&" => left & right,
+            "==" => (left == right) as i64,
+            "!=" => (left != right) as i64,
+            "<" => (left < right) as i64,
+            "<=" => (left <= right) as i64,
+            ">" => (left > right) as i64,
+            ">=" => (left >= right) as i64,
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" | "%" if right == 0 => {
+                if self.skip > 0 {
+                    return Ok(0);
+                }
+                return Err("division by 0".to_string());
+            }
+            "/" => left.wrapping_div(right),
+            "%" => left.wrapping_rem(right),
+            "**" => {
+                if right < 0 {
+                    return Err("exponent less than 0".to_string());
+                }
+                left.wrapping_pow(right.min(u32::MAX as i64) as u32)
+            }
+            _ => unreachable!("{} is not a binary operator", op),
+        };
+        Ok(value)
+    }
+
+    // a ** b, right associative
+    fn power(&mut self) -> Result<i64> {
+        let base = self.unary()?;
+        if self.peek_op() != Some("**") {
+            return Ok(base);
+        }
+        self.pos += 1;
+        let exponent = self.power()?;
+        self.binary("**", base, exponent)
+    }
+
+    fn unary(&mut self) -> Result<i64> {
+        match self.peek_op() {
+            Some(op @ "-") | Some(op @ "+") | Some(op @ "!") | Some(op @ "~") => {
+                self.pos += 1;
+                let value = self.unary()?;
+                Ok(match op {
+                    "-" => value.wrapping_neg(),
+                    "+" => value,
+                    "!" => (value == 0) as i64,
+                    _ => !value,
+                })
+            }
+            Some(op @ "++") | Some(op @ "--") => {
+                self.pos += 1;
+                let name = match self.peek() {
+                    Some(Token::Name(name)) => name.clone(),
+                    _ => return Err(format!("syntax error: {} needs a variable", op)),
+                };
+                self.pos += 1;
+                let value = self.value_of(&name)?;
+                let value = if op == "++" {
+                    value.wrapping_add(1)
+                } else {
+                    value.wrapping_sub(1)
+                };
+                self.assign(&name, value)
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    // name++ and name--, the value is the one from before
+    fn postfix(&mut self) -> Result<i64> {
+        if let Some(Token::Name(name)) = self.peek() {
+            let name = name.clone();
+            self.pos += 1;
+            let value = self.value_of(&name)?;
+            match self.peek_op() {
+                Some("++") => {
+                    self.pos += 1;
+                    self.assign(&name, value.wrapping_add(1))?;
+                }
+                Some("--") => {
+                    self.pos += 1;
+                    self.assign(&name, value.wrapping_sub(1))?;
+                }
+                _ => {}
+            }
+            return Ok(value);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<i64> {
+        match self.peek().cloned() {
+            Some(Token::Number(value)) => {
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let value = self.comma()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some(token) => Err(format!(
+                "syntax error: operand expected (error token is \"{}\")",
+                token_text(&token)
+            )),
+            None => Err("syntax error: operand expected".to_string()),
+        }
+    }
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Number(value) => value.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Op(op) => op.to_string(),
+    }
+}
+
+fn evaluate_at_depth(variables: &mut Variables, expr: &str, depth: usize) -> Result<i64> {
+    let tokens = tokenize(expr)?;
+    // An empty expression is 0
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut evaluator = Evaluator {
+        tokens,
+        pos: 0,
+        variables,
+        depth,
+        skip: 0,
+    };
+    let value = evaluator.comma()?;
+    if let Some(token) = evaluator.peek() {
+        return Err(format!(
+            "syntax error in expression (error token is \"{}\")",
+            token_text(token)
+        ));
+    }
+    Ok(value)
+}
+
+// evaluate(variables, "x += 2 * 3")
+// The errors start with the expression, "1 / 0: division by 0"
+pub fn evaluate(variables: &mut Variables, expr: &str) -> Result<i64> {
+    evaluate_at_depth(variables, expr, 0).map_err(|err| format!("{}: {}", expr.trim(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::evaluate;
+    use crate::variables::Variables;
+
+    #[test]
+    fn test_evaluate() {
+        let mut variables = Variables::from_env();
+        let v = vec![
+            ("1 + 2 * 3", 7),
+            ("(1 + 2) * 3", 9),
+            ("2 ** 3 ** 2", 512),
+            ("-2 ** 2", 4),
+            ("7 / 2 + 7 % 2", 4),
+            ("1 << 4 | 1", 17),
+            ("5 & 3 ^ 1", 0),
+            ("!0 + ~0", 0),
+            ("3 > 2 && 2 >= 2 && 1 != 2", 1),
+            ("0 || 0", 0),
+            ("1 ? 10 : 20", 10),
+            ("0 ? 10 : 1 ? 20 : 30", 20),
+            ("0x10 + 010 + 2#101", 29),
+            ("", 0),
+            ("zz_arith = 5, zz_arith *= 2", 10),
+            ("zz_arith++ + zz_arith", 21),
+            ("--zz_arith", 10),
+            ("zz_arith_expr = 0, zz_arith_expr", 0),
+            // Nothing is evaluated in the branch that is not taken
+            ("0 && (zz_arith = 1 / 0)", 0),
+            ("1 || zz_arith++", 1),
+            ("zz_arith", 10),
+        ];
+        for (expr, value) in v {
+            assert_eq!(evaluate(&mut variables, expr), Ok(value), "{}", expr);
+        }
+        variables.set("zz_arith_expr", "zz_arith + 1").unwrap();
+        assert_eq!(evaluate(&mut variables, "zz_arith_expr * 2"), Ok(22));
+
+        for expr in &[
+            "1 / 0",
+            "5 % (3 - 3)",
+            "1 +",
+            "(1",
+            "2 ** -1",
+            "1 2",
+            "08",
+            "a ++ b",
+        ] {
+            assert!(evaluate(&mut variables, expr).is_err(), "{}", expr);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use super::builtin_error;
+use crate::shell::Shell;
+use crate::utils;
+
+// Names with these chars could never be the first word of a command
+fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains(|c: char| c.is_whitespace() || "/$`='\"\\|&;<>()".contains(c))
+}
+
+fn print(name: &str, value: &str) {
+    println!("alias {}={}", name, utils::quote(value));
+}
+
+// alias [-p] [name[=value]...]
+pub fn alias(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let args: Vec<String> = args.into_iter().filter(|arg| arg != "-p").collect();
+    if args.is_empty() {
+        for (name, value) in &shell.aliases {
+            print(name, value);
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, value)) => {
+                if !is_valid_alias_name(name) {
+                    builtin_error("alias", format!("`{}': invalid alias name", name));
+                    status = 1;
+                    continue;
+                }
+                shell.aliases.insert(name.to_string(), value.to_string());
+            }
+            None => match shell.aliases.get(&arg) {
+                Some(value) => print(&arg, value),
+                None => {
+                    builtin_error("alias", format!("{}: not found", arg));
+                    status = 1;
+                }
+            },
+        }
+    }
+    status
+}
@@ -0,0 +1,34 @@
+use super::builtin_error;
+use crate::jobs::JobState;
+use crate::shell::Shell;
+
+// bg [job...], continues stopped jobs in the background
+pub fn bg(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let specs = if args.is_empty() {
+        vec!["%+".to_string()]
+    } else {
+        args
+    };
+    let mut status = 0;
+    for spec in specs {
+        let id = match shell.jobs.find(&spec) {
+            Ok(id) => id,
+            Err(err) => {
+                builtin_error("bg", err);
+                status = 1;
+                continue;
+            }
+        };
+        let job = match shell.jobs.get(id) {
+            Some(job) => job,
+            None => continue,
+        };
+        if job.state() == JobState::Running {
+            builtin_error("bg", format!("job {} already in background", id));
+            continue;
+        }
+        println!("[{}]{} {} &", id, shell.jobs.marker(id), job.command);
+        shell.continue_job(id);
+    }
+    status
+}
@@ -0,0 +1,45 @@
+use super::builtin_error;
+use crate::exec::Flow;
+use crate::shell::Shell;
+
+// The n of "break n" and "continue n", None after an error
+pub fn loop_count(shell: &Shell, builtin: &str, args: &[String]) -> Option<usize> {
+    if shell.loop_depth == 0 {
+        builtin_error(
+            builtin,
+            "only meaningful in a `for', `while', or `until' loop",
+        );
+        return None;
+    }
+    let count = match args {
+        [] => 1,
+        [count] => match count.parse::<usize>() {
+            Ok(0) => {
+                builtin_error(builtin, format!("{}: loop count out of range", count));
+                return None;
+            }
+            Ok(count) => count,
+            Err(_) => {
+                builtin_error(builtin, format!("{}: numeric argument required", count));
+                return None;
+            }
+        },
+        _ => {
+            builtin_error(builtin, "too many arguments");
+            return None;
+        }
+    };
+    // "break 5" in two loops breaks out of both
+    Some(count.min(shell.loop_depth))
+}
+
+// break [n], leaves the n innermost loops
+pub fn r#break(shell: &mut Shell, args: Vec<String>) -> i32 {
+    match loop_count(shell, "break", &args) {
+        Some(count) => {
+            shell.flow = Some(Flow::Break(count));
+            0
+        }
+        None => 1,
+    }
+}
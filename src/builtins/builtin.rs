@@ -0,0 +1,15 @@
+use super::{builtin_error, is_builtin};
+use crate::shell::Shell;
+
+// builtin name [args...], runs the builtin even if there is a function with its name
+pub fn builtin(shell: &mut Shell, mut args: Vec<String>) -> i32 {
+    if args.is_empty() {
+        return 0;
+    }
+    let name = args.remove(0);
+    if !is_builtin(&name) {
+        builtin_error("builtin", format!("{}: not a shell builtin", name));
+        return 1;
+    }
+    shell.exec_builtin(&name, args)
+}
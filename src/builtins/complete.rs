@@ -0,0 +1,144 @@
+use super::builtin_error;
+use crate::completion::{fish, CompletionSpec};
+use crate::shell::Shell;
+use crate::utils;
+use std::fs;
+
+const USAGE: &str = "usage: complete [-prfd] [-W words] [-G glob] [-F function] [name...]";
+
+// "complete -f -W 'start stop' svc", the descriptions of fish completions are left out
+fn print(name: &str, spec: &CompletionSpec) {
+    let mut line = String::from("complete");
+    if spec.dirs {
+        line.push_str(" -d");
+    } else if spec.files {
+        line.push_str(" -f");
+    }
+    if !spec.words.is_empty() {
+        let words: Vec<&str> = spec.words.iter().map(|(word, _)| word.as_str()).collect();
+        line.push_str(&format!(" -W {}", utils::quote(&words.join(" "))));
+    }
+    if let Some(glob) = &spec.glob {
+        line.push_str(&format!(" -G {}", utils::quote(glob)));
+    }
+    if let Some(function) = &spec.function {
+        line.push_str(&format!(" -F {}", function));
+    }
+    println!("{} {}", line, utils::quote_if_needed(name));
+}
+
+// complete --fish file...
+fn import(shell: &mut Shell, files: Vec<String>) -> i32 {
+    let mut status = 0;
+    for file in files {
+        match fs::read_to_string(&file) {
+            Ok(text) => fish::import(&text, &mut shell.completions),
+            Err(err) => {
+                builtin_error("complete", format!("{}: {}", file, err));
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+// complete [-fd] [-W words] [-G glob] [-F function] name...
+// complete [-p] [name...] prints the specs, complete -r [name...] removes them
+// and complete --fish file... reads fish completions
+pub fn complete(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let mut spec = CompletionSpec::default();
+    let mut print_specs = false;
+    let mut remove = false;
+    let mut names = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--fish" {
+            return import(shell, args.collect());
+        }
+        if arg == "--" {
+            names.extend(args);
+            break;
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            names.push(arg);
+            continue;
+        }
+        for flag in arg[1..].chars() {
+            match flag {
+                'p' => print_specs = true,
+                'r' => remove = true,
+                'f' => spec.files = true,
+                'd' => spec.dirs = true,
+                'W' | 'G' | 'F' => {
+                    let value = match args.next() {
+                        Some(value) => value,
+                        None => {
+                            builtin_error(
+                                "complete",
+                                format!("-{}: option requires an argument", flag),
+                            );
+                            return 2;
+                        }
+                    };
+                    match flag {
+                        'W' => spec.words.extend(
+                            value
+                                .split_whitespace()
+                                .map(|word| (word.to_string(), None)),
+                        ),
+                        'G' => {
+                            if let Err(err) = glob::Pattern::new(&value) {
+                                builtin_error("complete", format!("{}: {}", value, err.msg));
+                                return 2;
+                            }
+                            spec.glob = Some(value);
+                        }
+                        _ => spec.function = Some(value),
+                    }
+                }
+                _ => {
+                    builtin_error("complete", format!("-{}: invalid option", flag));
+                    builtin_error("complete", USAGE);
+                    return 2;
+                }
+            }
+        }
+    }
+
+    if remove {
+        if names.is_empty() {
+            shell.completions.clear();
+        }
+        for name in names {
+            shell.completions.remove(&name);
+        }
+        return 0;
+    }
+    if print_specs || spec == CompletionSpec::default() {
+        if names.is_empty() {
+            for (name, spec) in &shell.completions {
+                print(name, spec);
+            }
+            return 0;
+        }
+        let mut status = 0;
+        for name in names {
+            match shell.completions.get(&name) {
+                Some(spec) => print(&name, spec),
+                None => {
+                    builtin_error("complete", format!("{}: no completion specification", name));
+                    status = 1;
+                }
+            }
+        }
+        return status;
+    }
+    if names.is_empty() {
+        builtin_error("complete", USAGE);
+        return 2;
+    }
+    for name in names {
+        shell.completions.insert(name, spec.clone());
+    }
+    0
+}
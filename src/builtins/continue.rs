@@ -0,0 +1,14 @@
+use super::r#break::loop_count;
+use crate::exec::Flow;
+use crate::shell::Shell;
+
+// continue [n], goes on with the next iteration of the nth innermost loop
+pub fn r#continue(shell: &mut Shell, args: Vec<String>) -> i32 {
+    match loop_count(shell, "continue", &args) {
+        Some(count) => {
+            shell.flow = Some(Flow::Continue(count));
+            0
+        }
+        None => 1,
+    }
+}
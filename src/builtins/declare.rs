@@ -0,0 +1,167 @@
+use super::builtin_error;
+use crate::arithmetic;
+use crate::parsers::lexer::is_valid_variable_name;
+use crate::shell::Shell;
+use crate::variables;
+
+#[derive(Debug, Default)]
+pub struct Attributes {
+    // Some(false) takes the export attribute away
+    pub exported: Option<bool>,
+    pub readonly: bool,
+    // Some(false) takes the integer attribute away
+    pub integer: Option<bool>,
+    pub local: bool,
+    pub print: bool,
+}
+
+// Prints the variables with the given names, or every variable the filter accepts
+pub fn print(
+    shell: &Shell,
+    builtin: &str,
+    names: &[String],
+    filter: impl Fn(&variables::Variable) -> bool,
+) -> i32 {
+    if names.is_empty() {
+        for (name, variable) in shell.variables.iter().filter(|(_, v)| filter(v)) {
+            println!("{}", variables::declaration(name, variable));
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for name in names {
+        match shell.variables.get_variable(name) {
+            Some(variable) => println!("{}", variables::declaration(name, variable)),
+            None => {
+                builtin_error(builtin, format!("{}: not found", name));
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+// Sets the values and attributes for "name" and "name=value" arguments,
+// shared by declare, local, export and readonly
+pub fn declare_names(
+    shell: &mut Shell,
+    builtin: &str,
+    attributes: &Attributes,
+    args: Vec<String>,
+) -> i32 {
+    if attributes.print {
+        return print(shell, builtin, &args, |_| true);
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !is_valid_variable_name(name) {
+            builtin_error(builtin, format!("`{}': not a valid identifier", arg));
+            status = 1;
+            continue;
+        }
+        // "declare -i n=3+4" sets 7, the variable is not an integer one yet
+        let value = match value {
+            Some(value) if attributes.integer == Some(true) => {
+                match arithmetic::evaluate(&mut shell.variables, value) {
+                    Ok(value) => Some(value.to_string()),
+                    Err(err) => {
+                        builtin_error(builtin, err);
+                        status = 1;
+                        continue;
+                    }
+                }
+            }
+            value => value.map(str::to_string),
+        };
+        let result = if attributes.local {
+            shell.variables.set_local(name, value.as_deref())
+        } else if let Some(value) = &value {
+            shell.variables.set(name, value)
+        } else {
+            Ok(())
+        };
+        if let Err(err) = result {
+            builtin_error(builtin, err);
+            status = 1;
+            continue;
+        }
+        if attributes.exported.is_some() || attributes.readonly || attributes.integer.is_some() {
+            shell.variables.set_attributes(
+                name,
+                attributes.exported,
+                attributes.readonly,
+                attributes.integer,
+            );
+        }
+    }
+    status
+}
+
+// Splits "-rx" and "+x" style options from the names, calls f for each option letter
+pub fn parse_options(
+    builtin: &str,
+    args: Vec<String>,
+    mut f: impl FnMut(char, bool) -> bool,
+) -> Option<Vec<String>> {
+    let mut names = Vec::new();
+    let mut options_done = false;
+    for arg in args {
+        if options_done || arg.len() < 2 || !(arg.starts_with('-') || arg.starts_with('+')) {
+            options_done = true;
+            names.push(arg);
+            continue;
+        }
+        if arg == "--" {
+            options_done = true;
+            continue;
+        }
+        let on = arg.starts_with('-');
+        for option in arg.chars().skip(1) {
+            if !f(option, on) {
+                builtin_error(builtin, format!("{}{}: invalid option", &arg[..1], option));
+                return None;
+            }
+        }
+    }
+    Some(names)
+}
+
+// declare [-p] [-irx] [+ix] [name[=value]...]
+pub fn declare(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let mut attributes = Attributes {
+        // Variables declared in a function are local to it
+        local: shell.function_depth > 0,
+        ..Default::default()
+    };
+    let names = match parse_options("declare", args, |option, on| {
+        match option {
+            'x' => attributes.exported = Some(on),
+            'r' if on => attributes.readonly = true,
+            'i' => attributes.integer = Some(on),
+            'p' => attributes.print = true,
+            _ => return false,
+        }
+        true
+    }) {
+        Some(names) => names,
+        None => return 2,
+    };
+    if names.is_empty() {
+        let Attributes {
+            exported,
+            readonly,
+            integer,
+            ..
+        } = attributes;
+        return print(shell, "declare", &names, |variable| {
+            exported.is_none_or(|exported| variable.exported == exported)
+                && (!readonly || variable.readonly)
+                && integer.is_none_or(|integer| variable.integer == integer)
+        });
+    }
+    declare_names(shell, "declare", &attributes, names)
+}
@@ -0,0 +1,50 @@
+use super::builtin_error;
+use crate::jobs::JobState;
+use crate::shell::Shell;
+
+// disown [-a | -r] [job...], removes jobs from the table so they are not waited for
+pub fn disown(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let mut all = false;
+    let mut running = false;
+    let mut specs = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-a" => all = true,
+            "-r" => running = true,
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                builtin_error("disown", format!("{}: invalid option", flag));
+                return 2;
+            }
+            _ => specs.push(arg),
+        }
+    }
+
+    let ids: Vec<usize> = if all || (running && specs.is_empty()) {
+        shell
+            .jobs
+            .iter()
+            .filter(|job| !running || job.state() == JobState::Running)
+            .map(|job| job.id)
+            .collect()
+    } else {
+        if specs.is_empty() {
+            specs.push("%+".to_string());
+        }
+        let mut ids = Vec::new();
+        for spec in specs {
+            match shell.jobs.find(&spec) {
+                Ok(id) => ids.push(id),
+                Err(err) => {
+                    builtin_error("disown", err);
+                    return 1;
+                }
+            }
+        }
+        ids
+    };
+
+    for id in ids {
+        shell.jobs.remove(id);
+    }
+    0
+}
@@ -0,0 +1,62 @@
+use super::builtin_error;
+use crate::exec::exec_command;
+use crate::redirect::Redirections;
+use crate::shell::Shell;
+use crate::terminal;
+use crate::utils;
+
+// exec [command [args...]], replaces the shell with the command. The
+// redirections are applied to the shell itself and kept, so without a command
+// "exec 3>file" opens fd 3 for the commands after it and "exec 3>&-" closes it
+pub fn exec(shell: &mut Shell, mut args: Vec<String>, redirections: &Redirections) -> i32 {
+    if let Err(err) = redirections.apply_permanently() {
+        builtin_error("exec", err);
+        return 1;
+    }
+    if args.is_empty() {
+        return 0;
+    }
+    let command = args.remove(0);
+    if shell.job_control {
+        terminal::reset_signals();
+    }
+    let status = exec_command(&command, args);
+    // A script can't go on without the command it meant to become
+    if !shell.interactive {
+        utils::exit(status);
+    }
+    if shell.job_control {
+        terminal::ignore_signals();
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::exec;
+    use crate::redirect::Redirections;
+    use crate::shell::Shell;
+
+    // Runs exec in a forked child, it doesn't come back, and returns how the child exited
+    fn exit_status(args: &[&str]) -> i32 {
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            let mut shell = Shell::new();
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            exec(&mut shell, args, &Redirections::default());
+            unsafe { libc::_exit(100) };
+        }
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0) };
+        libc::WEXITSTATUS(status)
+    }
+
+    #[test]
+    fn test_exec() {
+        assert_eq!(exit_status(&["sh", "-c", "exit 3"]), 3);
+        assert_eq!(exit_status(&["zash-no-such-command"]), 127);
+        assert_eq!(exit_status(&["/"]), 126);
+        // Without a command the shell goes on
+        assert_eq!(exit_status(&[]), 100);
+    }
+}
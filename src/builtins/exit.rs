@@ -2,7 +2,7 @@ use crate::utils;
 
 pub fn exit(args: Vec<String>) -> i32
 {
-    if let Some(exit_code) = args.get(0)
+    if let Some(exit_code) = args.first()
     {
         utils::exit(match exit_code.to_string().parse::<i32>(){
             Ok(m) => m,
@@ -14,5 +14,4 @@ pub fn exit(args: Vec<String>) -> i32
     } else {
         utils::exit(0);
     }
-    0
 }
\ No newline at end of file
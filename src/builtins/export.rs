@@ -0,0 +1,26 @@
+use super::declare::{declare_names, parse_options, print, Attributes};
+use crate::shell::Shell;
+
+// export [-n] [-p] [name[=value]...]
+pub fn export(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let mut attributes = Attributes {
+        exported: Some(true),
+        ..Default::default()
+    };
+    let names = match parse_options("export", args, |option, on| {
+        match option {
+            // Takes the export attribute away
+            'n' if on => attributes.exported = Some(false),
+            'p' if on => attributes.print = true,
+            _ => return false,
+        }
+        true
+    }) {
+        Some(names) => names,
+        None => return 2,
+    };
+    if names.is_empty() {
+        return print(shell, "export", &names, |variable| variable.exported);
+    }
+    declare_names(shell, "export", &attributes, names)
+}
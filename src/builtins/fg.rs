@@ -0,0 +1,34 @@
+use super::builtin_error;
+use crate::shell::Shell;
+use crate::terminal;
+
+// fg [job], continues a job in the foreground and waits for it
+pub fn fg(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if args.len() > 1 {
+        builtin_error("fg", "too many arguments");
+        return 1;
+    }
+    let spec = args.first().map_or("%+", |arg| arg.as_str());
+    let id = match shell.jobs.find(spec) {
+        Ok(id) => id,
+        Err(err) => {
+            builtin_error("fg", err);
+            return 1;
+        }
+    };
+    let job_control = shell.job_control;
+    if let Some(job) = shell.jobs.get_mut(id) {
+        job.background = false;
+        println!("{}", job.command);
+        // The terminal has to be given back before the job continues, or it
+        // would get stopped again as soon as it reads from it
+        if job_control {
+            terminal::give_to(job.pgid);
+            if let Some(modes) = job.modes.take() {
+                terminal::set_modes(&modes).ok();
+            }
+        }
+    }
+    shell.continue_job(id);
+    shell.wait_job(id)
+}
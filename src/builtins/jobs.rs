@@ -0,0 +1,55 @@
+use super::builtin_error;
+use crate::jobs::JobState;
+use crate::shell::Shell;
+
+// jobs [-l | -p] [job...]
+pub fn jobs(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let mut pids = false;
+    let mut only_pids = false;
+    let mut specs = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-l" => pids = true,
+            "-p" => only_pids = true,
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                builtin_error("jobs", format!("{}: invalid option", flag));
+                return 2;
+            }
+            _ => specs.push(arg),
+        }
+    }
+
+    shell.jobs.reap();
+    let ids: Vec<usize> = if specs.is_empty() {
+        shell.jobs.iter().map(|job| job.id).collect()
+    } else {
+        let mut ids = Vec::new();
+        for spec in specs {
+            match shell.jobs.find(&spec) {
+                Ok(id) => ids.push(id),
+                Err(err) => {
+                    builtin_error("jobs", err);
+                    return 1;
+                }
+            }
+        }
+        ids
+    };
+
+    for id in ids {
+        let job = match shell.jobs.get(id) {
+            Some(job) => job,
+            None => continue,
+        };
+        if only_pids {
+            println!("{}", job.pgid);
+        } else {
+            println!("{}", shell.jobs.format(job, pids));
+        }
+        // Finished jobs are shown once, like with the notification before the prompt
+        if let JobState::Done(_) = job.state() {
+            shell.jobs.remove(id);
+        }
+    }
+    0
+}
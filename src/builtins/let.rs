@@ -0,0 +1,22 @@
+use super::builtin_error;
+use crate::arithmetic;
+use crate::shell::Shell;
+
+// let expr..., the status is 0 if the last value is not 0
+pub fn r#let(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if args.is_empty() {
+        builtin_error("let", "expression expected");
+        return 1;
+    }
+    let mut value = 0;
+    for arg in args {
+        match arithmetic::evaluate(&mut shell.variables, &arg) {
+            Ok(m) => value = m,
+            Err(err) => {
+                builtin_error("let", err);
+                return 1;
+            }
+        }
+    }
+    (value == 0) as i32
+}
@@ -0,0 +1,29 @@
+use super::builtin_error;
+use super::declare::{declare_names, parse_options, Attributes};
+use crate::shell::Shell;
+
+// local [-irx] [name[=value]...], only inside functions
+pub fn local(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if shell.function_depth == 0 {
+        builtin_error("local", "can only be used in a function");
+        return 1;
+    }
+    let mut attributes = Attributes {
+        local: true,
+        ..Default::default()
+    };
+    let names = match parse_options("local", args, |option, on| {
+        match option {
+            'x' => attributes.exported = Some(on),
+            'r' if on => attributes.readonly = true,
+            'i' => attributes.integer = Some(on),
+            'p' => attributes.print = true,
+            _ => return false,
+        }
+        true
+    }) {
+        Some(names) => names,
+        None => return 2,
+    };
+    declare_names(shell, "local", &attributes, names)
+}
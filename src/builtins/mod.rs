@@ -1,2 +1,43 @@
+use colored::Colorize;
+
+use crate::utils;
+
+pub mod alias;
+pub mod bg;
+pub mod r#break;
+pub mod builtin;
 pub mod cd;
-pub mod exit;
\ No newline at end of file
+pub mod complete;
+pub mod r#continue;
+pub mod declare;
+pub mod disown;
+pub mod exec;
+pub mod exit;
+pub mod export;
+pub mod fg;
+pub mod jobs;
+pub mod r#let;
+pub mod local;
+pub mod readonly;
+pub mod r#return;
+pub mod set;
+pub mod shift;
+pub mod source;
+pub mod unalias;
+pub mod unset;
+pub mod wait;
+
+pub const BUILTINS: &[&str] = &[
+    "cd", "exit", "jobs", "fg", "bg", "disown", "wait", "export", "unset", "readonly", "declare",
+    "local", "let", "break", "continue", "shift", "return", "builtin", "alias", "unalias",
+    "source", ".", "set", "complete", "exec",
+];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+// "zash: fg: %3: no such job"
+pub fn builtin_error<T: std::string::ToString>(builtin: &str, error: T) {
+    utils::zash_error(format!("{}: {}", builtin.red(), error.to_string()));
+}
@@ -0,0 +1,24 @@
+use super::declare::{declare_names, parse_options, print, Attributes};
+use crate::shell::Shell;
+
+// readonly [-p] [name[=value]...]
+pub fn readonly(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let mut attributes = Attributes {
+        readonly: true,
+        ..Default::default()
+    };
+    let names = match parse_options("readonly", args, |option, on| {
+        match option {
+            'p' if on => attributes.print = true,
+            _ => return false,
+        }
+        true
+    }) {
+        Some(names) => names,
+        None => return 2,
+    };
+    if names.is_empty() {
+        return print(shell, "readonly", &names, |variable| variable.readonly);
+    }
+    declare_names(shell, "readonly", &attributes, names)
+}
@@ -0,0 +1,24 @@
+use super::builtin_error;
+use crate::exec::Flow;
+use crate::shell::Shell;
+
+// return [n], without n the status is the one of the last command
+pub fn r#return(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if shell.function_depth == 0 && shell.source_depth == 0 {
+        builtin_error(
+            "return",
+            "can only `return' from a function or sourced script",
+        );
+        return 1;
+    }
+    let status = match args.first().map(|status| status.parse::<i32>()) {
+        None => shell.status,
+        Some(Ok(status)) => status & 0xff,
+        Some(Err(_)) => {
+            builtin_error("return", format!("{}: numeric argument required", args[0]));
+            2
+        }
+    };
+    shell.flow = Some(Flow::Return);
+    status
+}
@@ -0,0 +1,60 @@
+use super::builtin_error;
+use crate::shell::Shell;
+use crate::utils;
+
+// "set -o" with no name
+fn print_options(shell: &Shell) {
+    println!("xtrace\t{}", if shell.xtrace { "on" } else { "off" });
+}
+
+// set [-x|+x] [-o xtrace|+o xtrace] [--] [arg...]
+// Without arguments it prints the variables, the other arguments replace $1, $2...
+pub fn set(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if args.is_empty() {
+        for (name, variable) in shell.variables.iter() {
+            if let Some(value) = &variable.value {
+                println!("{}={}", name, utils::quote(value));
+            }
+        }
+        return 0;
+    }
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.peek() {
+        if arg == "--" {
+            args.next();
+            shell.positional = args.collect();
+            return 0;
+        }
+        let enable = match arg.chars().next() {
+            Some('-') => true,
+            Some('+') => false,
+            _ => break,
+        };
+        if arg.len() == 1 {
+            break;
+        }
+        let arg = args.next().unwrap();
+        for flag in arg[1..].chars() {
+            match flag {
+                'x' => shell.xtrace = enable,
+                'o' => match args.next().as_deref() {
+                    Some("xtrace") => shell.xtrace = enable,
+                    Some(name) => {
+                        builtin_error("set", format!("{}: invalid option name", name));
+                        return 2;
+                    }
+                    None => print_options(shell),
+                },
+                _ => {
+                    builtin_error("set", format!("{}{}: invalid option", &arg[..1], flag));
+                    return 2;
+                }
+            }
+        }
+    }
+    let rest: Vec<String> = args.collect();
+    if !rest.is_empty() {
+        shell.positional = rest;
+    }
+    0
+}
@@ -0,0 +1,20 @@
+use super::builtin_error;
+use crate::shell::Shell;
+
+// shift [n], drops the first n positional parameters
+pub fn shift(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let count = match args.first().map(|count| count.parse::<usize>()) {
+        None => 1,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            builtin_error("shift", format!("{}: numeric argument required", args[0]));
+            return 1;
+        }
+    };
+    if count > shell.positional.len() {
+        builtin_error("shift", "shift count out of range");
+        return 1;
+    }
+    shell.positional.drain(..count);
+    0
+}
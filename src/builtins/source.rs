@@ -0,0 +1,49 @@
+use super::builtin_error;
+use crate::scripting;
+use crate::shell::Shell;
+use crate::utils;
+use std::path::{Path, PathBuf};
+
+// A name without a slash is looked for in PATH first, then in the current directory
+fn find_file(name: &str) -> PathBuf {
+    if !name.contains('/') {
+        let path = std::env::var("PATH").unwrap_or_default();
+        if let Some(found) = path
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Path::new(dir).join(name))
+            .find(|file| file.is_file())
+        {
+            return found;
+        }
+    }
+    PathBuf::from(name)
+}
+
+// source file [args...] and . file [args...], runs the file in the current shell
+pub fn source(shell: &mut Shell, builtin: &str, mut args: Vec<String>) -> i32 {
+    if args.is_empty() {
+        builtin_error(builtin, "filename argument required");
+        return 2;
+    }
+    let name = args.remove(0);
+    let file = find_file(&name);
+    // The arguments replace the positional parameters while the file runs
+    let positional = if args.is_empty() {
+        None
+    } else {
+        Some(std::mem::replace(&mut shell.positional, args))
+    };
+    let status = match scripting::source(shell, file.display().to_string()) {
+        Ok(status) => status,
+        Err(err) => {
+            let errno = err.raw_os_error().unwrap_or(0);
+            builtin_error(builtin, format!("{}: {}", name, utils::error_string(errno)));
+            1
+        }
+    };
+    if let Some(positional) = positional {
+        shell.positional = positional;
+    }
+    status
+}
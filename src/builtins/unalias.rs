@@ -0,0 +1,22 @@
+use super::builtin_error;
+use crate::shell::Shell;
+
+// unalias [-a] name...
+pub fn unalias(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if args.first().map(String::as_str) == Some("-a") {
+        shell.aliases.clear();
+        return 0;
+    }
+    if args.is_empty() {
+        builtin_error("unalias", "usage: unalias [-a] name [name ...]");
+        return 2;
+    }
+    let mut status = 0;
+    for name in args {
+        if shell.aliases.remove(&name).is_none() {
+            builtin_error("unalias", format!("{}: not found", name));
+            status = 1;
+        }
+    }
+    status
+}
@@ -0,0 +1,37 @@
+use super::builtin_error;
+use super::declare::parse_options;
+use crate::parsers::lexer::is_valid_variable_name;
+use crate::shell::Shell;
+
+// unset [-fv] name..., -f removes functions
+pub fn unset(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let mut functions = false;
+    let names = match parse_options("unset", args, |option, on| match option {
+        'f' if on => {
+            functions = true;
+            true
+        }
+        'v' => on,
+        _ => false,
+    }) {
+        Some(names) => names,
+        None => return 2,
+    };
+    let mut status = 0;
+    for name in names {
+        if functions {
+            shell.functions.remove(&name);
+            continue;
+        }
+        if !is_valid_variable_name(&name) {
+            builtin_error("unset", format!("`{}': not a valid identifier", name));
+            status = 1;
+            continue;
+        }
+        if let Err(err) = shell.variables.unset(&name) {
+            builtin_error("unset", err);
+            status = 1;
+        }
+    }
+    status
+}
@@ -0,0 +1,42 @@
+use super::builtin_error;
+use crate::shell::Shell;
+
+// wait [pid | job...], without arguments waits for every background job
+pub fn wait(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if args.is_empty() {
+        let ids: Vec<usize> = shell.jobs.iter().map(|job| job.id).collect();
+        for id in ids {
+            shell.wait_job(id);
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let id = if arg.starts_with('%') {
+            shell.jobs.find(&arg)
+        } else {
+            match arg.parse::<i32>() {
+                Ok(pid) => shell
+                    .jobs
+                    .iter()
+                    .find(|job| job.processes.iter().any(|p| p.pid == pid))
+                    .map(|job| job.id)
+                    .ok_or_else(|| format!("pid {} is not a child of this shell", pid)),
+                Err(_) => {
+                    builtin_error("wait", format!("{}: not a pid or valid job spec", arg));
+                    status = 2;
+                    continue;
+                }
+            }
+        };
+        status = match id {
+            Ok(id) => shell.wait_job(id),
+            Err(err) => {
+                builtin_error("wait", err);
+                127
+            }
+        };
+    }
+    status
+}
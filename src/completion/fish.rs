@@ -0,0 +1,232 @@
+// Imports the "complete -c git -l help -d 'Show help'" lines that fish completion
+// files are made of. The rest of such a file is fish code and is skipped, like
+// the completions that only apply under a condition zash can't check.
+use std::collections::BTreeMap;
+
+use super::CompletionSpec;
+
+pub fn import(text: &str, specs: &mut BTreeMap<String, CompletionSpec>) {
+    let mut line = String::new();
+    for part in text.lines() {
+        // A backslash at the end continues the line
+        if let Some(start) = part.strip_suffix('\\') {
+            line.push_str(start);
+            continue;
+        }
+        line.push_str(part);
+        let words = words(&line);
+        if words.first().map(String::as_str) == Some("complete") {
+            declaration(&words[1..], specs);
+        }
+        line.clear();
+    }
+}
+
+// The options of one "complete" line
+#[derive(Default)]
+struct Declaration {
+    commands: Vec<String>,
+    options: Vec<String>,
+    arguments: Vec<String>,
+    description: Option<String>,
+    condition: Option<String>,
+    no_files: bool,
+    erase: bool,
+}
+
+fn declaration(args: &[String], specs: &mut BTreeMap<String, CompletionSpec>) {
+    let mut declaration = Declaration::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(long) = arg.strip_prefix("--") {
+            // --long-option=help or --long-option help
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let flag = match name {
+                "command" | "path" => 'c',
+                "short-option" => 's',
+                "long-option" => 'l',
+                "old-option" => 'o',
+                "description" => 'd',
+                "arguments" => 'a',
+                "condition" => 'n',
+                "wraps" => 'w',
+                "no-files" => 'f',
+                "exclusive" => 'x',
+                "erase" => 'e',
+                _ => continue,
+            };
+            let value = match takes_value(flag) {
+                true => value.or_else(|| args.next().cloned()),
+                false => None,
+            };
+            set(&mut declaration, flag, value);
+        } else if let Some(flags) = arg.strip_prefix('-') {
+            // "-xa 'start stop'", the value is the rest of the word or the next one
+            for (index, flag) in flags.char_indices() {
+                if takes_value(flag) {
+                    let rest = &flags[index + 1..];
+                    let value = match rest.is_empty() {
+                        true => args.next().cloned(),
+                        false => Some(rest.to_string()),
+                    };
+                    set(&mut declaration, flag, value);
+                    break;
+                }
+                set(&mut declaration, flag, None);
+            }
+        }
+    }
+
+    // "__fish_use_subcommand" is true for the first argument, close enough
+    let conditional = declaration
+        .condition
+        .as_ref()
+        .is_some_and(|condition| !condition.contains("__fish_use_subcommand"));
+    if declaration.erase || conditional {
+        return;
+    }
+    let mut words = declaration.options;
+    // Arguments like "(__fish_complete_users)" are fish commands to run
+    words.extend(
+        declaration
+            .arguments
+            .iter()
+            .flat_map(|arguments| arguments.split_whitespace())
+            .filter(|word| !word.contains(['(', ')', '$']))
+            .map(String::from),
+    );
+    for command in declaration.commands {
+        // Fish offers files too unless told not to
+        let spec = specs.entry(command).or_insert_with(|| CompletionSpec {
+            files: true,
+            ..CompletionSpec::default()
+        });
+        if declaration.no_files && declaration.condition.is_none() {
+            spec.files = false;
+        }
+        for word in &words {
+            if !spec.words.iter().any(|(existing, _)| existing == word) {
+                spec.words
+                    .push((word.clone(), declaration.description.clone()));
+            }
+        }
+    }
+}
+
+fn takes_value(flag: char) -> bool {
+    matches!(flag, 'c' | 'p' | 's' | 'l' | 'o' | 'd' | 'a' | 'n' | 'w')
+}
+
+fn set(declaration: &mut Declaration, flag: char, value: Option<String>) {
+    match (flag, value) {
+        ('c', Some(command)) | ('p', Some(command)) => declaration.commands.push(command),
+        ('s', Some(name)) | ('o', Some(name)) => declaration.options.push(format!("-{}", name)),
+        ('l', Some(name)) => declaration.options.push(format!("--{}", name)),
+        ('d', description) => declaration.description = description,
+        ('a', Some(arguments)) => declaration.arguments.push(arguments),
+        ('n', condition) => declaration.condition = condition,
+        ('f', _) | ('x', _) => declaration.no_files = true,
+        ('e', _) => declaration.erase = true,
+        _ => {}
+    }
+}
+
+// Splits a line of fish like a shell would, with quotes and backslashes.
+// Stops at a comment
+fn words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), '\\') | (Some('"'), '\\') => {
+                // Only quotes and backslashes are escaped in quotes
+                match chars.next() {
+                    Some(next) if next == '\\' || Some(next) == quote => word.push(next),
+                    Some(next) => {
+                        word.push('\\');
+                        word.push(next);
+                    }
+                    None => {}
+                }
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (None, '#') if !in_word => break,
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        assert_eq!(
+            words(r#"complete -c foo -d 'It\'s "here"' # comment"#),
+            vec!["complete", "-c", "foo", "-d", "It's \"here\""]
+        );
+
+        let mut specs = BTreeMap::new();
+        import(
+            r#"
+# Completions for svc
+function __svc_units
+    systemctl list-units
+end
+complete -c svc -f
+complete -c svc -s h -l help -d 'Show help'
+complete -c svc -n __fish_use_subcommand -xa 'start stop' \
+    -d Command
+complete -c svc -n '__fish_seen_subcommand_from start' -a '(__svc_units)'
+complete --command=other --long-option=verbose
+complete -c svc -xa '(__svc_units) restart'
+"#,
+            &mut specs,
+        );
+        let svc = &specs["svc"];
+        assert!(!svc.files);
+        let help = Some("Show help".to_string());
+        let command = Some("Command".to_string());
+        assert_eq!(
+            svc.words,
+            vec![
+                ("-h".to_string(), help.clone()),
+                ("--help".to_string(), help),
+                ("start".to_string(), command.clone()),
+                ("stop".to_string(), command),
+                ("restart".to_string(), None),
+            ]
+        );
+        assert!(specs["other"].files);
+        assert_eq!(specs["other"].words[0].0, "--verbose");
+    }
+}
@@ -0,0 +1,653 @@
+// Tab completion, what is offered depends on where the cursor is in the command line
+// and on the specs given with the complete builtin
+use rustyline::completion::Pair;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::builtins;
+use crate::config;
+use crate::parsers::lexer::{self, is_valid_variable_name};
+use crate::parsers::parser;
+use crate::parsers::tokens::{Operator, TokenKind};
+use crate::scripting;
+use crate::shell::Shell;
+use crate::utils;
+
+pub mod fish;
+
+// What to complete for the arguments of a command, from the complete builtin
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompletionSpec {
+    // -W, and the options and arguments of fish completions with their descriptions
+    pub words: Vec<(String, Option<String>)>,
+    // -f
+    pub files: bool,
+    // -d, only directories
+    pub dirs: bool,
+    // -G, files matching the pattern
+    pub glob: Option<String>,
+    // -F, a function that puts the candidates in COMPREPLY
+    pub function: Option<String>,
+}
+
+// Where the word being completed is
+#[derive(Debug, Clone, PartialEq)]
+enum Position {
+    // The first word of a command
+    Command,
+    // An argument, after these words of the command. Empty if they are not known
+    Argument(Vec<String>),
+    // The file after > or <
+    Redirect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Files {
+    All,
+    Dirs,
+    // Directories too, to get to the executables in them
+    Executables,
+}
+
+// The line editor can't borrow the shell, so the names it knows are copied
+// before each prompt
+#[derive(Default)]
+pub struct ShellCompleter {
+    functions: Vec<String>,
+    aliases: Vec<String>,
+    variables: Vec<String>,
+    // Job ids and their commands
+    jobs: Vec<(usize, String)>,
+    path: String,
+    home: String,
+    // $PATH and the executables in it, read again when it changes
+    executables: RefCell<(String, Vec<String>)>,
+    specs: BTreeMap<String, CompletionSpec>,
+    // To run the -F functions in, only copied when there are some
+    shell: RefCell<Option<Shell>>,
+}
+
+impl ShellCompleter {
+    pub fn new() -> Self {
+        Self {
+            home: utils::get_home_dir(),
+            ..Self::default()
+        }
+    }
+
+    pub fn update(&mut self, shell: &Shell) {
+        self.functions = shell.functions.keys().cloned().collect();
+        self.aliases = shell.aliases.keys().cloned().collect();
+        self.variables = shell
+            .variables
+            .iter()
+            .filter(|(_, variable)| variable.value.is_some())
+            .map(|(name, _)| name.clone())
+            .collect();
+        self.jobs = shell
+            .jobs
+            .iter()
+            .map(|job| (job.id, job.command.clone()))
+            .collect();
+        self.path = shell.variables.get("PATH").unwrap_or_default().to_string();
+        self.specs = shell.completions.clone();
+        let functions = self.specs.values().any(|spec| spec.function.is_some());
+        *self.shell.borrow_mut() = functions.then(|| shell.clone());
+    }
+
+    // Where the replaced text starts and the candidates for it
+    pub fn complete(&self, line: &str, pos: usize) -> (usize, Vec<Pair>) {
+        let start = word_start(&line[..pos]);
+        let word = &line[start..pos];
+        if let Some(name_start) = variable_start(word) {
+            let braced = word[..name_start].ends_with('{');
+            return (
+                start + name_start,
+                self.complete_variable(&word[name_start..], braced),
+            );
+        }
+        if word.starts_with('%') && !self.jobs.is_empty() {
+            return (start, self.complete_job(word));
+        }
+        let candidates = match position(&line[..start]) {
+            Position::Command if !word.contains('/') => self.complete_command(word),
+            Position::Command => self.complete_path(word, Files::Executables),
+            Position::Argument(words) => match self.spec(&words) {
+                Some(spec) => self.complete_spec(spec, &words, word, line, pos),
+                None if words.first().map(String::as_str) == Some("cd") => {
+                    self.complete_path(word, Files::Dirs)
+                }
+                None => self.complete_path(word, Files::All),
+            },
+            Position::Redirect => self.complete_path(word, Files::All),
+        };
+        (start, candidates)
+    }
+
+    // The spec for "git" is used for "/usr/bin/git" too
+    fn spec(&self, words: &[String]) -> Option<&CompletionSpec> {
+        let command = words.first()?;
+        let name = command.rsplit('/').next().unwrap_or(command);
+        self.specs.get(command).or_else(|| self.specs.get(name))
+    }
+
+    fn complete_spec(
+        &self,
+        spec: &CompletionSpec,
+        words: &[String],
+        word: &str,
+        line: &str,
+        pos: usize,
+    ) -> Vec<Pair> {
+        let mut candidates: Vec<Pair> = spec
+            .words
+            .iter()
+            .filter(|(candidate, _)| candidate.starts_with(word))
+            .map(|(candidate, description)| Pair {
+                display: match description {
+                    Some(description) => format!("{}  ({})", candidate, description),
+                    None => candidate.clone(),
+                },
+                replacement: format!("{} ", escape(candidate)),
+            })
+            .collect();
+        if let Some(function) = &spec.function {
+            candidates.extend(
+                self.run_function(function, words, word, line, pos)
+                    .into_iter()
+                    .map(|candidate| Pair {
+                        replacement: format!("{} ", escape(&candidate)),
+                        display: candidate,
+                    }),
+            );
+        }
+        if spec.dirs {
+            candidates.extend(self.complete_path(word, Files::Dirs));
+        } else if spec.files {
+            candidates.extend(self.complete_path(word, Files::All));
+        } else if let Some(pattern) = spec
+            .glob
+            .as_ref()
+            .and_then(|glob| glob::Pattern::new(glob).ok())
+        {
+            // Directories too, the matching files may be in them
+            candidates.extend(
+                self.complete_path(word, Files::All)
+                    .into_iter()
+                    .filter(|pair| pair.display.ends_with('/') || pattern.matches(&pair.display)),
+            );
+        }
+        candidates
+    }
+
+    // Calls "function command word previous" in a subshell like bash does, with
+    // COMP_LINE, COMP_POINT and COMP_CWORD set. The words it puts in COMPREPLY
+    // that start like the word are the candidates, there is no compgen to do that
+    fn run_function(
+        &self,
+        function: &str,
+        words: &[String],
+        word: &str,
+        line: &str,
+        pos: usize,
+    ) -> Vec<String> {
+        let mut shell = self.shell.borrow_mut();
+        let shell = match shell.as_mut() {
+            Some(shell) => shell,
+            None => return Vec::new(),
+        };
+        let variables = [
+            ("COMP_LINE", line.to_string()),
+            ("COMP_POINT", pos.to_string()),
+            ("COMP_CWORD", words.len().to_string()),
+        ];
+        for (name, value) in &variables {
+            if shell.variables.set(name, value).is_err() {
+                return Vec::new();
+            }
+        }
+        if shell.variables.unset("COMPREPLY").is_err() {
+            return Vec::new();
+        }
+        // Anything it prints would mess up the line being edited
+        let command = format!(
+            "{} {} {} {} >/dev/null 2>&1; echo \"$COMPREPLY\"",
+            utils::quote(function),
+            utils::quote(&words[0]),
+            utils::quote(word),
+            utils::quote(words.last().unwrap_or(&words[0]))
+        );
+        match parser::parse_line(&command).map(|list| shell.capture(&list)) {
+            Ok(Ok(output)) => output
+                .split_whitespace()
+                .filter(|candidate| candidate.starts_with(word))
+                .map(String::from)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn complete_command(&self, word: &str) -> Vec<Pair> {
+        let mut names: Vec<String> = builtins::BUILTINS
+            .iter()
+            .map(|name| name.to_string())
+            .chain(self.functions.iter().cloned())
+            .chain(self.aliases.iter().cloned())
+            .filter(|name| name.starts_with(word))
+            .collect();
+        self.update_executables();
+        let executables = &self.executables.borrow().1;
+        names.extend(
+            executables
+                .iter()
+                .filter(|name| name.starts_with(word))
+                .cloned(),
+        );
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .map(|name| Pair {
+                replacement: format!("{} ", escape(&name)),
+                display: name,
+            })
+            .collect()
+    }
+
+    fn update_executables(&self) {
+        if self.executables.borrow().0 == self.path {
+            return;
+        }
+        let mut names = Vec::new();
+        for dir in self.path.split(':').filter(|dir| !dir.is_empty()) {
+            for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+                let executable = fs::metadata(entry.path())
+                    .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+                    .unwrap_or(false);
+                if executable {
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+        }
+        *self.executables.borrow_mut() = (self.path.clone(), names);
+    }
+
+    fn complete_variable(&self, prefix: &str, braced: bool) -> Vec<Pair> {
+        self.variables
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: if braced {
+                    format!("{}}}", name)
+                } else {
+                    name.clone()
+                },
+            })
+            .collect()
+    }
+
+    // "%" or "%2" gives the job numbers, "%vi" the command name, since the line
+    // editor only puts in candidates longer than the word. Shown with their commands
+    fn complete_job(&self, word: &str) -> Vec<Pair> {
+        let body = &word[1..];
+        let by_number = body.chars().all(|c| c.is_ascii_digit());
+        self.jobs
+            .iter()
+            .filter_map(|(id, command)| {
+                let replacement = if by_number && id.to_string().starts_with(body) {
+                    format!("%{} ", id)
+                } else if !by_number && command.starts_with(body) {
+                    let name = command.split_whitespace().next().unwrap_or(command);
+                    format!("%{} ", name)
+                } else {
+                    return None;
+                };
+                Some(Pair {
+                    display: format!("%{}  {}", id, command),
+                    replacement,
+                })
+            })
+            .collect()
+    }
+
+    fn complete_path(&self, word: &str, files: Files) -> Vec<Pair> {
+        let (quote, text) = unquote(word);
+        // "~/src" is looked for in the home dir but stays "~/src" in the line
+        let tilde = word.starts_with('~') && (text == "~" || text.starts_with("~/"));
+        if tilde && text == "~" {
+            return vec![Pair {
+                display: "~/".to_string(),
+                replacement: "~/".to_string(),
+            }];
+        }
+        let text = if tilde { &text[1..] } else { &text[..] };
+        let (dir, prefix) = match text.rfind('/') {
+            Some(index) => text.split_at(index + 1),
+            None => ("", text),
+        };
+        let lookup = match (tilde, dir) {
+            (true, _) => format!("{}{}", self.home, dir),
+            (false, "") => ".".to_string(),
+            (false, _) => dir.to_string(),
+        };
+
+        let mut candidates = Vec::new();
+        for entry in fs::read_dir(&lookup).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Hidden files only when asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                continue;
+            }
+            let meta = match fs::metadata(Path::new(&lookup).join(&name)) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            let wanted = match files {
+                Files::All => true,
+                Files::Dirs => meta.is_dir(),
+                Files::Executables => meta.is_dir() || meta.permissions().mode() & 0o111 != 0,
+            };
+            if !wanted {
+                continue;
+            }
+            let mut replacement = if tilde {
+                "~".to_string()
+            } else {
+                String::new()
+            };
+            replacement.push_str(&requote(quote, &format!("{}{}", dir, name)));
+            // Directories stay open to keep completing inside them
+            let display = if meta.is_dir() {
+                replacement.push('/');
+                format!("{}/", name)
+            } else {
+                replacement.extend(quote);
+                replacement.push(' ');
+                name
+            };
+            candidates.push(Pair {
+                display,
+                replacement,
+            });
+        }
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates
+    }
+}
+
+// The files in ~/.config/zash/completions, fish completions are imported and
+// the others are run like with source, to define functions and call complete
+pub fn load_scripts(shell: &mut Shell, homedir: &str) {
+    let dir = match config::path(homedir).parent() {
+        Some(dir) => dir.join("completions"),
+        None => return,
+    };
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    for path in paths {
+        let result = if path
+            .extension()
+            .is_some_and(|extension| extension == "fish")
+        {
+            fs::read_to_string(&path).map(|text| fish::import(&text, &mut shell.completions))
+        } else {
+            scripting::source(shell, path.display().to_string()).map(|_| ())
+        };
+        if let Err(err) = result {
+            utils::zash_error(format!("{}: {}", path.display(), err));
+        }
+    }
+}
+
+// Start of the word the cursor is at the end of, quotes and backslashes
+// keep spaces and operators in the word
+fn word_start(line: &str) -> usize {
+    let mut start = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() || ";&|<>()".contains(c) => start = index + c.len_utf8(),
+            _ => {}
+        }
+    }
+    start
+}
+
+// "$HO" or "${HO", where the name being typed starts
+fn variable_start(word: &str) -> Option<usize> {
+    let dollar = word.rfind('$')?;
+    if word.starts_with('\'') || word[..dollar].ends_with('\\') {
+        return None;
+    }
+    let name_start = if word[dollar + 1..].starts_with('{') {
+        dollar + 2
+    } else {
+        dollar + 1
+    };
+    word[name_start..]
+        .chars()
+        .all(|c| c == '_' || c.is_ascii_alphanumeric())
+        .then_some(name_start)
+}
+
+// Looks at the tokens before the word to tell if it is a command name
+fn position(before: &str) -> Position {
+    let tokens = match lexer::tokenize(before) {
+        Ok((tokens, _)) => tokens,
+        // Like in an unfinished $(...)
+        Err(_) => return Position::Argument(Vec::new()),
+    };
+    let mut words: Vec<String> = Vec::new();
+    let mut redirect = false;
+    for token in tokens {
+        match token.kind {
+            TokenKind::Operator(op) if is_redirect(op) => redirect = true,
+            TokenKind::Operator(_) | TokenKind::Newline => {
+                words.clear();
+                redirect = false;
+            }
+            TokenKind::IoNumber(_) => {}
+            TokenKind::Arithmetic(word) => words.push(format!("(({}))", word)),
+            TokenKind::Word(_) if redirect => redirect = false,
+            TokenKind::Word(word) => {
+                let text = word.to_string();
+                if !words.is_empty() || !(is_assignment(&text) || is_command_keyword(&text)) {
+                    words.push(text);
+                }
+            }
+        }
+    }
+    if redirect {
+        Position::Redirect
+    } else if words.is_empty() {
+        Position::Command
+    } else {
+        Position::Argument(words)
+    }
+}
+
+fn is_redirect(op: Operator) -> bool {
+    use Operator::*;
+    matches!(
+        op,
+        Less | Great
+            | DGreat
+            | LessAnd
+            | GreatAnd
+            | LessGreat
+            | Clobber
+            | AndGreat
+            | AndDGreat
+            | DLess
+            | DLessDash
+            | TLess
+    )
+}
+
+// "FOO=1 cmd"
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=')
+        .is_some_and(|(name, _)| is_valid_variable_name(name))
+}
+
+// Keywords followed by a command
+fn is_command_keyword(word: &str) -> bool {
+    matches!(
+        word,
+        "if" | "then" | "elif" | "else" | "while" | "until" | "do" | "{" | "!"
+    )
+}
+
+// The text of a word without its quotes, and the quote that is still open
+fn unquote(word: &str) -> (Option<char>, String) {
+    let mut text = String::new();
+    let mut quote = None;
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => text.push(c),
+            (Some(_), '\\') if matches!(chars.peek(), Some('"' | '\\' | '$' | '`')) => {
+                text.extend(chars.next())
+            }
+            (None, '\\') => text.extend(chars.next()),
+            (None, '\'') | (None, '"') => quote = Some(c),
+            _ => text.push(c),
+        }
+    }
+    (quote, text)
+}
+
+// Quotes the text again the way the word started
+fn requote(quote: Option<char>, text: &str) -> String {
+    match quote {
+        Some('\'') => format!("'{}", text.replace('\'', "'\\''")),
+        Some(_) => {
+            let mut quoted = String::from("\"");
+            for c in text.chars() {
+                if matches!(c, '"' | '\\' | '$' | '`') {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted
+        }
+        None => escape(text),
+    }
+}
+
+// Backslashes before the characters the shell would treat specially
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() || "'\"\\$`&|;<>()*?[]#!{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replacements(candidates: Vec<Pair>) -> Vec<String> {
+        candidates
+            .into_iter()
+            .map(|pair| pair.replacement)
+            .collect()
+    }
+
+    #[test]
+    fn test_position() {
+        assert_eq!(word_start("echo hel"), 5);
+        assert_eq!(word_start("ls|gr"), 3);
+        assert_eq!(word_start("cat 'my fi"), 4);
+        assert_eq!(word_start("cat my\\ fi"), 4);
+        assert_eq!(word_start("echo \"a;b"), 5);
+
+        assert_eq!(variable_start("$HO"), Some(1));
+        assert_eq!(variable_start("a${HO"), Some(3));
+        assert_eq!(variable_start("\"$"), Some(2));
+        assert_eq!(variable_start("'$HO"), None);
+        assert_eq!(variable_start("$HOME/"), None);
+
+        assert_eq!(position(""), Position::Command);
+        assert_eq!(position("ls | "), Position::Command);
+        assert_eq!(position("FOO=1 "), Position::Command);
+        assert_eq!(position("if true; then "), Position::Command);
+        assert_eq!(
+            position("FOO=1 cd "),
+            Position::Argument(vec!["cd".to_string()])
+        );
+        assert_eq!(position("echo hi > "), Position::Redirect);
+        assert_eq!(
+            position("echo hi 2> err "),
+            Position::Argument(vec!["echo".to_string(), "hi".to_string()])
+        );
+        assert_eq!(position("echo $("), Position::Argument(Vec::new()));
+    }
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(unquote("'my fi"), (Some('\''), "my fi".to_string()));
+        assert_eq!(unquote("my\\ fi"), (None, "my fi".to_string()));
+        assert_eq!(unquote("\"a\\\"b"), (Some('"'), "a\"b".to_string()));
+        assert_eq!(requote(Some('\''), "it's"), "'it'\\''s");
+        assert_eq!(requote(Some('"'), "$x"), "\"\\$x");
+        assert_eq!(escape("my file (1)"), "my\\ file\\ \\(1\\)");
+    }
+
+    #[test]
+    fn test_complete() {
+        let completer = ShellCompleter {
+            functions: vec!["greet".to_string()],
+            aliases: vec!["ll".to_string()],
+            variables: vec!["HOME".to_string(), "HOSTNAME".to_string()],
+            jobs: vec![(1, "sleep 10".to_string()), (2, "vim".to_string())],
+            ..ShellCompleter::default()
+        };
+        let (start, candidates) = completer.complete("ls; gre", 7);
+        assert_eq!(start, 4);
+        assert_eq!(replacements(candidates), vec!["greet "]);
+        assert_eq!(
+            replacements(completer.complete("l", 1).1),
+            vec!["let ", "ll ", "local "]
+        );
+
+        let (start, candidates) = completer.complete("echo ${HOS", 10);
+        assert_eq!(start, 7);
+        assert_eq!(replacements(candidates), vec!["HOSTNAME}"]);
+        assert_eq!(completer.complete("echo $HO", 8).1.len(), 2);
+
+        assert_eq!(
+            replacements(completer.complete("fg %", 4).1),
+            vec!["%1 ", "%2 "]
+        );
+        assert_eq!(
+            replacements(completer.complete("kill %vi", 8).1),
+            vec!["%vim "]
+        );
+    }
+}
@@ -1,17 +1,317 @@
-// This file is currently not used
- 
+// Settings for the line editor and the prompt, from $XDG_CONFIG_HOME/zash/config.toml or
+// ~/.config/zash/config.toml. Everything is optional, a missing file gives
+// the defaults.
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::highlight;
+use crate::utils;
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     // Completion
-    CompletionType: String,
-    EditMode: String,
-    OutputStream: String,
-    Validator: bool,
+    pub completion_type: CompletionType,
+    pub edit_mode: EditMode,
+    pub output_stream: OutputStream,
+    // Unfinished commands are read a line at a time with PS2. With the validator
+    // they continue on a new line of the same buffer instead, so the lines
+    // before can still be edited, but without PS2 on the new lines
+    pub validator: bool,
 
     // History
-    IgnoreSpaces: bool,
-    HistoryPath: String,
+    pub ignore_spaces: bool,
+    // ~/.zash_history if not set, $HISTFILE comes first
+    pub history_path: Option<String>,
+    pub history_duplicates: Duplicates,
+    // Commands run in other terminals show up in this one from the next prompt,
+    // otherwise only new shells see them
+    pub share_history: bool,
 
     // Shell
-    Prompt: String
+    // Used when PS1 is not set, with the same escapes
+    pub prompt: Option<String>,
+    // Commands that take at least this many seconds show how long they took
+    // in the prompt and run zash_notify
+    pub duration_threshold: f64,
+    // Ring the bell when such a command finishes
+    pub bell: bool,
+
+    // Syntax highlighting, in [colors]
+    pub colors: Colors,
+}
+
+// A color name or 0-255 like in %F{...}, "bold" and "bold red" work too.
+// An empty string leaves the text as it is
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    // Commands, by what they turn out to be
+    pub command: String,
+    pub builtin: String,
+    pub function: String,
+    pub alias: String,
+    pub keyword: String,
+    pub not_found: String,
+
+    pub string: String,
+    pub variable: String,
+    // &&, ||, |, ; and redirections
+    pub operator: String,
+    pub comment: String,
+    // Unterminated quotes
+    pub error: String,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            command: "green".to_string(),
+            builtin: "bold green".to_string(),
+            function: "blue".to_string(),
+            alias: "blue".to_string(),
+            keyword: "magenta".to_string(),
+            not_found: "red".to_string(),
+            string: "yellow".to_string(),
+            variable: "cyan".to_string(),
+            operator: "bold".to_string(),
+            comment: "8".to_string(),
+            error: "red".to_string(),
+        }
+    }
+}
+
+impl Colors {
+    fn iter(&self) -> impl Iterator<Item = (&'static str, &String)> {
+        vec![
+            ("command", &self.command),
+            ("builtin", &self.builtin),
+            ("function", &self.function),
+            ("alias", &self.alias),
+            ("keyword", &self.keyword),
+            ("not_found", &self.not_found),
+            ("string", &self.string),
+            ("variable", &self.variable),
+            ("operator", &self.operator),
+            ("comment", &self.comment),
+            ("error", &self.error),
+        ]
+        .into_iter()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            completion_type: CompletionType::List,
+            edit_mode: EditMode::Emacs,
+            output_stream: OutputStream::Stdout,
+            validator: false,
+            ignore_spaces: true,
+            history_path: None,
+            history_duplicates: Duplicates::Ignore,
+            share_history: false,
+            prompt: None,
+            duration_threshold: 5.0,
+            bell: false,
+            colors: Colors::default(),
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletionType {
+    List,
+    Circular,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EditMode {
+    Emacs,
+    Vi,
+}
+
+// What to do when a command is the same as one in the history
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Duplicates {
+    Keep,
+    // Not added again right after itself
+    Ignore,
+    // The older ones are removed
+    Erase,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl From<CompletionType> for rustyline::CompletionType {
+    fn from(completion_type: CompletionType) -> Self {
+        match completion_type {
+            CompletionType::List => Self::List,
+            CompletionType::Circular => Self::Circular,
+        }
+    }
+}
+
+impl From<EditMode> for rustyline::EditMode {
+    fn from(edit_mode: EditMode) -> Self {
+        match edit_mode {
+            EditMode::Emacs => Self::Emacs,
+            EditMode::Vi => Self::Vi,
+        }
+    }
+}
+
+impl From<OutputStream> for rustyline::config::OutputStreamType {
+    fn from(output_stream: OutputStream) -> Self {
+        match output_stream {
+            OutputStream::Stdout => Self::Stdout,
+            OutputStream::Stderr => Self::Stderr,
+        }
+    }
+}
+
+impl Config {
+    // The history file, with ~ expanded
+    pub fn history_path(&self, homedir: &str) -> String {
+        match &self.history_path {
+            Some(path) if path == "~" || path.starts_with("~/") => {
+                format!("{}{}", homedir, &path[1..])
+            }
+            Some(path) => path.clone(),
+            None => format!("{}/.zash_history", homedir),
+        }
+    }
+}
+
+pub fn path(homedir: &str) -> PathBuf {
+    let config_home = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(homedir).join(".config"),
+    };
+    config_home.join("zash").join("config.toml")
+}
+
+// Reads the config file, errors are printed and give the defaults
+pub fn load(homedir: &str) -> Config {
+    let path = path(homedir);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Config::default(),
+        Err(err) => {
+            utils::zash_error(format!("{}: {}", path.display(), err));
+            return Config::default();
+        }
+    };
+    parse(&text).unwrap_or_else(|err| {
+        utils::zash_error(format!("{}:{}", path.display(), err));
+        Config::default()
+    })
+}
+
+// "3: unknown variant `vim`..." with the line the error is on
+fn parse(text: &str) -> Result<Config, String> {
+    // Syntax errors know their line
+    let value: toml::Value = text.parse().map_err(|err: toml::de::Error| {
+        let line = err.line_col().map_or(1, |(line, _)| line + 1);
+        format!("{}: {}", line, without_position(err.to_string()))
+    })?;
+    // But errors about the values all say line 1, so look for the key instead
+    let config: Config = value.try_into().map_err(|err: toml::de::Error| {
+        let message = without_position(err.to_string());
+        let line = error_key(&message)
+            .and_then(|key| key_line(text, key))
+            .unwrap_or(1);
+        format!("{}: {}", line, message)
+    })?;
+    if !(config.duration_threshold >= 0.0 && config.duration_threshold.is_finite()) {
+        let line = key_line(text, "duration_threshold").unwrap_or(1);
+        return Err(format!(
+            "{}: duration_threshold should be a number of seconds",
+            line
+        ));
+    }
+    for (key, color) in config.colors.iter() {
+        if highlight::style(color).is_none() {
+            let line = key_line(text, key).unwrap_or(1);
+            return Err(format!("{}: unknown color `{}` for {}", line, color, key));
+        }
+    }
+    Ok(config)
+}
+
+// toml puts " at line 3 column 13" at the end, the line goes in front instead
+fn without_position(mut message: String) -> String {
+    if let Some(index) = message.rfind(" at line ") {
+        message.truncate(index);
+    }
+    message
+}
+
+// "... for key `edit_mode`" or "unknown field `theme`, ..."
+fn error_key(message: &str) -> Option<&str> {
+    let start = match message.find("for key `") {
+        Some(index) => index + "for key `".len(),
+        None => message.find("unknown field `")? + "unknown field `".len(),
+    };
+    let len = message[start..].find('`')?;
+    Some(&message[start..start + len])
+}
+
+fn key_line(text: &str, key: &str) -> Option<usize> {
+    text.lines()
+        .position(|line| {
+            line.trim_start()
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with('='))
+        })
+        .map(|index| index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("").unwrap(), Config::default());
+        let config = parse(
+            "edit_mode = \"vi\"\nhistory_path = \"~/.hist\"\nhistory_duplicates = \"erase\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.edit_mode, EditMode::Vi);
+        assert_eq!(config.history_duplicates, Duplicates::Erase);
+        assert_eq!(config.completion_type, CompletionType::List);
+        assert_eq!(config.history_path("/home/me"), "/home/me/.hist");
+
+        let err = parse("validator = true\nedit_mode = \"vim\"\n").unwrap_err();
+        assert!(err.starts_with("2: unknown variant `vim`"), "{}", err);
+        let err = parse("\n\nignore_spaces = 1\n").unwrap_err();
+        assert!(err.starts_with("3: invalid type"), "{}", err);
+        let err = parse("prompt = \"$ \"\ntheme = true\n").unwrap_err();
+        assert!(err.starts_with("2: unknown field `theme`"), "{}", err);
+        let err = parse("prompt = \"$ \"\nedit_mode = \n").unwrap_err();
+        assert!(err.starts_with("2: "), "{}", err);
+
+        assert_eq!(
+            parse("duration_threshold = 2").unwrap().duration_threshold,
+            2.0
+        );
+        let err = parse("bell = true\nduration_threshold = -1.5\n").unwrap_err();
+        assert!(err.starts_with("2: duration_threshold"), "{}", err);
+
+        let config = parse("[colors]\nstring = \"bold 208\"\ncomment = \"\"\n").unwrap();
+        assert_eq!(config.colors.string, "bold 208");
+        assert_eq!(config.colors.command, "green");
+        let err = parse("[colors]\n\nstring = \"pink\"\n").unwrap_err();
+        assert_eq!(err, "3: unknown color `pink` for string");
+    }
+}
@@ -0,0 +1,782 @@
+// Runs the syntax tree from the parser. Builtins run in the shell itself when
+// they are alone, everything else is forked and waited for through the job table.
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::raw::c_char;
+use std::os::unix::io::{FromRawFd, RawFd};
+
+use crate::builtins;
+use crate::expand;
+use crate::jobs::{self, JobState};
+use crate::parsers::ast;
+use crate::prompt;
+use crate::redirect;
+use crate::shell::Shell;
+use crate::terminal;
+use crate::utils;
+
+fn make_pipe() -> io::Result<(RawFd, RawFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((fds[0], fds[1]))
+}
+
+fn move_fd(from: RawFd, to: RawFd) {
+    unsafe {
+        libc::dup2(from, to);
+        libc::close(from);
+    }
+}
+
+// Replaces the process with the command, for a forked child or "exec cmd".
+// Returns the status if it can't be run, 127 if it is not found and 126 otherwise.
+pub fn exec_command(command: &str, args: Vec<String>) -> i32 {
+    let c_args: Vec<CString> = match std::iter::once(command.to_string())
+        .chain(args)
+        .map(CString::new)
+        .collect()
+    {
+        Ok(m) => m,
+        Err(_) => {
+            utils::zash_error(format!("{}: argument contains a nul byte", command));
+            return 126;
+        }
+    };
+    let mut argv: Vec<*const c_char> = c_args.iter().map(|arg| arg.as_ptr()).collect();
+    argv.push(std::ptr::null());
+    io::stdout().flush().ok();
+    unsafe { libc::execvp(argv[0], argv.as_ptr()) };
+
+    let errno = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+    if errno == libc::ENOENT && !command.contains('/') {
+        utils::zash_error(format!("command not found: {}", command));
+        return 127;
+    }
+    utils::zash_error(format!("{}: {}", command, utils::error_string(errno)));
+    if errno == libc::ENOENT {
+        127
+    } else {
+        126
+    }
+}
+
+// Set by break, continue and return, the commands after them are skipped until
+// the loop or function they are for. A foreground job killed with Ctrl-C stops everything.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+    Break(usize),
+    Continue(usize),
+    Return,
+    Interrupted,
+}
+
+// Deeper recursion would overflow the stack
+const MAX_FUNCTION_DEPTH: usize = 1000;
+
+impl Shell {
+    // Returns the status of the last command, 0 if the list is empty
+    pub fn exec_list(&mut self, list: &ast::List) -> i32 {
+        let mut status = 0;
+        for item in &list.items {
+            if self.flow.is_some() {
+                break;
+            }
+            status = if item.background {
+                self.exec_background(&item.and_or)
+            } else {
+                self.exec_and_or(&item.and_or)
+            };
+            self.status = status;
+        }
+        status
+    }
+
+    fn exec_and_or(&mut self, and_or: &ast::AndOr) -> i32 {
+        let mut status = self.exec_pipeline(&and_or.first);
+        for (connector, pipeline) in &and_or.rest {
+            if self.flow.is_some() {
+                break;
+            }
+            // "&& "Don't run the other commands if the one before failed
+            //
+            // "||" = "Or"
+            // "ls || dir"
+            // If ls does not succed it runs "dir"
+            // If ls succed it does not run dir
+            match connector {
+                ast::Connector::And if status != 0 => continue,
+                ast::Connector::Or if status == 0 => continue,
+                _ => {}
+            }
+            self.status = status;
+            status = self.exec_pipeline(pipeline);
+        }
+        status
+    }
+
+    // "sleep 10 &", starts the job and returns right away
+    fn exec_background(&mut self, and_or: &ast::AndOr) -> i32 {
+        let spawned = if and_or.rest.is_empty() {
+            self.spawn_pipeline(&and_or.first, true)
+        } else {
+            // "a && b &" runs the whole list in a subshell
+            self.fork_process(0, true).map(|pid| {
+                if pid == 0 {
+                    let status = self.exec_and_or(and_or);
+                    self.exit_child(status);
+                }
+                (pid, vec![pid])
+            })
+        };
+        let (pgid, pids) = match spawned {
+            Ok(m) => m,
+            Err(err) => {
+                utils::zash_error(err);
+                return 1;
+            }
+        };
+        self.last_background_pid = pids.last().copied();
+        let id = self.jobs.add(pgid, pids, and_or.to_string(), true);
+        if self.interactive {
+            eprintln!("[{}] {}", id, self.last_background_pid.unwrap_or(0));
+        }
+        0
+    }
+
+    fn exec_pipeline(&mut self, pipeline: &ast::Pipeline) -> i32 {
+        let status = if let [command] = pipeline.commands.as_slice() {
+            self.exec_command(command, false)
+        } else {
+            match self.spawn_pipeline(pipeline, false) {
+                Ok((pgid, pids)) => {
+                    let id = self.jobs.add(pgid, pids, pipeline.to_string(), false);
+                    self.wait_job(id)
+                }
+                Err(err) => {
+                    utils::zash_error(err);
+                    1
+                }
+            }
+        };
+        if pipeline.negated {
+            return (status == 0) as i32;
+        }
+        status
+    }
+
+    // Forks every command of the pipeline with pipes between them.
+    // Returns the process group and the pids.
+    fn spawn_pipeline(
+        &mut self,
+        pipeline: &ast::Pipeline,
+        background: bool,
+    ) -> Result<(i32, Vec<i32>), String> {
+        let mut pids = Vec::new();
+        let mut pgid = 0;
+        let mut prev_read: Option<RawFd> = None;
+        // Background jobs without job control don't read from the terminal
+        let null_stdin = background && !self.job_control;
+        for (i, command) in pipeline.commands.iter().enumerate() {
+            let pipe = if i + 1 < pipeline.commands.len() {
+                Some(make_pipe().map_err(|err| format!("pipe: {}", err))?)
+            } else {
+                None
+            };
+            let pid = match self.fork_process(pgid, background) {
+                Ok(pid) => pid,
+                Err(err) => {
+                    if let Some((read, write)) = pipe {
+                        unsafe {
+                            libc::close(read);
+                            libc::close(write);
+                        }
+                    }
+                    if let Some(read) = prev_read {
+                        unsafe { libc::close(read) };
+                    }
+                    return Err(err);
+                }
+            };
+            if pid == 0 {
+                match prev_read {
+                    Some(read) => move_fd(read, 0),
+                    None if null_stdin => {
+                        if let Ok(null) = std::fs::File::open("/dev/null") {
+                            move_fd(std::os::unix::io::IntoRawFd::into_raw_fd(null), 0);
+                        }
+                    }
+                    None => {}
+                }
+                if let Some((read, write)) = pipe {
+                    unsafe { libc::close(read) };
+                    move_fd(write, 1);
+                }
+                let status = self.exec_command(command, true);
+                self.exit_child(status);
+            }
+            if pgid == 0 {
+                pgid = pid;
+            }
+            pids.push(pid);
+            if let Some(read) = prev_read {
+                unsafe { libc::close(read) };
+            }
+            prev_read = pipe.map(|(read, write)| {
+                unsafe { libc::close(write) };
+                read
+            });
+        }
+        Ok((pgid, pids))
+    }
+
+    fn exec_command(&mut self, command: &ast::Command, in_child: bool) -> i32 {
+        match command {
+            ast::Command::Simple(simple) => self.exec_simple(simple, in_child),
+            ast::Command::Arithmetic(expr) => self.exec_arithmetic(expr),
+            ast::Command::Compound(compound, redirects) => {
+                let redirections = match redirect::prepare(self, redirects) {
+                    Ok(m) => m,
+                    Err(err) => {
+                        utils::zash_error(err);
+                        return 1;
+                    }
+                };
+                match redirections.apply() {
+                    Ok(_saved) => self.exec_compound(compound, in_child),
+                    Err(err) => {
+                        utils::zash_error(err);
+                        1
+                    }
+                }
+            }
+            ast::Command::Function { name, body } => {
+                self.functions.insert(name.clone(), body.clone());
+                0
+            }
+        }
+    }
+
+    fn exec_compound(&mut self, compound: &ast::CompoundCommand, in_child: bool) -> i32 {
+        match compound {
+            ast::CompoundCommand::If {
+                branches,
+                otherwise,
+            } => {
+                for (condition, body) in branches {
+                    let status = self.exec_list(condition);
+                    if self.flow.is_some() {
+                        return status;
+                    }
+                    if status == 0 {
+                        return self.exec_list(body);
+                    }
+                }
+                match otherwise {
+                    Some(otherwise) => self.exec_list(otherwise),
+                    None => 0,
+                }
+            }
+            ast::CompoundCommand::While {
+                until,
+                condition,
+                body,
+            } => {
+                let mut status = 0;
+                self.loop_depth += 1;
+                loop {
+                    let result = self.exec_list(condition);
+                    if self.end_of_loop() || (result == 0) == *until {
+                        break;
+                    }
+                    status = self.exec_list(body);
+                    if self.end_of_loop() {
+                        break;
+                    }
+                }
+                self.loop_depth -= 1;
+                status
+            }
+            ast::CompoundCommand::For { name, words, body } => {
+                let values = match words {
+                    Some(words) => match expand::expand_words(self, words) {
+                        Ok(m) => m,
+                        Err(err) => return self.expansion_error(err),
+                    },
+                    None => self.positional.clone(),
+                };
+                let mut status = 0;
+                self.loop_depth += 1;
+                for value in values {
+                    if let Err(err) = self.variables.set(name, &value) {
+                        utils::zash_error(err);
+                        status = 1;
+                        break;
+                    }
+                    status = self.exec_list(body);
+                    if self.end_of_loop() {
+                        break;
+                    }
+                }
+                self.loop_depth -= 1;
+                status
+            }
+            ast::CompoundCommand::Case { word, items } => {
+                let text = match expand::expand_word_to_string(self, word) {
+                    Ok(m) => m,
+                    Err(err) => return self.expansion_error(err),
+                };
+                for item in items {
+                    for pattern in &item.patterns {
+                        match expand::pattern_matches(self, pattern, &text) {
+                            Ok(true) => return self.exec_list(&item.body),
+                            Ok(false) => {}
+                            Err(err) => return self.expansion_error(err),
+                        }
+                    }
+                }
+                0
+            }
+            ast::CompoundCommand::BraceGroup(list) => self.exec_list(list),
+            ast::CompoundCommand::Subshell(list) if in_child => self.exec_list(list),
+            ast::CompoundCommand::Subshell(list) => match self.fork_process(0, false) {
+                Ok(0) => {
+                    let status = self.exec_list(list);
+                    self.exit_child(status);
+                }
+                Ok(pid) => {
+                    let id = self.jobs.add(pid, vec![pid], compound.to_string(), false);
+                    self.wait_job(id)
+                }
+                Err(err) => {
+                    utils::zash_error(err);
+                    1
+                }
+            },
+        }
+    }
+
+    // Runs a function with its own positional parameters and local variables
+    fn call_function(&mut self, body: &ast::Command, args: Vec<String>) -> i32 {
+        if self.function_depth >= MAX_FUNCTION_DEPTH {
+            utils::zash_error("maximum function nesting level exceeded");
+            return 1;
+        }
+        let positional = std::mem::replace(&mut self.positional, args);
+        // break in a function does not reach the loops of the caller
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        self.variables.push_scope();
+        self.function_depth += 1;
+        let status = self.exec_command(body, false);
+        if self.flow == Some(Flow::Return) {
+            self.flow = None;
+        }
+        self.function_depth -= 1;
+        self.variables.pop_scope();
+        self.loop_depth = loop_depth;
+        self.positional = positional;
+        status
+    }
+
+    // Called after a part of a loop has run, returns true if the loop is over
+    fn end_of_loop(&mut self) -> bool {
+        match self.flow {
+            Some(Flow::Break(n)) => {
+                self.flow = if n > 1 {
+                    Some(Flow::Break(n - 1))
+                } else {
+                    None
+                };
+                true
+            }
+            // "continue 2" ends this loop and continues the one around it
+            Some(Flow::Continue(n)) if n > 1 => {
+                self.flow = Some(Flow::Continue(n - 1));
+                true
+            }
+            Some(Flow::Continue(_)) => {
+                self.flow = None;
+                false
+            }
+            Some(Flow::Return) | Some(Flow::Interrupted) => true,
+            None => false,
+        }
+    }
+
+    // (( expr )) is true when the value is not 0
+    fn exec_arithmetic(&mut self, expr: &ast::Word) -> i32 {
+        match expand::arithmetic(self, expr) {
+            Ok(value) => (value == 0) as i32,
+            Err(err) => {
+                utils::zash_error(err);
+                1
+            }
+        }
+    }
+
+    // When in_child is true the shell is already a forked process for this
+    // command, so external commands are exec'ed without forking again
+    fn exec_simple(&mut self, simple: &ast::SimpleCommand, in_child: bool) -> i32 {
+        self.substitution_status = None;
+        let mut assignments = Vec::new();
+        for assignment in &simple.assignments {
+            match expand::expand_word_to_string(self, &assignment.value) {
+                Ok(value) => assignments.push((assignment.name.clone(), value)),
+                Err(err) => return self.expansion_error(err),
+            }
+        }
+        let mut args = match expand::expand_words(self, &simple.words) {
+            Ok(m) => m,
+            Err(err) => return self.expansion_error(err),
+        };
+        if self.xtrace {
+            self.trace(&assignments, &args);
+        }
+        let redirections = match redirect::prepare(self, &simple.redirects) {
+            Ok(m) => m,
+            Err(err) => {
+                utils::zash_error(err);
+                return 1;
+            }
+        };
+        if args.is_empty() {
+            // "FOO=bar" on its own sets a shell variable
+            for (name, value) in &assignments {
+                if let Err(err) = self.variables.set(name, value) {
+                    utils::zash_error(err);
+                    return 1;
+                }
+            }
+            // "FOO=$(false)" fails
+            return self.substitution_status.unwrap_or(0);
+        }
+        let mut command = args.remove(0);
+        // "%1" is the same as "fg %1"
+        if command.starts_with('%') {
+            args.insert(0, command);
+            command = "fg".to_string();
+        }
+        // Functions come before builtins, so "cd() { builtin cd "$@"; }" works
+        let function = self.functions.get(&command).cloned();
+        if function.is_some() || builtins::is_builtin(&command) {
+            let temporary = !assignments.is_empty();
+            if temporary {
+                if let Err(err) = self.assign_temporary(&assignments) {
+                    utils::zash_error(err);
+                    self.variables.pop_scope();
+                    return 1;
+                }
+            }
+            // "exec 3>file" keeps fd 3 open, there is nothing to restore
+            let status = if command == "exec" && function.is_none() {
+                builtins::exec::exec(self, args, &redirections)
+            } else {
+                match redirections.apply() {
+                    Ok(_saved) => match function {
+                        Some(body) => self.call_function(&body, args),
+                        None => self.exec_builtin(&command, args),
+                    },
+                    Err(err) => {
+                        utils::zash_error(err);
+                        1
+                    }
+                }
+            };
+            if temporary {
+                self.variables.pop_scope();
+            }
+            return status;
+        }
+        if in_child {
+            self.exec_external(&command, args, &assignments, &redirections);
+        }
+        match self.fork_process(0, false) {
+            Ok(0) => self.exec_external(&command, args, &assignments, &redirections),
+            Ok(pid) => {
+                let id = self.jobs.add(pid, vec![pid], simple.to_string(), false);
+                self.wait_job(id)
+            }
+            Err(err) => {
+                utils::zash_error(err);
+                1
+            }
+        }
+    }
+
+    // "+ FOO=bar echo 'a b'" for set -x, after the expansions
+    fn trace(&self, assignments: &[(String, String)], args: &[String]) {
+        let words: Vec<String> = assignments
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, utils::quote_if_needed(value)))
+            .chain(args.iter().map(|arg| utils::quote_if_needed(arg)))
+            .collect();
+        eprintln!("{}{}", prompt::ps4(self), words.join(" "));
+    }
+
+    // Scripts stop at expansion errors like ${name:?}, the prompt only skips the command
+    fn expansion_error(&mut self, err: String) -> i32 {
+        utils::zash_error(err);
+        if !self.interactive {
+            utils::exit(1);
+        }
+        1
+    }
+
+    pub fn exec_builtin(&mut self, command: &str, args: Vec<String>) -> i32 {
+        match command {
+            "cd" => builtins::cd::cd(args),
+            "exit" => builtins::exit::exit(args),
+            "jobs" => builtins::jobs::jobs(self, args),
+            "fg" => builtins::fg::fg(self, args),
+            "bg" => builtins::bg::bg(self, args),
+            "disown" => builtins::disown::disown(self, args),
+            "wait" => builtins::wait::wait(self, args),
+            "export" => builtins::export::export(self, args),
+            "unset" => builtins::unset::unset(self, args),
+            "readonly" => builtins::readonly::readonly(self, args),
+            "declare" => builtins::declare::declare(self, args),
+            "local" => builtins::local::local(self, args),
+            "let" => builtins::r#let::r#let(self, args),
+            "break" => builtins::r#break::r#break(self, args),
+            "continue" => builtins::r#continue::r#continue(self, args),
+            "shift" => builtins::shift::shift(self, args),
+            "return" => builtins::r#return::r#return(self, args),
+            "builtin" => builtins::builtin::builtin(self, args),
+            "alias" => builtins::alias::alias(self, args),
+            "unalias" => builtins::unalias::unalias(self, args),
+            "source" | "." => builtins::source::source(self, command, args),
+            "set" => builtins::set::set(self, args),
+            "complete" => builtins::complete::complete(self, args),
+            // "builtin exec 3>file", the redirections were already applied
+            "exec" => builtins::exec::exec(self, args, &redirect::Redirections::default()),
+            _ => unreachable!("{} is not a builtin", command),
+        }
+    }
+
+    // "FOO=bar cmd", the variables are exported for the command only.
+    // They go in a new scope which the caller has to pop afterwards.
+    fn assign_temporary(&mut self, assignments: &[(String, String)]) -> Result<(), String> {
+        self.variables.push_scope();
+        for (name, value) in assignments {
+            self.variables.set_local(name, Some(value))?;
+            self.variables.set_attributes(name, Some(true), false, None);
+        }
+        Ok(())
+    }
+
+    // Replaces the forked process with the command
+    fn exec_external(
+        &mut self,
+        command: &str,
+        args: Vec<String>,
+        assignments: &[(String, String)],
+        redirections: &redirect::Redirections,
+    ) -> ! {
+        if let Err(err) = self.assign_temporary(assignments) {
+            utils::zash_error(err);
+            self.exit_child(1);
+        }
+        if let Err(err) = redirect::apply_actions(&redirections.actions) {
+            utils::zash_error(format!("{}: {}", command, err));
+            self.exit_child(1);
+        }
+        let status = exec_command(command, args);
+        self.exit_child(status);
+    }
+
+    // Returns 0 in the child and the pid in the shell.
+    // With job control the process is put in the process group pgid, or a new one if
+    // pgid is 0, and foreground groups get the terminal.
+    fn fork_process(&mut self, pgid: i32, background: bool) -> Result<i32, String> {
+        io::stdout().flush().ok();
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            return Err(format!("fork: {}", io::Error::last_os_error()));
+        }
+        let child = pid == 0;
+        if self.job_control {
+            // Done in both processes so it is set before either of them continues
+            let pid = if child {
+                unsafe { libc::getpid() }
+            } else {
+                pid
+            };
+            let pgid = if pgid == 0 { pid } else { pgid };
+            unsafe { libc::setpgid(pid, pgid) };
+            // SIGTTOU is still ignored in the child, so it can take the terminal
+            // before it reads from it
+            if child && !background {
+                terminal::give_to(pgid);
+            }
+        }
+        if child {
+            self.become_subshell(background);
+        }
+        Ok(pid)
+    }
+
+    // Called in a forked child
+    fn become_subshell(&mut self, background: bool) {
+        if self.job_control {
+            terminal::reset_signals();
+        } else if background {
+            // Ctrl-C at the prompt should not kill background jobs
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_IGN);
+                libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+            }
+        }
+        // Subshells don't do job control of their own
+        self.interactive = false;
+        self.job_control = false;
+        self.jobs = Default::default();
+    }
+
+    // Runs the list in a subshell and returns what it printed, for $(...).
+    // The subshell stays in the process group of the shell.
+    pub fn capture(&mut self, list: &ast::List) -> Result<String, String> {
+        let (read, write) = make_pipe().map_err(|err| format!("pipe: {}", err))?;
+        io::stdout().flush().ok();
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            unsafe {
+                libc::close(read);
+                libc::close(write);
+            }
+            return Err(format!("fork: {}", io::Error::last_os_error()));
+        }
+        if pid == 0 {
+            unsafe { libc::close(read) };
+            move_fd(write, 1);
+            self.become_subshell(false);
+            self.exec_list(list);
+            let status = self.status;
+            self.exit_child(status);
+        }
+        unsafe { libc::close(write) };
+        let mut output = Vec::new();
+        unsafe { File::from_raw_fd(read) }
+            .read_to_end(&mut output)
+            .ok();
+        let status = loop {
+            let mut status = 0;
+            if unsafe { libc::waitpid(pid, &mut status, 0) } >= 0 {
+                break jobs::decode_status(status).0;
+            }
+            if io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                break 127;
+            }
+        };
+        // $? is the status of the substitution from here on
+        self.status = status;
+        self.substitution_status = Some(status);
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    pub fn exit_child(&mut self, status: i32) -> ! {
+        io::stdout().flush().ok();
+        unsafe { libc::_exit(status) }
+    }
+
+    // Waits until every process of the job has finished or it is stopped,
+    // returns the status of the last one. Foreground jobs get the terminal meanwhile.
+    pub fn wait_job(&mut self, id: usize) -> i32 {
+        let (pgid, foreground) = match self.jobs.get(id) {
+            Some(job) => (job.pgid, !job.background),
+            None => return 127,
+        };
+        let job_control = foreground && self.job_control;
+        if job_control {
+            terminal::give_to(pgid);
+        }
+        let status = loop {
+            let state = match self.jobs.get(id) {
+                Some(job) => job.state(),
+                None => break 127,
+            };
+            match state {
+                JobState::Running => {}
+                JobState::Stopped(signal) => {
+                    if foreground {
+                        self.stopped(id);
+                    }
+                    break 128 + signal;
+                }
+                JobState::Done(status) => {
+                    let signal = self.jobs.remove(id).and_then(|job| job.signal);
+                    match signal {
+                        // The prompt should start on a new line after Ctrl-C
+                        Some(libc::SIGINT) if job_control => {
+                            eprintln!();
+                            self.flow = Some(Flow::Interrupted);
+                        }
+                        Some(libc::SIGINT) | Some(libc::SIGPIPE) | None => {}
+                        // "Segmentation fault" like other shells
+                        Some(signal) if foreground => {
+                            eprintln!("{}", utils::signal_description(signal))
+                        }
+                        Some(_) => {}
+                    }
+                    break status;
+                }
+            }
+            let mut status = 0;
+            let pid = unsafe { libc::waitpid(-1, &mut status, libc::WUNTRACED) };
+            if pid < 0 {
+                if io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) {
+                    continue;
+                }
+                // No children left, this should not happen
+                self.jobs.remove(id);
+                break 127;
+            }
+            self.jobs.update(pid, status);
+        };
+        if job_control {
+            terminal::give_to(self.pgid);
+            // Keep what "stty" changed, but not what a killed or stopped program left behind
+            if status < 128 {
+                self.terminal_modes = terminal::get_modes();
+            } else if let Some(modes) = &self.terminal_modes {
+                terminal::set_modes(modes).ok();
+            }
+        }
+        status
+    }
+
+    // A foreground job was stopped with Ctrl-Z, it is now a background job
+    fn stopped(&mut self, id: usize) {
+        let modes = if self.job_control {
+            terminal::get_modes()
+        } else {
+            None
+        };
+        self.jobs.touch(id);
+        if let Some(job) = self.jobs.get_mut(id) {
+            job.background = true;
+            job.modes = modes;
+            job.notified = true;
+        }
+        if let Some(job) = self.jobs.get(id) {
+            eprintln!();
+            eprintln!("{}", self.jobs.format(job, false));
+        }
+    }
+
+    // Sends SIGCONT to a stopped job, for "fg" and "bg"
+    pub fn continue_job(&mut self, id: usize) {
+        if let Some(job) = self.jobs.get(id) {
+            if self.job_control {
+                unsafe { libc::kill(-job.pgid, libc::SIGCONT) };
+            } else {
+                for process in &job.processes {
+                    unsafe { libc::kill(process.pid, libc::SIGCONT) };
+                }
+            }
+        }
+        self.jobs.continued(id);
+    }
+}
+
@@ -0,0 +1,685 @@
+// Word expansion, turns the words from the parser into the arguments for a command
+use glob::{glob, MatchOptions, Pattern};
+
+use crate::arithmetic;
+use crate::parsers::ast::{Parameter, ParameterOp, ReplaceMode, Word, WordPart};
+use crate::parsers::lexer::is_valid_variable_name;
+use crate::shell::Shell;
+use crate::utils;
+
+pub type Result<T> = std::result::Result<T, String>;
+
+// None if the parameter is not set
+fn variable(shell: &Shell, name: &str) -> Option<String> {
+    match name {
+        "?" => Some(shell.status.to_string()),
+        "$" => Some(std::process::id().to_string()),
+        "!" => shell.last_background_pid.map(|pid| pid.to_string()),
+        "#" => Some(shell.positional.len().to_string()),
+        "0" => Some(shell.arg0.clone()),
+        // "$*" is joined with the first char of IFS
+        "*" => {
+            let separator = match shell.variables.get("IFS") {
+                Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+                None => " ".to_string(),
+            };
+            Some(shell.positional.join(&separator))
+        }
+        "@" => Some(shell.positional.join(" ")),
+        name if name.starts_with(|c: char| c.is_ascii_digit()) => {
+            let index = name.parse::<usize>().ok()?;
+            // ${00} is not $0
+            index
+                .checked_sub(1)
+                .and_then(|index| shell.positional.get(index))
+                .cloned()
+        }
+        name => shell.variables.get(name).map(str::to_string),
+    }
+}
+
+fn match_options() -> MatchOptions {
+    MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: false,
+        require_literal_leading_dot: false,
+    }
+}
+
+// A pattern from ${name#pattern} etc, quoted parts match literally
+fn expand_pattern(shell: &mut Shell, word: &Word) -> Result<Pattern> {
+    let expanded = expand(shell, word)?;
+    Ok(Pattern::new(&expanded.pattern)
+        .unwrap_or_else(|_| Pattern::new(&Pattern::escape(&expanded.text)).unwrap()))
+}
+
+fn matches(pattern: &Pattern, text: &str) -> bool {
+    pattern.matches_with(text, match_options())
+}
+
+// For case, quoted parts of the pattern match literally
+pub fn pattern_matches(shell: &mut Shell, pattern: &Word, text: &str) -> Result<bool> {
+    Ok(matches(&expand_pattern(shell, pattern)?, text))
+}
+
+// Byte offsets of every char boundary, including the end
+fn boundaries(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect()
+}
+
+fn remove_prefix(value: &str, pattern: &Pattern, longest: bool) -> String {
+    let mut ends = boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    match ends
+        .into_iter()
+        .find(|end| matches(pattern, &value[..*end]))
+    {
+        Some(end) => value[end..].to_string(),
+        None => value.to_string(),
+    }
+}
+
+fn remove_suffix(value: &str, pattern: &Pattern, longest: bool) -> String {
+    let mut starts = boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+    match starts
+        .into_iter()
+        .find(|start| matches(pattern, &value[*start..]))
+    {
+        Some(start) => value[..start].to_string(),
+        None => value.to_string(),
+    }
+}
+
+// The longest match of the pattern starting at start, as an end offset
+fn longest_match(value: &str, pattern: &Pattern, start: usize, bounds: &[usize]) -> Option<usize> {
+    bounds
+        .iter()
+        .rev()
+        .filter(|end| **end >= start)
+        .find(|end| matches(pattern, &value[start..**end]))
+        .copied()
+}
+
+fn replace(value: &str, pattern: &Pattern, replacement: &str, mode: ReplaceMode) -> String {
+    let bounds = boundaries(value);
+    match mode {
+        ReplaceMode::Prefix => match longest_match(value, pattern, 0, &bounds) {
+            Some(end) => format!("{}{}", replacement, &value[end..]),
+            None => value.to_string(),
+        },
+        ReplaceMode::Suffix => match bounds
+            .iter()
+            .find(|start| matches(pattern, &value[**start..]))
+        {
+            Some(start) => format!("{}{}", &value[..*start], replacement),
+            None => value.to_string(),
+        },
+        ReplaceMode::First | ReplaceMode::All => {
+            let mut result = String::new();
+            let mut i = 0;
+            let mut replaced = false;
+            while i < bounds.len() {
+                let start = bounds[i];
+                let found = if replaced && mode == ReplaceMode::First {
+                    None
+                } else {
+                    // An empty match would replace between every char
+                    longest_match(value, pattern, start, &bounds).filter(|end| *end > start)
+                };
+                match found {
+                    Some(end) => {
+                        result.push_str(replacement);
+                        replaced = true;
+                        i = bounds.iter().position(|b| *b == end).unwrap();
+                    }
+                    None => {
+                        if let Some(next) = bounds.get(i + 1) {
+                            result.push_str(&value[start..*next]);
+                        }
+                        i += 1;
+                    }
+                }
+            }
+            result
+        }
+    }
+}
+
+// Converts the offset and length of ${name:offset:length} to a range of chars
+fn substring(value: &str, offset: i64, length: Option<i64>) -> Result<String> {
+    let count = value.chars().count() as i64;
+    let mut start = offset;
+    if start < 0 {
+        start += count;
+    }
+    if start < 0 || start > count {
+        return Ok(String::new());
+    }
+    let end = match length {
+        // A negative length counts from the end
+        Some(length) if length < 0 => count + length,
+        Some(length) => (start + length).min(count),
+        None => count,
+    };
+    if end < start {
+        return Err(format!(
+            "{}: substring expression < 0",
+            length.unwrap_or_default()
+        ));
+    }
+    Ok(value
+        .chars()
+        .skip(start as usize)
+        .take((end - start) as usize)
+        .collect())
+}
+
+fn change_case(value: &str, pattern: Option<&Pattern>, upper: bool, all: bool) -> String {
+    let mut result = String::new();
+    let mut changed = false;
+    for c in value.chars() {
+        let matching = pattern.is_none_or(|pattern| matches(pattern, &c.to_string()));
+        if matching && (all || !changed) {
+            if upper {
+                result.extend(c.to_uppercase());
+            } else {
+                result.extend(c.to_lowercase());
+            }
+        } else {
+            result.push(c);
+        }
+        // ${name^} only looks at the first char
+        changed = true;
+    }
+    result
+}
+
+// ${name:-default} and friends
+fn parameter(shell: &mut Shell, parameter: &Parameter) -> Result<String> {
+    let name = &parameter.name;
+    let value = variable(shell, name);
+    // The value if it counts as set, for the operators with and without ":"
+    let set = |colon: bool| value.clone().filter(|value| !colon || !value.is_empty());
+    let text = match &parameter.op {
+        ParameterOp::Length => value.unwrap_or_default().chars().count().to_string(),
+        ParameterOp::Default { colon, word } => match set(*colon) {
+            Some(value) => value,
+            None => expand_word_to_string(shell, word)?,
+        },
+        ParameterOp::Assign { colon, word } => match set(*colon) {
+            Some(value) => value,
+            None => {
+                if !is_valid_variable_name(name) {
+                    return Err(format!("${}: cannot assign in this way", name));
+                }
+                let value = expand_word_to_string(shell, word)?;
+                shell.variables.set(name, &value)?;
+                value
+            }
+        },
+        ParameterOp::Error { colon, word } => match set(*colon) {
+            Some(value) => value,
+            None => {
+                let message = expand_word_to_string(shell, word)?;
+                if message.is_empty() {
+                    return Err(format!("{}: parameter null or not set", name));
+                }
+                return Err(format!("{}: {}", name, message));
+            }
+        },
+        ParameterOp::Alternative { colon, word } => match set(*colon) {
+            Some(_) => expand_word_to_string(shell, word)?,
+            None => String::new(),
+        },
+        ParameterOp::RemovePrefix { longest, pattern } => {
+            let pattern = expand_pattern(shell, pattern)?;
+            remove_prefix(&value.unwrap_or_default(), &pattern, *longest)
+        }
+        ParameterOp::RemoveSuffix { longest, pattern } => {
+            let pattern = expand_pattern(shell, pattern)?;
+            remove_suffix(&value.unwrap_or_default(), &pattern, *longest)
+        }
+        ParameterOp::Replace {
+            mode,
+            pattern,
+            replacement,
+        } => {
+            let pattern = expand_pattern(shell, pattern)?;
+            let replacement = expand_word_to_string(shell, replacement)?;
+            replace(&value.unwrap_or_default(), &pattern, &replacement, *mode)
+        }
+        ParameterOp::Substring { offset, length } => {
+            let offset = arithmetic(shell, offset)?;
+            let length = match length {
+                Some(length) => Some(arithmetic(shell, length)?),
+                None => None,
+            };
+            substring(&value.unwrap_or_default(), offset, length)?
+        }
+        ParameterOp::Case {
+            upper,
+            all,
+            pattern,
+        } => {
+            let pattern = if pattern.parts.is_empty() {
+                None
+            } else {
+                Some(expand_pattern(shell, pattern)?)
+            };
+            change_case(&value.unwrap_or_default(), pattern.as_ref(), *upper, *all)
+        }
+        ParameterOp::Bad(_) => return Err(format!("{}: bad substitution", parameter)),
+    };
+    Ok(text)
+}
+
+// Expanded text of a word plus a glob pattern where the quoted parts are escaped
+#[derive(Default)]
+struct Expanded {
+    text: String,
+    pattern: String,
+    has_glob: bool,
+    // Kept even if it is empty, it had quotes or ended at an IFS char that is
+    // not whitespace, like the middle field of "a::b" with IFS=:
+    keep: bool,
+}
+
+impl Expanded {
+    fn push_quoted(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.pattern.push_str(&Pattern::escape(text));
+    }
+}
+
+// A word can expand to several fields when the result of an expansion is split
+struct Fields {
+    fields: Vec<Expanded>,
+    // The chars of IFS, None when the word is not split
+    ifs: Option<String>,
+    // The last field was ended by a separator, the next one starts when
+    // something goes in it so trailing separators add no field
+    ended: bool,
+}
+
+impl Fields {
+    fn new(ifs: Option<String>) -> Self {
+        Self {
+            fields: vec![Expanded::default()],
+            ifs,
+            ended: false,
+        }
+    }
+
+    fn current(&mut self) -> &mut Expanded {
+        if self.ended {
+            self.ended = false;
+            self.fields.push(Expanded::default());
+        }
+        self.fields.last_mut().unwrap()
+    }
+
+    // Ends the current field if there is one, leading separators add no field
+    fn end_field(&mut self) {
+        let current = self.fields.last().unwrap();
+        if !current.text.is_empty() || current.keep {
+            self.ended = true;
+        }
+    }
+
+    // Adds the result of an unquoted expansion, a new field starts at each IFS char.
+    // Runs of IFS whitespace count as one separator.
+    fn push_split(&mut self, text: &str) {
+        let ifs = match &self.ifs {
+            Some(ifs) if !ifs.is_empty() => ifs.clone(),
+            _ => return self.current().push_quoted(text),
+        };
+        let is_space = |c: char| ifs.contains(c) && c.is_whitespace();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !ifs.contains(c) {
+                self.current().push_quoted(&c.to_string());
+                continue;
+            }
+            // A separator is whitespace, or one other IFS char with whitespace around it
+            let mut other = !is_space(c);
+            while let Some(next) = chars.peek().copied() {
+                if is_space(next) || (!other && ifs.contains(next)) {
+                    other |= !is_space(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            // ":a" and "a::b" have empty fields
+            if other {
+                self.current().keep = true;
+            }
+            self.end_field();
+        }
+    }
+}
+
+fn expand_parts(
+    shell: &mut Shell,
+    parts: &[WordPart],
+    fields: &mut Fields,
+    quoted: bool,
+) -> Result<()> {
+    for (i, part) in parts.iter().enumerate() {
+        let text = match part {
+            WordPart::Literal(text) => {
+                let mut text = text.as_str();
+                let expanded = fields.current();
+                // Replace ~ with home dir
+                if i == 0 && (text == "~" || text.starts_with("~/")) {
+                    expanded.push_quoted(&utils::get_home_dir());
+                    text = &text[1..];
+                }
+                if text.contains(['*', '?', '[']) {
+                    expanded.has_glob = true;
+                }
+                expanded.text.push_str(text);
+                expanded.pattern.push_str(text);
+                continue;
+            }
+            WordPart::Quoted(text) => {
+                fields.current().keep = true;
+                fields.current().push_quoted(text);
+                continue;
+            }
+            WordPart::DoubleQuoted(parts) => {
+                // "$@" without parameters is no field at all, not an empty one
+                let all_params =
+                    matches!(parts.as_slice(), [WordPart::Variable(name)] if name == "@");
+                if !all_params || !shell.positional.is_empty() {
+                    fields.current().keep = true;
+                }
+                expand_parts(shell, parts, fields, true)?;
+                continue;
+            }
+            // Every parameter is a field of its own, unless the word is not split
+            WordPart::Variable(name)
+                if (name == "@" || (name == "*" && !quoted)) && fields.ifs.is_some() =>
+            {
+                for (i, param) in shell.positional.iter().enumerate() {
+                    // Empty parameters are no field unless they are quoted
+                    if i > 0 && quoted {
+                        fields.ended = true;
+                        fields.current().keep = true;
+                    } else if i > 0 {
+                        fields.end_field();
+                    }
+                    if quoted {
+                        fields.current().push_quoted(param);
+                    } else {
+                        fields.push_split(param);
+                    }
+                }
+                continue;
+            }
+            WordPart::Variable(name) => variable(shell, name).unwrap_or_default(),
+            WordPart::Parameter(param) => parameter(shell, param)?,
+            WordPart::CommandSubstitution(list) => {
+                let output = shell.capture(list)?;
+                output.trim_end_matches('\n').to_string()
+            }
+            WordPart::Arithmetic(expr) => arithmetic(shell, expr)?.to_string(),
+        };
+        if quoted {
+            fields.current().push_quoted(&text);
+        } else {
+            fields.push_split(&text);
+        }
+    }
+    Ok(())
+}
+
+fn expand_fields(shell: &mut Shell, word: &Word, split: bool) -> Result<Vec<Expanded>> {
+    let ifs = if split {
+        Some(shell.variables.get("IFS").unwrap_or(" \t\n").to_string())
+    } else {
+        None
+    };
+    let mut fields = Fields::new(ifs);
+    expand_parts(shell, &word.parts, &mut fields, false)?;
+    Ok(fields.fields)
+}
+
+fn expand(shell: &mut Shell, word: &Word) -> Result<Expanded> {
+    Ok(expand_fields(shell, word, false)?.remove(0))
+}
+
+// Expands a word without field splitting or globbing, used for variable definitions
+pub fn expand_word_to_string(shell: &mut Shell, word: &Word) -> Result<String> {
+    Ok(expand(shell, word)?.text)
+}
+
+// The expression of $((...)), ((...)) and ${name:offset:length} is expanded
+// like a double quoted string and then evaluated
+pub fn arithmetic(shell: &mut Shell, expr: &Word) -> Result<i64> {
+    let text = expand_word_to_string(shell, expr)?;
+    arithmetic::evaluate(&mut shell.variables, &text)
+}
+
+// Todo: rustyline escape star character in filenames
+// ["echo", "$HOME/*.md"] -> ["echo", "/home/user/README.md"]
+pub fn expand_words(shell: &mut Shell, words: &[Word]) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+    for word in words {
+        for expanded in expand_fields(shell, word, true)? {
+            // A word that expanded to nothing without quotes is removed, "$empty"
+            if expanded.text.is_empty() && !expanded.keep {
+                continue;
+            }
+            // Glob paths. ex ./*.md
+            if expanded.has_glob {
+                if let Ok(globs) = glob(&expanded.pattern) {
+                    let mut entries: Vec<String> = globs
+                        .flatten()
+                        .map(|entry| {
+                            let entry_string = entry.display().to_string();
+                            if entry_string.starts_with('/') {
+                                entry_string
+                            } else {
+                                format!("./{}", entry_string)
+                            }
+                        })
+                        .collect();
+                    // If there is none the word is used as is
+                    if !entries.is_empty() {
+                        result.append(&mut entries);
+                        continue;
+                    }
+                }
+            }
+            result.push(expanded.text);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variable() {
+        let mut shell = Shell::new();
+        shell.arg0 = "zash".to_string();
+        shell.positional = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(variable(&shell, "0").as_deref(), Some("zash"));
+        assert_eq!(variable(&shell, "2").as_deref(), Some("b"));
+        assert_eq!(variable(&shell, "02").as_deref(), Some("b"));
+        assert_eq!(variable(&shell, "00"), None);
+        assert_eq!(variable(&shell, "3"), None);
+        assert_eq!(variable(&shell, "#").as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_patterns() {
+        let pattern = |pattern: &str| Pattern::new(pattern).unwrap();
+
+        // (value, pattern, longest, prefix removed, suffix removed)
+        let v = vec![
+            ("a.b.c.d", "*.", false, "b.c.d", "a.b.c.d"),
+            ("a.b.c.d", "*.", true, "d", "a.b.c.d"),
+            ("a.b.c.d", ".*", false, "a.b.c.d", "a.b.c"),
+            ("a.b.c.d", ".*", true, "a.b.c.d", "a"),
+            ("a.b.c.d", "x", true, "a.b.c.d", "a.b.c.d"),
+            // The shortest match of * is empty
+            ("abc", "*", false, "abc", "abc"),
+            ("abc", "*", true, "", ""),
+            ("日本.語", "*.", false, "語", "日本.語"),
+        ];
+        for (value, p, longest, prefix, suffix) in v {
+            assert_eq!(remove_prefix(value, &pattern(p), longest), prefix);
+            assert_eq!(remove_suffix(value, &pattern(p), longest), suffix);
+        }
+
+        // (value, pattern, mode, result), the replacement is "-"
+        let v = vec![
+            ("aXbXc", "X", ReplaceMode::First, "a-bXc"),
+            ("aXbXc", "X", ReplaceMode::All, "a-b-c"),
+            ("aXbXc", "a", ReplaceMode::Prefix, "-XbXc"),
+            ("aXbXc", "X", ReplaceMode::Prefix, "aXbXc"),
+            ("aXbXc", "c", ReplaceMode::Suffix, "aXbX-"),
+            ("aXbXc", "X", ReplaceMode::Suffix, "aXbXc"),
+            // The longest match is replaced
+            ("aXbXc", "X*", ReplaceMode::First, "a-"),
+            ("aXbXc", "a*X", ReplaceMode::Prefix, "-c"),
+            ("aXbXc", "X*", ReplaceMode::Suffix, "a-"),
+            ("abc", "?", ReplaceMode::All, "---"),
+            ("日本語", "本", ReplaceMode::All, "日-語"),
+            // Empty matches are only replaced at the start or the end
+            ("abc", "", ReplaceMode::First, "abc"),
+            ("abc", "", ReplaceMode::All, "abc"),
+            ("abc", "x*", ReplaceMode::All, "abc"),
+            ("abc", "", ReplaceMode::Prefix, "-abc"),
+            ("abc", "", ReplaceMode::Suffix, "abc-"),
+        ];
+        for (value, p, mode, result) in v {
+            assert_eq!(replace(value, &pattern(p), "-", mode), result);
+        }
+    }
+
+    #[test]
+    fn test_substring() {
+        // (offset, length, result) for "hello"
+        let v = vec![
+            (0, None, "hello"),
+            (1, None, "ello"),
+            (1, Some(3), "ell"),
+            (0, Some(100), "hello"),
+            (5, None, ""),
+            (10, None, ""),
+            // Negative offsets count from the end
+            (-3, None, "llo"),
+            (-3, Some(2), "ll"),
+            (-10, None, ""),
+            // And so do negative lengths
+            (1, Some(-1), "ell"),
+            (-4, Some(-1), "ell"),
+            (2, Some(-3), ""),
+        ];
+        for (offset, length, result) in v {
+            assert_eq!(substring("hello", offset, length).unwrap(), result);
+        }
+        assert_eq!(substring("日本語", 1, Some(1)).unwrap(), "本");
+        assert_eq!(
+            substring("hello", 3, Some(-3)),
+            Err("-3: substring expression < 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let split = |ifs: Option<&str>, text: &str| -> Vec<String> {
+            let mut fields = Fields::new(ifs.map(str::to_string));
+            fields.push_split(text);
+            fields.fields.into_iter().map(|field| field.text).collect()
+        };
+
+        let v = vec![
+            (None, "a b", vec!["a b"]),
+            (Some(""), "a b", vec!["a b"]),
+            // Runs of whitespace are one separator
+            (Some(" \t\n"), "a b", vec!["a", "b"]),
+            (Some(" \t\n"), "a \t\n b", vec!["a", "b"]),
+            // Leading and trailing whitespace is no field
+            (Some(" \t\n"), " a ", vec!["a"]),
+            (Some(" \t\n"), "   ", vec![""]),
+            (Some(" \t\n"), "a:b", vec!["a:b"]),
+            // Every other IFS char is a separator
+            (Some(":"), "a:b", vec!["a", "b"]),
+            (Some(":"), "a::b", vec!["a", "", "b"]),
+            (Some(":"), ":a", vec!["", "a"]),
+            (Some(":"), "a:", vec!["a"]),
+            (Some(":"), "::", vec!["", ""]),
+            (Some(":"), "a b", vec!["a b"]),
+            // Whitespace around them is part of the separator
+            (Some(" :"), "a : b", vec!["a", "b"]),
+            (Some(" :"), "a  b", vec!["a", "b"]),
+            (Some(" :"), "a :: b", vec!["a", "", "b"]),
+            (Some(" :"), "a: :b", vec!["a", "", "b"]),
+            (Some(" :"), " : a", vec!["", "a"]),
+        ];
+        for (ifs, text, fields) in v {
+            assert_eq!(split(ifs, text), fields, "{:?} split by {:?}", text, ifs);
+        }
+
+        // The first field goes on with what was before the expansion
+        let mut fields = Fields::new(Some(" ".to_string()));
+        fields.current().push_quoted("x");
+        fields.push_split("a b");
+        assert_eq!(fields.fields[0].text, "xa");
+        assert_eq!(fields.fields[1].text, "b");
+    }
+
+    #[test]
+    fn test_expand_words() {
+        use crate::parsers::ast::Command;
+        use crate::parsers::parser::parse_line;
+
+        let mut shell = Shell::new();
+        shell.positional = vec!["".to_string(), "a".to_string(), "".to_string()];
+        // (IFS, x, line, words)
+        let v = vec![
+            (":", "a::b", "echo $x", vec!["echo", "a", "", "b"]),
+            (":", "a:", "echo $x", vec!["echo", "a"]),
+            (":", ":", "echo $x", vec!["echo", ""]),
+            (":", "a:", "echo $x\"\"", vec!["echo", "a", ""]),
+            (" ", " a ", "echo $x", vec!["echo", "a"]),
+            (" ", " a ", "echo x$x\"y\"", vec!["echo", "x", "a", "y"]),
+            (" ", " a ", "echo \"\"$x", vec!["echo", "", "a"]),
+            (" ", "  ", "echo $x $x", vec!["echo"]),
+            (" ", "", "echo $x \"$x\"", vec!["echo", ""]),
+            // Empty parameters are only fields in "$@"
+            (" ", "", "echo $@", vec!["echo", "a"]),
+            (" ", "", "echo \"$@\"", vec!["echo", "", "a", ""]),
+        ];
+        for (ifs, x, line, words) in v {
+            shell.variables.set("IFS", ifs).unwrap();
+            shell.variables.set("x", x).unwrap();
+            let list = parse_line(line).unwrap();
+            let Command::Simple(simple) = &list.items[0].and_or.first.commands[0] else {
+                unreachable!()
+            };
+            assert_eq!(
+                expand_words(&mut shell, &simple.words).unwrap(),
+                words,
+                "{} with x={:?} IFS={:?}",
+                line,
+                x,
+                ifs
+            );
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// The .git/index file, what is staged for the next commit
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+
+use super::objects::Id;
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: String,
+    pub id: Id,
+    pub mode: u32,
+    pub size: u32,
+    // Seconds and nanoseconds, to tell if the file changed without reading it
+    pub mtime: (u32, u32),
+    // Not 0 for the sides of a merge conflict
+    pub stage: u8,
+    // Sparse checkouts don't have these files
+    pub skip_worktree: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    pub entries: Vec<Entry>,
+    // The tree of all the entries, if git has it in the cache-tree extension
+    pub root_tree: Option<Id>,
+}
+
+pub fn read(path: &Path) -> Option<Index> {
+    parse(&fs::read(path).ok()?)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+pub fn parse(data: &[u8]) -> Option<Index> {
+    if data.get(..4)? != b"DIRC" {
+        return None;
+    }
+    let version = u32_at(data, 4)?;
+    if !(2..=4).contains(&version) {
+        return None;
+    }
+    let count = u32_at(data, 8)?;
+    let mut index = Index::default();
+    let mut pos = 12;
+    let mut previous: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        let start = pos;
+        let flags = u16_at(data, start + 60)?;
+        let mut path_start = start + 62;
+        let mut skip_worktree = false;
+        if version >= 3 && flags & 0x4000 != 0 {
+            skip_worktree = u16_at(data, path_start)? & 0x4000 != 0;
+            path_start += 2;
+        }
+        let path = if version == 4 {
+            // The end of the previous path is replaced with a new suffix
+            let mut byte = *data.get(path_start)?;
+            path_start += 1;
+            let mut strip = (byte & 0x7f) as usize;
+            while byte & 0x80 != 0 {
+                byte = *data.get(path_start)?;
+                path_start += 1;
+                strip = ((strip + 1) << 7) | (byte & 0x7f) as usize;
+            }
+            let len = data.get(path_start..)?.iter().position(|&b| b == 0)?;
+            let mut path = previous[..previous.len().checked_sub(strip)?].to_vec();
+            path.extend_from_slice(&data[path_start..path_start + len]);
+            pos = path_start + len + 1;
+            path
+        } else {
+            let len = data.get(path_start..)?.iter().position(|&b| b == 0)?;
+            // Entries are padded with NULs to a multiple of 8 bytes
+            pos = start + ((path_start - start + len + 8) & !7);
+            data[path_start..path_start + len].to_vec()
+        };
+        index.entries.push(Entry {
+            path: String::from_utf8_lossy(&path).into_owned(),
+            id: data.get(start + 40..start + 60)?.try_into().ok()?,
+            mode: u32_at(data, start + 24)?,
+            size: u32_at(data, start + 36)?,
+            mtime: (u32_at(data, start + 8)?, u32_at(data, start + 12)?),
+            stage: ((flags >> 12) & 3) as u8,
+            skip_worktree,
+        });
+        previous = path;
+    }
+
+    // Extensions until the checksum at the end
+    while pos + 8 + 20 <= data.len() {
+        let size = u32_at(data, pos + 4)? as usize;
+        let extension = data.get(pos + 8..pos + 8 + size)?;
+        if &data[pos..pos + 4] == b"TREE" {
+            index.root_tree = root_tree(extension);
+        }
+        pos += 8 + size;
+    }
+    Some(index)
+}
+
+// The first cache-tree entry is the root: "\0<entries> <subtrees>\n<id>",
+// with -1 entries when it is out of date
+fn root_tree(extension: &[u8]) -> Option<Id> {
+    if *extension.first()? != 0 {
+        return None;
+    }
+    let newline = extension.iter().position(|&b| b == b'\n')?;
+    let counts = std::str::from_utf8(&extension[1..newline]).ok()?;
+    let entries: i64 = counts.split(' ').next()?.parse().ok()?;
+    if entries < 0 {
+        return None;
+    }
+    extension.get(newline + 1..newline + 21)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(data: &mut Vec<u8>, path: &str, flags: u16, extended: Option<u16>) {
+        let start = data.len();
+        data.extend_from_slice(&[0; 8]);
+        // mtime
+        data.extend_from_slice(&5u32.to_be_bytes());
+        data.extend_from_slice(&6u32.to_be_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&0o100644u32.to_be_bytes());
+        data.extend_from_slice(&[0; 8]);
+        // size
+        data.extend_from_slice(&3u32.to_be_bytes());
+        data.extend_from_slice(&[7; 20]);
+        data.extend_from_slice(&(flags | path.len() as u16).to_be_bytes());
+        if let Some(extended) = extended {
+            data.extend_from_slice(&extended.to_be_bytes());
+        }
+        data.extend_from_slice(path.as_bytes());
+        // 1 to 8 NULs
+        let padding = 8 - (data.len() - start) % 8;
+        data.extend_from_slice(&[0; 8][..padding]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut data = b"DIRC".to_vec();
+        data.extend_from_slice(&3u32.to_be_bytes());
+        data.extend_from_slice(&3u32.to_be_bytes());
+        entry(&mut data, "a.txt", 0, None);
+        entry(&mut data, "dir/sparse", 0x4000, Some(0x4000));
+        entry(&mut data, "conflict", 0x2000, None);
+        data.extend_from_slice(b"TREE");
+        let mut tree = b"\x003 1\n".to_vec();
+        tree.extend_from_slice(&[9; 20]);
+        data.extend_from_slice(&(tree.len() as u32).to_be_bytes());
+        data.extend_from_slice(&tree);
+        data.extend_from_slice(&[0; 20]);
+
+        let index = parse(&data).unwrap();
+        assert_eq!(index.entries.len(), 3);
+        let first = &index.entries[0];
+        assert_eq!(first.path, "a.txt");
+        assert_eq!((first.mode, first.size, first.mtime), (0o100644, 3, (5, 6)));
+        assert_eq!(first.id, [7; 20]);
+        assert!(index.entries[1].skip_worktree);
+        assert_eq!(index.entries[1].path, "dir/sparse");
+        assert_eq!(index.entries[2].stage, 2);
+        assert_eq!(index.root_tree, Some([9; 20]));
+
+        assert!(root_tree(b"\0-1 1\n").is_none());
+        assert!(parse(b"DIRX").is_none());
+    }
+}
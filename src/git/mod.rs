@@ -0,0 +1,534 @@
+// Git information for the prompt, read from the .git directory without running
+// git. Everything has a time budget so a huge repository can't hold up the
+// prompt, whatever is not done by then is left out. The results are cached per
+// directory until HEAD, the index or the refs change.
+use std::cell::RefCell;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+mod index;
+mod objects;
+
+use index::Index;
+use objects::{Id, Objects};
+
+const BUDGET: Duration = Duration::from_millis(100);
+
+struct Repo {
+    work_dir: PathBuf,
+    git_dir: PathBuf,
+    // Where the refs and objects are, not the same as git_dir for worktrees
+    common_dir: PathBuf,
+}
+
+enum Head {
+    Branch(String),
+    Detached(Id),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    // The branch, or the short id when HEAD is detached
+    pub head: String,
+    // Commits that are not in the upstream branch and the other way around
+    pub ahead_behind: Option<(usize, usize)>,
+    // Files that are different in the index than in HEAD
+    pub staged: Option<usize>,
+    // Files that are different in the working tree than in the index
+    pub dirty: Option<usize>,
+    // "REBASE 2/5", "MERGING"...
+    pub state: Option<String>,
+}
+
+// "main ↑1↓2 +3 !4 REBASE 2/5", counts that are 0 or unknown are left out
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.head)?;
+        if let Some((ahead, behind)) = self.ahead_behind {
+            if ahead > 0 || behind > 0 {
+                f.write_str(" ")?;
+            }
+            if ahead > 0 {
+                write!(f, "↑{}", ahead)?;
+            }
+            if behind > 0 {
+                write!(f, "↓{}", behind)?;
+            }
+        }
+        if let Some(staged) = self.staged.filter(|&staged| staged > 0) {
+            write!(f, " +{}", staged)?;
+        }
+        if let Some(dirty) = self.dirty.filter(|&dirty| dirty > 0) {
+            write!(f, " !{}", dirty)?;
+        }
+        if let Some(state) = &self.state {
+            write!(f, " {}", state)?;
+        }
+        Ok(())
+    }
+}
+
+impl Repo {
+    // The repository dir is in. Worktrees and submodules have a .git file
+    // with "gitdir: path" instead of the directory.
+    fn find(dir: &Path) -> Option<Self> {
+        for dir in dir.ancestors() {
+            let git = dir.join(".git");
+            let git_dir = if git.is_dir() {
+                git
+            } else if let Ok(text) = fs::read_to_string(&git) {
+                dir.join(text.strip_prefix("gitdir:")?.trim())
+            } else {
+                continue;
+            };
+            let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+                Ok(common) => git_dir.join(common.trim()),
+                Err(_) => git_dir.clone(),
+            };
+            return Some(Self {
+                work_dir: dir.to_path_buf(),
+                git_dir,
+                common_dir,
+            });
+        }
+        None
+    }
+
+    fn head(&self) -> Option<Head> {
+        let head = fs::read_to_string(self.git_dir.join("HEAD")).ok()?;
+        match head.trim().strip_prefix("ref: ") {
+            Some(reference) => Some(Head::Branch(reference.to_string())),
+            None => Some(Head::Detached(objects::parse_hex(&head)?)),
+        }
+    }
+
+    // Loose refs are files, the others are lines in packed-refs
+    fn resolve(&self, reference: &str) -> Option<Id> {
+        let mut reference = reference.to_string();
+        // Symbolic refs point to other refs
+        for _ in 0..5 {
+            let text = fs::read_to_string(self.git_dir.join(&reference))
+                .or_else(|_| fs::read_to_string(self.common_dir.join(&reference)));
+            match text {
+                Ok(text) => match text.trim().strip_prefix("ref: ") {
+                    Some(next) => reference = next.to_string(),
+                    None => return objects::parse_hex(&text),
+                },
+                Err(_) => break,
+            }
+        }
+        let packed = fs::read_to_string(self.common_dir.join("packed-refs")).ok()?;
+        packed.lines().find_map(|line| match line.split_once(' ') {
+            Some((id, name)) if name == reference => objects::parse_hex(id),
+            _ => None,
+        })
+    }
+
+    // "refs/remotes/origin/main" from the [branch "main"] section of the config
+    fn upstream(&self, branch: &str) -> Option<String> {
+        let config = fs::read_to_string(self.common_dir.join("config")).ok()?;
+        let section = format!("[branch \"{}\"]", branch);
+        let mut inside = false;
+        let mut remote = None;
+        let mut merge = None;
+        for line in config.lines().map(str::trim) {
+            if line.starts_with('[') {
+                inside = line == section;
+            } else if let (true, Some((key, value))) = (inside, line.split_once('=')) {
+                match key.trim() {
+                    "remote" => remote = Some(value.trim().to_string()),
+                    "merge" => merge = Some(value.trim().to_string()),
+                    _ => {}
+                }
+            }
+        }
+        let merge = merge?;
+        match remote?.as_str() {
+            // Tracking a local branch
+            "." => Some(merge),
+            remote => Some(format!(
+                "refs/remotes/{}/{}",
+                remote,
+                merge.strip_prefix("refs/heads/")?
+            )),
+        }
+    }
+
+    // A rebase, merge and such that is not finished
+    fn state(&self) -> Option<String> {
+        let git_dir = &self.git_dir;
+        let read = |name: &str| {
+            fs::read_to_string(git_dir.join(name))
+                .ok()
+                .map(|text| text.trim().to_string())
+        };
+        let progress = |name: &str, step: &str, total: &str| match (read(step), read(total)) {
+            (Some(step), Some(total)) => format!("{} {}/{}", name, step, total),
+            _ => name.to_string(),
+        };
+        if git_dir.join("rebase-merge").is_dir() {
+            Some(progress(
+                "REBASE",
+                "rebase-merge/msgnum",
+                "rebase-merge/end",
+            ))
+        } else if git_dir.join("rebase-apply").is_dir() {
+            let name = if git_dir.join("rebase-apply/applying").exists() {
+                "AM"
+            } else {
+                "REBASE"
+            };
+            Some(progress(name, "rebase-apply/next", "rebase-apply/last"))
+        } else if git_dir.join("MERGE_HEAD").exists() {
+            Some("MERGING".to_string())
+        } else if git_dir.join("CHERRY_PICK_HEAD").exists() {
+            Some("CHERRY-PICKING".to_string())
+        } else if git_dir.join("REVERT_HEAD").exists() {
+            Some("REVERTING".to_string())
+        } else if git_dir.join("BISECT_LOG").exists() {
+            Some("BISECTING".to_string())
+        } else {
+            None
+        }
+    }
+
+    // The branch being rebased, HEAD is detached meanwhile
+    fn rebased_branch(&self) -> Option<String> {
+        ["rebase-merge/head-name", "rebase-apply/head-name"]
+            .iter()
+            .find_map(|name| fs::read_to_string(self.git_dir.join(name)).ok())
+            .map(|name| name.trim().to_string())
+    }
+
+    // When these files change the cached status is out of date
+    fn stamp(&self, branch: Option<&str>) -> Vec<Option<SystemTime>> {
+        let mut files = vec![
+            self.git_dir.join("HEAD"),
+            self.git_dir.join("index"),
+            self.git_dir.join("MERGE_HEAD"),
+            self.git_dir.join("CHERRY_PICK_HEAD"),
+            self.git_dir.join("REVERT_HEAD"),
+            self.git_dir.join("BISECT_LOG"),
+            self.git_dir.join("rebase-merge"),
+            self.git_dir.join("rebase-merge/msgnum"),
+            self.git_dir.join("rebase-apply"),
+            self.git_dir.join("rebase-apply/next"),
+            self.common_dir.join("packed-refs"),
+            self.common_dir.join("config"),
+        ];
+        if let Some(branch) = branch {
+            files.push(self.common_dir.join(branch));
+            if let Some(upstream) = self.upstream(short_name(branch)) {
+                files.push(self.common_dir.join(upstream));
+            }
+        }
+        files
+            .iter()
+            .map(|file| fs::metadata(file).and_then(|meta| meta.modified()).ok())
+            .collect()
+    }
+}
+
+fn short_name(reference: &str) -> &str {
+    reference.strip_prefix("refs/heads/").unwrap_or(reference)
+}
+
+// Walks the commits newest first from both ends, until every commit left is
+// in both histories. The ones only one side reached are ahead or behind.
+fn ahead_behind(
+    objects: &Objects,
+    local: Id,
+    upstream: Id,
+    deadline: Instant,
+) -> Option<(usize, usize)> {
+    const LOCAL: u8 = 1;
+    const UPSTREAM: u8 = 2;
+    const BOTH: u8 = LOCAL | UPSTREAM;
+    if local == upstream {
+        return Some((0, 0));
+    }
+    let mut commits = HashMap::new();
+    let mut commit = |id: Id| -> objects::Commit {
+        commits
+            .entry(id)
+            .or_insert_with(|| match objects.read(&id) {
+                Some((objects::Kind::Commit, data)) => objects::parse_commit(&data),
+                // Shallow clones don't have every commit
+                _ => objects::Commit::default(),
+            })
+            .clone()
+    };
+    let mut flags = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    for (id, flag) in [(local, LOCAL), (upstream, UPSTREAM)] {
+        flags.insert(id, flag);
+        queue.push((commit(id).time, id));
+    }
+    while queue.iter().any(|(_, id)| flags[id] != BOTH) {
+        if Instant::now() > deadline {
+            return None;
+        }
+        let (_, id) = queue.pop()?;
+        let flag = flags[&id];
+        for parent in commit(id).parents {
+            let parent_flag = flags.entry(parent).or_insert(0);
+            if *parent_flag | flag != *parent_flag {
+                *parent_flag |= flag;
+                queue.push((commit(parent).time, parent));
+            }
+        }
+    }
+    // Commits with the same time can be walked in the wrong order, so a commit
+    // reached from one side first may be in both histories after all
+    let mut stack: Vec<Id> = flags
+        .iter()
+        .filter(|(_, &flag)| flag == BOTH)
+        .map(|(&id, _)| id)
+        .collect();
+    while let Some(id) = stack.pop() {
+        for parent in commits
+            .get(&id)
+            .map(|commit| commit.parents.clone())
+            .unwrap_or_default()
+        {
+            if let Some(flag) = flags.get_mut(&parent).filter(|flag| **flag != BOTH) {
+                *flag = BOTH;
+                stack.push(parent);
+            }
+        }
+    }
+    let count = |side| flags.values().filter(|&&flag| flag == side).count();
+    Some((count(LOCAL), count(UPSTREAM)))
+}
+
+// Every file in the tree, with its mode and id
+fn flatten_tree(
+    objects: &Objects,
+    tree: Id,
+    prefix: &str,
+    files: &mut HashMap<String, (u32, Id)>,
+    deadline: Instant,
+) -> Option<()> {
+    if Instant::now() > deadline {
+        return None;
+    }
+    let data = match objects.read(&tree)? {
+        (objects::Kind::Tree, data) => data,
+        _ => return None,
+    };
+    for (mode, name, id) in objects::parse_tree(&data)? {
+        let path = format!("{}{}", prefix, name);
+        if mode == 0o40000 {
+            flatten_tree(objects, id, &format!("{}/", path), files, deadline)?;
+        } else {
+            files.insert(path, (mode, id));
+        }
+    }
+    Some(())
+}
+
+fn staged(objects: &Objects, head_tree: Id, index: &Index, deadline: Instant) -> Option<usize> {
+    // git keeps the tree of the index after a commit, nothing is staged if it is HEAD's
+    if index.root_tree == Some(head_tree) {
+        return Some(0);
+    }
+    let mut files = HashMap::new();
+    flatten_tree(objects, head_tree, "", &mut files, deadline)?;
+    let mut staged = 0;
+    for entry in &index.entries {
+        match files.remove(&entry.path) {
+            // Conflicts are counted as dirty
+            _ if entry.stage != 0 => {}
+            Some((mode, id)) if mode == entry.mode && id == entry.id => {}
+            _ => staged += 1,
+        }
+    }
+    // Deleted files
+    Some(staged + files.len())
+}
+
+fn dirty(work_dir: &Path, index: &Index, deadline: Instant) -> Option<usize> {
+    let mut dirty = 0;
+    let mut last_conflict = None;
+    for (i, entry) in index.entries.iter().enumerate() {
+        if i % 64 == 0 && Instant::now() > deadline {
+            return None;
+        }
+        if entry.stage != 0 {
+            if last_conflict != Some(&entry.path) {
+                dirty += 1;
+                last_conflict = Some(&entry.path);
+            }
+            continue;
+        }
+        // Submodules have their own status
+        if entry.skip_worktree || entry.mode == 0o160000 {
+            continue;
+        }
+        if changed(&work_dir.join(&entry.path), entry) {
+            dirty += 1;
+        }
+    }
+    Some(dirty)
+}
+
+fn changed(path: &Path, entry: &index::Entry) -> bool {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return true,
+    };
+    let mode = if meta.file_type().is_symlink() {
+        0o120000
+    } else if !meta.is_file() {
+        return true;
+    } else if meta.permissions().mode() & 0o111 != 0 {
+        0o100755
+    } else {
+        0o100644
+    };
+    if mode != entry.mode || meta.len() as u32 != entry.size {
+        return true;
+    }
+    if (meta.mtime() as u32, meta.mtime_nsec() as u32) == entry.mtime {
+        return false;
+    }
+    // Touched but the same size, only the content can tell
+    let data = if mode == 0o120000 {
+        fs::read_link(path).map(|target| target.as_os_str().as_bytes().to_vec())
+    } else {
+        fs::read(path)
+    };
+    match data {
+        Ok(data) => objects::blob_id(&data) != entry.id,
+        Err(_) => true,
+    }
+}
+
+struct Cached {
+    stamp: Vec<Option<SystemTime>>,
+    // Without the dirty count, the working tree has no stamp
+    status: Status,
+    index: Option<Rc<Index>>,
+    // The last try ran out of time, don't make every prompt slow
+    dirty_too_slow: bool,
+}
+
+thread_local! {
+    static CACHE: RefCell<HashMap<PathBuf, Cached>> = RefCell::new(HashMap::new());
+}
+
+// The branch or short id of HEAD, for %g
+pub fn head(dir: &Path) -> Option<String> {
+    let repo = Repo::find(dir)?;
+    match repo.head()? {
+        Head::Branch(branch) => Some(short_name(&branch).to_string()),
+        Head::Detached(id) => Some(objects::to_hex(&id)[..7].to_string()),
+    }
+}
+
+pub fn status(dir: &Path) -> Option<Status> {
+    let deadline = Instant::now() + BUDGET;
+    let repo = Repo::find(dir)?;
+    let branch = match repo.head()? {
+        Head::Branch(branch) => Some(branch),
+        Head::Detached(_) => None,
+    };
+    let stamp = repo.stamp(branch.as_deref());
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let cached = match cache.get_mut(dir) {
+            Some(cached) if cached.stamp == stamp => cached,
+            _ => {
+                let (status, index) = compute(&repo, branch.as_deref(), deadline);
+                cache.insert(
+                    dir.to_path_buf(),
+                    Cached {
+                        stamp,
+                        status,
+                        index: index.map(Rc::new),
+                        dirty_too_slow: false,
+                    },
+                );
+                cache.get_mut(dir).unwrap()
+            }
+        };
+        let mut status = cached.status.clone();
+        if let (Some(index), false) = (&cached.index, cached.dirty_too_slow) {
+            status.dirty = dirty(&repo.work_dir, index, deadline);
+            cached.dirty_too_slow = status.dirty.is_none();
+        }
+        Some(status)
+    })
+}
+
+// Everything but the dirty count
+fn compute(repo: &Repo, branch: Option<&str>, deadline: Instant) -> (Status, Option<Index>) {
+    let mut status = Status {
+        state: repo.state(),
+        ..Default::default()
+    };
+    let head_id = match (branch, repo.head()) {
+        (Some(branch), _) => {
+            status.head = short_name(branch).to_string();
+            repo.resolve(branch)
+        }
+        (None, Some(Head::Detached(id))) => {
+            status.head = match repo.rebased_branch() {
+                Some(branch) => short_name(&branch).to_string(),
+                None => objects::to_hex(&id)[..7].to_string(),
+            };
+            Some(id)
+        }
+        (None, _) => None,
+    };
+    let index = index::read(&repo.git_dir.join("index"));
+    let objects = Objects::open(repo.common_dir.join("objects"));
+
+    if let Some(head_id) = head_id {
+        let upstream = branch
+            .and_then(|branch| repo.upstream(short_name(branch)))
+            .and_then(|upstream| repo.resolve(&upstream));
+        if let Some(upstream) = upstream {
+            status.ahead_behind = ahead_behind(&objects, head_id, upstream, deadline);
+        }
+        let head_tree = match objects.read(&head_id) {
+            Some((objects::Kind::Commit, data)) => objects::parse_commit(&data).tree,
+            _ => None,
+        };
+        if let (Some(head_tree), Some(index)) = (head_tree, &index) {
+            status.staged = staged(&objects, head_tree, index, deadline);
+        }
+    } else if let Some(index) = &index {
+        // No commits yet, everything in the index is staged
+        status.staged = Some(index.entries.len());
+    }
+    (status, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_display() {
+        let mut status = Status {
+            head: "main".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(status.to_string(), "main");
+        status.ahead_behind = Some((0, 0));
+        status.staged = Some(0);
+        assert_eq!(status.to_string(), "main");
+        status.ahead_behind = Some((1, 2));
+        status.staged = Some(3);
+        status.dirty = Some(4);
+        status.state = Some("REBASE 2/5".to_string());
+        assert_eq!(status.to_string(), "main ↑1↓2 +3 !4 REBASE 2/5");
+    }
+}
@@ -0,0 +1,383 @@
+// Reading objects from .git/objects, the loose ones and the ones in packs
+use flate2::read::ZlibDecoder;
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+pub type Id = [u8; 20];
+
+// Sizes are read from the objects, a corrupt one could ask for anything, so
+// no more than this is reserved up front and the rest grows as it is read
+const MAX_RESERVE: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl Kind {
+    fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"commit" => Some(Kind::Commit),
+            b"tree" => Some(Kind::Tree),
+            b"blob" => Some(Kind::Blob),
+            b"tag" => Some(Kind::Tag),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_hex(hex: &str) -> Option<Id> {
+    let hex = hex.trim();
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut id = [0; 20];
+    for (i, byte) in id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(id)
+}
+
+pub fn to_hex(id: &Id) -> String {
+    id.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The id git gives a file with this content
+pub fn blob_id(data: &[u8]) -> Id {
+    let mut sha = sha1_smol::Sha1::new();
+    sha.update(format!("blob {}\0", data.len()).as_bytes());
+    sha.update(data);
+    sha.digest().bytes()
+}
+
+// A pack and its .idx (version 2), read with seeks so huge packs are not loaded
+struct Pack {
+    index: File,
+    pack: File,
+    // Number of objects starting with a byte up to each value
+    fanout: [u32; 256],
+}
+
+const FANOUT_START: u64 = 8;
+const NAMES_START: u64 = FANOUT_START + 256 * 4;
+
+impl Pack {
+    fn open(index_path: &Path) -> Option<Self> {
+        let index = File::open(index_path).ok()?;
+        let mut header = [0u8; NAMES_START as usize];
+        index.read_exact_at(&mut header, 0).ok()?;
+        if header[..8] != [0xff, b't', b'O', b'c', 0, 0, 0, 2] {
+            return None;
+        }
+        let mut fanout = [0u32; 256];
+        for (i, count) in fanout.iter_mut().enumerate() {
+            let start = FANOUT_START as usize + i * 4;
+            *count = u32::from_be_bytes(header[start..start + 4].try_into().unwrap());
+        }
+        let pack = File::open(index_path.with_extension("pack")).ok()?;
+        Some(Self {
+            index,
+            pack,
+            fanout,
+        })
+    }
+
+    // Where the object starts in the pack, a binary search in the sorted ids
+    fn find(&self, id: &Id) -> Option<u64> {
+        let first = id[0] as usize;
+        let mut low = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1]
+        } as u64;
+        let mut high = self.fanout[first] as u64;
+        let mut name = [0u8; 20];
+        while low < high {
+            let middle = (low + high) / 2;
+            self.index
+                .read_exact_at(&mut name, NAMES_START + middle * 20)
+                .ok()?;
+            match name.cmp(id) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return self.offset(middle),
+            }
+        }
+        None
+    }
+
+    fn offset(&self, position: u64) -> Option<u64> {
+        let count = self.fanout[255] as u64;
+        // After the ids and their crc32s
+        let offsets = NAMES_START + count * 24;
+        let mut small = [0u8; 4];
+        self.index
+            .read_exact_at(&mut small, offsets + position * 4)
+            .ok()?;
+        let small = u32::from_be_bytes(small);
+        if small & 0x8000_0000 == 0 {
+            return Some(small as u64);
+        }
+        // Packs over 2GB have a table of 8 byte offsets after that
+        let mut large = [0u8; 8];
+        let position = (small & 0x7fff_ffff) as u64;
+        self.index
+            .read_exact_at(&mut large, offsets + count * 4 + position * 8)
+            .ok()?;
+        Some(u64::from_be_bytes(large))
+    }
+
+    fn read(&self, objects: &Objects, offset: u64) -> Option<(Kind, Vec<u8>)> {
+        let mut header = [0u8; 32];
+        let len = self.pack.read_at(&mut header, offset).ok()?;
+        let header = &header[..len];
+        let mut pos = 0;
+        let mut byte = *header.first()?;
+        let kind = (byte >> 4) & 7;
+        let mut size = (byte & 15) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            pos += 1;
+            byte = *header.get(pos)?;
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+        pos += 1;
+        let base = match kind {
+            // The base is at a distance before this one
+            6 => {
+                let mut byte = *header.get(pos)?;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    pos += 1;
+                    byte = *header.get(pos)?;
+                    distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+                }
+                pos += 1;
+                Some(self.read(objects, offset.checked_sub(distance)?)?)
+            }
+            // The base is named by its id
+            7 => {
+                let id: Id = header.get(pos..pos + 20)?.try_into().ok()?;
+                pos += 20;
+                Some(objects.read(&id)?)
+            }
+            _ => None,
+        };
+        let data = self.inflate(offset + pos as u64, size)?;
+        match (base, kind) {
+            (Some((kind, base)), _) => Some((kind, apply_delta(&base, &data)?)),
+            (None, 1) => Some((Kind::Commit, data)),
+            (None, 2) => Some((Kind::Tree, data)),
+            (None, 3) => Some((Kind::Blob, data)),
+            (None, 4) => Some((Kind::Tag, data)),
+            _ => None,
+        }
+    }
+
+    fn inflate(&self, offset: u64, size: usize) -> Option<Vec<u8>> {
+        let mut file = &self.pack;
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        let mut data = Vec::with_capacity(size.min(MAX_RESERVE));
+        ZlibDecoder::new(BufReader::new(file))
+            .take(size as u64)
+            .read_to_end(&mut data)
+            .ok()?;
+        (data.len() == size).then_some(data)
+    }
+}
+
+// A delta is the sizes, then instructions to copy from the base or insert new bytes
+fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+    let _base_size = varint(delta, &mut pos)?;
+    let size = varint(delta, &mut pos)?;
+    let mut result = Vec::with_capacity(size.min(MAX_RESERVE));
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            let mut offset = 0;
+            let mut len = 0;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    offset |= (*delta.get(pos)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    len |= (*delta.get(pos)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            if len == 0 {
+                len = 0x10000;
+            }
+            result.extend_from_slice(base.get(offset..offset + len)?);
+        } else if op != 0 {
+            let len = op as usize;
+            result.extend_from_slice(delta.get(pos..pos + len)?);
+            pos += len;
+        } else {
+            return None;
+        }
+    }
+    (result.len() == size).then_some(result)
+}
+
+fn varint(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        // Too many bytes in a corrupt one would shift past the end
+        value |= ((byte & 0x7f) as usize).checked_shl(shift)?;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+}
+
+pub struct Objects {
+    dir: PathBuf,
+    packs: Vec<Pack>,
+}
+
+impl Objects {
+    pub fn open(dir: PathBuf) -> Self {
+        let packs = fs::read_dir(dir.join("pack"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "idx" {
+                    return None;
+                }
+                Pack::open(&path)
+            })
+            .collect();
+        Self { dir, packs }
+    }
+
+    pub fn read(&self, id: &Id) -> Option<(Kind, Vec<u8>)> {
+        let hex = to_hex(id);
+        if let Ok(file) = File::open(self.dir.join(&hex[..2]).join(&hex[2..])) {
+            let mut data = Vec::new();
+            ZlibDecoder::new(BufReader::new(file))
+                .read_to_end(&mut data)
+                .ok()?;
+            // "commit 123\0" and the content
+            let space = data.iter().position(|&b| b == b' ')?;
+            let nul = data.iter().position(|&b| b == 0)?;
+            let kind = Kind::from_name(&data[..space])?;
+            return Some((kind, data.split_off(nul + 1)));
+        }
+        self.packs
+            .iter()
+            .find_map(|pack| pack.read(self, pack.find(id)?))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Commit {
+    pub tree: Option<Id>,
+    pub parents: Vec<Id>,
+    // Committer time, for walking newest first
+    pub time: i64,
+}
+
+pub fn parse_commit(data: &[u8]) -> Commit {
+    let mut commit = Commit::default();
+    let text = String::from_utf8_lossy(data);
+    for line in text.lines().take_while(|line| !line.is_empty()) {
+        match line.split_once(' ') {
+            Some(("tree", id)) => commit.tree = parse_hex(id),
+            Some(("parent", id)) => commit.parents.extend(parse_hex(id)),
+            // "committer Name <mail> 1637000000 +0100"
+            Some(("committer", rest)) => {
+                let mut words = rest.rsplit(' ');
+                commit.time = words.nth(1).and_then(|time| time.parse().ok()).unwrap_or(0);
+            }
+            _ => {}
+        }
+    }
+    commit
+}
+
+// (mode, name, id) for each "100644 name\0<20 byte id>"
+pub fn parse_tree(data: &[u8]) -> Option<Vec<(u32, String, Id)>> {
+    let mut entries = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let nul = rest.iter().position(|&b| b == 0)?;
+        let mode = u32::from_str_radix(std::str::from_utf8(&rest[..space]).ok()?, 8).ok()?;
+        // The nul can come before the space in a broken tree
+        let name = String::from_utf8_lossy(rest.get(space + 1..nul)?).into_owned();
+        let id = rest.get(nul + 1..nul + 21)?.try_into().ok()?;
+        entries.push((mode, name, id));
+        rest = &rest[nul + 21..];
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_objects() {
+        // Copy "hello " from the base, then insert "git"
+        let delta = [11, 9, 0x90, 6, 3, b'g', b'i', b't'];
+        assert_eq!(
+            apply_delta(b"hello world", &delta).as_deref(),
+            Some(&b"hello git"[..])
+        );
+        assert_eq!(apply_delta(b"hello world", &[11, 9, 0x90, 20]), None);
+
+        // The id of an empty file, like "git hash-object /dev/null"
+        assert_eq!(
+            to_hex(&blob_id(b"")),
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+        );
+        let id = parse_hex("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391").unwrap();
+        assert_eq!(parse_hex("e69de29b"), None);
+
+        let commit = format!(
+            "tree {0}\nparent {0}\nparent {0}\nauthor A <a> 1 +0000\ncommitter C <c> 1637000000 +0100\n\nmsg\n",
+            to_hex(&id)
+        );
+        let commit = parse_commit(commit.as_bytes());
+        assert_eq!(commit.tree, Some(id));
+        assert_eq!(commit.parents.len(), 2);
+        assert_eq!(commit.time, 1637000000);
+
+        let mut tree = b"100644 a.txt\0".to_vec();
+        tree.extend_from_slice(&id);
+        tree.extend_from_slice(b"40000 dir\0");
+        tree.extend_from_slice(&id);
+        let tree = parse_tree(&tree).unwrap();
+        assert_eq!(tree[0], (0o100644, "a.txt".to_string(), id));
+        assert_eq!(tree[1].0, 0o40000);
+        // Broken and cut off trees
+        assert_eq!(parse_tree(b"100644\0a.txt \0"), None);
+        assert_eq!(parse_tree(b"100644 a.txt\0\x01\x02"), None);
+        assert_eq!(parse_tree(b"100644 a.txt"), None);
+
+        // A delta that claims a huge size is not allocated up front
+        let mut delta = vec![11];
+        delta.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+        delta.extend_from_slice(&[0x90, 6]);
+        assert_eq!(apply_delta(b"hello world", &delta), None);
+        assert_eq!(apply_delta(b"hello world", &[0x80; 12]), None);
+    }
+}
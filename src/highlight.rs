@@ -0,0 +1,374 @@
+// Syntax highlighting of the command line, from the tokens of the zash lexer
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::builtins;
+use crate::config::Colors;
+use crate::parsers::errors::SyntaxError;
+use crate::parsers::lexer::{self, is_valid_variable_name};
+use crate::parsers::parser::{self, is_reserved_word};
+use crate::parsers::tokens::TokenKind;
+use crate::prompt;
+use crate::shell::Shell;
+
+const RESET: &str = "\x1b[0m";
+const UNDERLINE: &str = "\x1b[4m";
+
+// "bold red" to its escape code, None for an unknown color
+pub fn style(spec: &str) -> Option<String> {
+    let mut codes = Vec::new();
+    for word in spec.split_whitespace() {
+        match word {
+            "bold" => codes.push("1".to_string()),
+            color => codes.push(prompt::color(color)?),
+        }
+    }
+    if codes.is_empty() {
+        return Some(String::new());
+    }
+    Some(format!("\x1b[{}m", codes.join(";")))
+}
+
+// The escape codes of the colors in the config
+#[derive(Default)]
+struct Styles {
+    command: String,
+    builtin: String,
+    function: String,
+    alias: String,
+    keyword: String,
+    not_found: String,
+    string: String,
+    variable: String,
+    operator: String,
+    comment: String,
+    error: String,
+}
+
+impl Styles {
+    fn new(colors: &Colors) -> Self {
+        // The config checked them already
+        let style = |color: &String| style(color).unwrap_or_default();
+        Self {
+            command: style(&colors.command),
+            builtin: style(&colors.builtin),
+            function: style(&colors.function),
+            alias: style(&colors.alias),
+            keyword: style(&colors.keyword),
+            not_found: style(&colors.not_found),
+            string: style(&colors.string),
+            variable: style(&colors.variable),
+            operator: style(&colors.operator),
+            comment: style(&colors.comment),
+            error: style(&colors.error),
+        }
+    }
+}
+
+// Like the completer, it gets the names from the shell before each prompt
+#[derive(Default)]
+pub struct ShellHighlighter {
+    styles: Styles,
+    functions: HashSet<String>,
+    aliases: HashSet<String>,
+    path: String,
+    // Whether commands are in $PATH, so typing doesn't look through it on every key
+    found: RefCell<HashMap<String, bool>>,
+}
+
+impl ShellHighlighter {
+    pub fn new(colors: &Colors) -> Self {
+        Self {
+            styles: Styles::new(colors),
+            ..Self::default()
+        }
+    }
+
+    pub fn update(&mut self, shell: &Shell) {
+        self.functions = shell.functions.keys().cloned().collect();
+        self.aliases = shell.aliases.keys().cloned().collect();
+        self.path = shell.variables.get("PATH").unwrap_or_default().to_string();
+        // Commands may have been installed since the last prompt
+        self.found.borrow_mut().clear();
+    }
+
+    pub fn highlight(&self, line: &str) -> String {
+        let (tokens, error) = lexer::tokenize_partial(line);
+        let end = error.unwrap_or(line.len());
+        // A token the parser doesn't expect there is underlined, like the second
+        // "|" of "a | | b". Unfinished commands are not errors yet
+        let unexpected = match parser::parse_line(line) {
+            Err(SyntaxError::Unexpected { span, .. }) if span.0 < span.1 => Some(span),
+            _ => None,
+        };
+        let underline = format!("{}{}", self.styles.error, UNDERLINE);
+        let mut out = String::new();
+        let mut last = 0;
+        // Whether the next word is a command name
+        let mut command = true;
+        let mut redirect = false;
+        for token in tokens {
+            let (start, stop) = token.span;
+            self.gap(&line[last..start], &mut out);
+            let text = &line[start..stop];
+            let mark = out.len();
+            match token.kind {
+                TokenKind::Operator(op) => {
+                    paint(&mut out, &self.styles.operator, text);
+                    redirect = op.as_str().contains(['<', '>']);
+                    command = command || !redirect;
+                }
+                TokenKind::IoNumber(_) => paint(&mut out, &self.styles.operator, text),
+                TokenKind::Newline => {
+                    out.push_str(text);
+                    command = true;
+                }
+                TokenKind::Arithmetic(_) => {
+                    self.word(text, &mut out);
+                    command = false;
+                }
+                TokenKind::Word(_) if redirect => {
+                    self.word(text, &mut out);
+                    redirect = false;
+                }
+                TokenKind::Word(_) if command => {
+                    if is_reserved_word(text) {
+                        paint(&mut out, &self.styles.keyword, text);
+                        // "for x", "case x" and "function f" are followed by a name
+                        command = !matches!(text, "for" | "case" | "function");
+                    } else if text
+                        .split_once('=')
+                        .is_some_and(|(name, _)| is_valid_variable_name(name))
+                    {
+                        self.word(text, &mut out);
+                    } else {
+                        self.command(text, &mut out);
+                        command = false;
+                    }
+                }
+                TokenKind::Word(_) => self.word(text, &mut out),
+            }
+            // The error can be in a part of the word, like in "$(a | | b)"
+            if let Some((from, to)) = unexpected
+                .filter(|&(from, to)| from >= start && to <= stop && !line[from..to].contains('\n'))
+            {
+                out.truncate(mark);
+                self.word(&line[start..from], &mut out);
+                paint(&mut out, &underline, &line[from..to]);
+                self.word(&line[to..stop], &mut out);
+            }
+            last = stop;
+        }
+        self.gap(&line[last..end], &mut out);
+        // The lexer stops at errors in substitutions, "$(a | | b)"
+        match unexpected.filter(|&(from, _)| from >= end) {
+            Some((from, to)) => {
+                paint(&mut out, &self.styles.error, &line[end..from]);
+                paint(&mut out, &underline, &line[from..to]);
+                paint(&mut out, &self.styles.error, &line[to..]);
+            }
+            None => paint(&mut out, &self.styles.error, &line[end..]),
+        }
+        out
+    }
+
+    // What is between tokens, spaces and comments
+    fn gap(&self, text: &str, out: &mut String) {
+        let mut rest = text;
+        while let Some(index) = rest.find('#') {
+            out.push_str(&rest[..index]);
+            let len = rest[index..].find('\n').unwrap_or(rest.len() - index);
+            paint(out, &self.styles.comment, &rest[index..index + len]);
+            rest = &rest[index + len..];
+        }
+        out.push_str(rest);
+    }
+
+    fn command(&self, name: &str, out: &mut String) {
+        // "$EDITOR" or "'ls'" can't be looked up before running them
+        if name.contains(|c| "'\"\\$`".contains(c)) {
+            return self.word(name, out);
+        }
+        // In the order the shell looks for them
+        let style = if self.aliases.contains(name) {
+            &self.styles.alias
+        } else if self.functions.contains(name) {
+            &self.styles.function
+        } else if builtins::is_builtin(name) {
+            &self.styles.builtin
+        } else if self.is_executable(name) {
+            &self.styles.command
+        } else {
+            &self.styles.not_found
+        };
+        paint(out, style, name);
+    }
+
+    fn is_executable(&self, name: &str) -> bool {
+        if let Some(&found) = self.found.borrow().get(name) {
+            return found;
+        }
+        let executable = |path: &Path| {
+            fs::metadata(path)
+                .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        };
+        let found = if name.contains('/') {
+            executable(Path::new(name))
+        } else {
+            self.path
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .any(|dir| executable(&Path::new(dir).join(name)))
+        };
+        self.found.borrow_mut().insert(name.to_string(), found);
+        found
+    }
+
+    // Quoted strings and the variables and substitutions in a word
+    fn word(&self, text: &str, out: &mut String) {
+        let mut index = 0;
+        while index < text.len() {
+            let rest = &text[index..];
+            let c = rest.chars().next().unwrap();
+            let len = match c {
+                '\\' => {
+                    let len = 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+                    out.push_str(&rest[..len]);
+                    len
+                }
+                '\'' => {
+                    let len = rest[1..].find('\'').map_or(rest.len(), |end| end + 2);
+                    paint(out, &self.styles.string, &rest[..len]);
+                    len
+                }
+                '"' => self.double_quoted(rest, out),
+                '$' | '`' => {
+                    let len = expansion_len(rest);
+                    paint(out, &self.styles.variable, &rest[..len]);
+                    len
+                }
+                c => {
+                    out.push(c);
+                    c.len_utf8()
+                }
+            };
+            index += len;
+        }
+    }
+
+    // Returns the length with the quotes
+    fn double_quoted(&self, text: &str, out: &mut String) -> usize {
+        let mut index = 1;
+        let mut start = 0;
+        while index < text.len() {
+            match text.as_bytes()[index] {
+                b'\\' => index += 2,
+                b'"' => {
+                    index += 1;
+                    break;
+                }
+                b'$' | b'`' => {
+                    paint(out, &self.styles.string, &text[start..index]);
+                    let len = expansion_len(&text[index..]);
+                    paint(out, &self.styles.variable, &text[index..index + len]);
+                    index += len;
+                    start = index;
+                }
+                _ => index += 1,
+            }
+        }
+        let index = index.min(text.len());
+        paint(out, &self.styles.string, &text[start..index]);
+        index
+    }
+}
+
+fn paint(out: &mut String, style: &str, text: &str) {
+    if style.is_empty() || text.is_empty() {
+        out.push_str(text);
+    } else {
+        out.push_str(style);
+        out.push_str(text);
+        out.push_str(RESET);
+    }
+}
+
+// Length of "$name", "${...}", "$(...)" or "`...`" at the start of the text
+fn expansion_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let closing = |open: u8, close: u8, from: usize| {
+        let mut depth = 0;
+        for (index, &byte) in bytes.iter().enumerate().skip(from) {
+            if byte == open {
+                depth += 1;
+            } else if byte == close {
+                depth -= 1;
+                if depth == 0 {
+                    return index + 1;
+                }
+            }
+        }
+        text.len()
+    };
+    match (bytes[0], bytes.get(1)) {
+        (b'`', _) => text[1..].find('`').map_or(text.len(), |end| end + 2),
+        (_, Some(b'{')) => closing(b'{', b'}', 1),
+        (_, Some(b'(')) => closing(b'(', b')', 1),
+        (_, Some(c)) if c.is_ascii_alphabetic() || *c == b'_' => {
+            1 + text[1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(text.len() - 1)
+        }
+        (_, Some(c)) if b"?#@*$!-0123456789".contains(c) => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(style("bold 208").unwrap(), "\x1b[1;38;5;208m");
+        assert_eq!(style("").unwrap(), "");
+        assert_eq!(style("pink"), None);
+
+        let mut highlighter = ShellHighlighter::new(&Colors::default());
+        highlighter.aliases.insert("ll".to_string());
+        // Show the styles as letters to read the tests, nothing is in $PATH
+        highlighter.styles = Styles {
+            command: "C".to_string(),
+            builtin: "B".to_string(),
+            function: "F".to_string(),
+            alias: "A".to_string(),
+            keyword: "K".to_string(),
+            not_found: "N".to_string(),
+            string: "S".to_string(),
+            variable: "V".to_string(),
+            operator: "O".to_string(),
+            comment: "#".to_string(),
+            error: "E".to_string(),
+        };
+        let highlight = |line: &str| highlighter.highlight(line).replace(RESET, "|");
+
+        assert_eq!(
+            highlight("cd \"$HOME/a\" && ll|nope 2>/dev/null # done"),
+            "Bcd| S\"|V$HOME|S/a\"| O&&| All|O||Nnope| O2|O>|/dev/null ## done|"
+        );
+        assert_eq!(
+            highlight("if true; then echo 'it''s' ${x:-$(pwd)}; fi"),
+            "Kif| Ntrue|O;| Kthen| Necho| S'it'|S's'| V${x:-$(pwd)}|O;| Kfi|"
+        );
+        assert_eq!(highlight("FOO=$1 cd `pwd`"), "FOO=V$1| Bcd| V`pwd`|");
+        assert_eq!(highlight("cd 'unterminated"), "Bcd| E'unterminated|");
+        assert_eq!(highlight("cd \"a $b"), "Bcd| E\"a $b|");
+        assert_eq!(highlight("ll | | fi"), "All| O|| E\x1b[4m|| Kfi|");
+        assert_eq!(highlight("ll | fi"), "All| O|| E\x1b[4mfi|");
+        assert_eq!(highlight("cd $(ll ;;)"), "Bcd| E$(ll |E\x1b[4m;;|E)|");
+    }
+}
@@ -0,0 +1,321 @@
+// The history file, shared by the shells that are open. Commands are appended
+// to it as they run with the file locked, so terminals don't write over each
+// other, and it is only rewritten when a shell starts and trims it.
+//
+// The commands are escaped like the line editor does, a newline is \n, and the
+// time a command ran is on a line before it like bash does, "#1700000000"
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rustyline::history::History;
+
+use crate::config::Duplicates;
+
+// Files written by older versions have no times and no escapes without it
+const HEADER: &str = "#V2";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    // Seconds since the epoch, None for the commands of old files
+    pub time: Option<u64>,
+    pub command: String,
+}
+
+pub struct HistoryFile {
+    pub path: String,
+    size: usize,
+    duplicates: Duplicates,
+    // How much of the file was read or written by this shell
+    offset: u64,
+}
+
+impl HistoryFile {
+    pub fn new(path: String, size: usize, duplicates: Duplicates) -> Self {
+        Self {
+            path,
+            size,
+            duplicates,
+            offset: 0,
+        }
+    }
+
+    // Reads the commands and trims the file to the size. A missing file is
+    // no history yet, a read-only one is read as it is.
+    pub fn load(&mut self) -> io::Result<Vec<Entry>> {
+        let (mut file, writable) = match open(&self.path, true) {
+            Ok(file) => (file, true),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                (open(&self.path, false)?, false)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        lock(&file, writable)?;
+        let text = read_from(&mut file, 0)?;
+        let old = !text.is_empty() && !text.starts_with(HEADER);
+        let entries = parse(&text, !old);
+        let kept = trim(&entries, self.size, self.duplicates);
+        self.offset = text.len() as u64;
+        if writable && (old || kept.len() != entries.len()) {
+            let mut text = format!("{}\n", HEADER);
+            for entry in &kept {
+                format_entry(entry, &mut text);
+            }
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(text.as_bytes())?;
+            self.offset = text.len() as u64;
+        }
+        Ok(kept)
+    }
+
+    // Writes the command at the end of the file. Returns the commands that
+    // other shells added since this one last looked.
+    pub fn append(&mut self, command: &str) -> io::Result<Vec<Entry>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        lock(&file, true)?;
+        let others = self.read_new(&mut file)?;
+        let mut text = String::new();
+        if self.offset == 0 {
+            text.push_str(HEADER);
+            text.push('\n');
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .ok();
+        format_entry(
+            &Entry {
+                time,
+                command: command.to_string(),
+            },
+            &mut text,
+        );
+        file.write_all(text.as_bytes())?;
+        self.offset += text.len() as u64;
+        Ok(others)
+    }
+
+    // The commands other shells added since the last load or append
+    pub fn added(&mut self) -> io::Result<Vec<Entry>> {
+        let mut file = open(&self.path, false)?;
+        lock(&file, false)?;
+        self.read_new(&mut file)
+    }
+
+    fn read_new(&mut self, file: &mut File) -> io::Result<Vec<Entry>> {
+        let len = file.metadata()?.len();
+        // Another shell trimmed it, what this one had is still there
+        if len < self.offset {
+            self.offset = len;
+        }
+        let text = read_from(file, self.offset)?;
+        self.offset = len;
+        Ok(parse(&text, true))
+    }
+}
+
+// Adds the command to the history of the line editor, false if it was left out,
+// like an empty line, a line that starts with a space or a duplicate
+pub fn add(history: &mut History, command: &str, duplicates: Duplicates) -> bool {
+    if duplicates == Duplicates::Erase && history.iter().any(|entry| entry == command) {
+        let rest: Vec<String> = history
+            .iter()
+            .filter(|entry| *entry != command)
+            .cloned()
+            .collect();
+        history.clear();
+        for entry in rest {
+            history.add(entry);
+        }
+    }
+    history.add(command)
+}
+
+fn open(path: &str, write: bool) -> io::Result<File> {
+    OpenOptions::new().read(true).write(write).open(path)
+}
+
+// Held until the file is closed
+fn lock(file: &File, exclusive: bool) -> io::Result<()> {
+    let operation = if exclusive {
+        libc::LOCK_EX
+    } else {
+        libc::LOCK_SH
+    };
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn read_from(file: &mut File, offset: u64) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Old files have a command on each line as it is
+fn parse(text: &str, escaped: bool) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut time = None;
+    for line in text.lines() {
+        if line == HEADER || line.is_empty() {
+            continue;
+        }
+        if !escaped {
+            entries.push(Entry {
+                time: None,
+                command: line.to_string(),
+            });
+            continue;
+        }
+        if let Some(seconds) = line.strip_prefix('#').and_then(|s| s.parse().ok()) {
+            time = Some(seconds);
+            continue;
+        }
+        entries.push(Entry {
+            time: time.take(),
+            command: unescape(line),
+        });
+    }
+    entries
+}
+
+fn format_entry(entry: &Entry, out: &mut String) {
+    if let Some(time) = entry.time {
+        out.push_str(&format!("#{}\n", time));
+    }
+    // So "#1" is not read as a time
+    if entry.command.starts_with('#') {
+        out.push('\\');
+    }
+    for c in entry.command.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\\' => out.push_str("\\\\"),
+            c => out.push(c),
+        }
+    }
+    out.push('\n');
+}
+
+fn unescape(line: &str) -> String {
+    let mut command = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some('n')) => '\n',
+            ('\\', Some('\\')) => '\\',
+            ('\\', Some('#')) => '#',
+            (c, _) => {
+                command.push(c);
+                continue;
+            }
+        };
+        chars.next();
+        command.push(escaped);
+    }
+    command
+}
+
+// The last size commands, without the duplicates that should not be there
+fn trim(entries: &[Entry], size: usize, duplicates: Duplicates) -> Vec<Entry> {
+    let mut kept: Vec<Entry> = match duplicates {
+        Duplicates::Keep => entries.to_vec(),
+        Duplicates::Ignore => {
+            let mut kept: Vec<Entry> = entries.to_vec();
+            kept.dedup_by(|entry, before| entry.command == before.command);
+            kept
+        }
+        Duplicates::Erase => {
+            let mut seen = HashSet::new();
+            let mut kept: Vec<Entry> = entries
+                .iter()
+                .rev()
+                .filter(|entry| seen.insert(entry.command.as_str()))
+                .cloned()
+                .collect();
+            kept.reverse();
+            kept
+        }
+    };
+    if kept.len() > size {
+        kept.drain(..kept.len() - size);
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(time: Option<u64>, command: &str) -> Entry {
+        Entry {
+            time,
+            command: command.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let entries = vec![
+            entry(Some(1700000000), "for x in a b\ndo echo \\$x; done"),
+            entry(None, "#1"),
+            entry(Some(1700000001), "ls"),
+        ];
+        let mut text = String::new();
+        for entry in &entries {
+            format_entry(entry, &mut text);
+        }
+        assert_eq!(
+            text,
+            "#1700000000\nfor x in a b\\ndo echo \\\\$x; done\n\\#1\n#1700000001\nls\n"
+        );
+        assert_eq!(parse(&text, true), entries);
+        assert_eq!(parse("ls\\n\n", false), vec![entry(None, "ls\\n")]);
+
+        let entries: Vec<Entry> = ["a", "b", "b", "a", "c"]
+            .iter()
+            .map(|command| entry(None, command))
+            .collect();
+        let commands = |size, duplicates| -> Vec<String> {
+            trim(&entries, size, duplicates)
+                .into_iter()
+                .map(|entry| entry.command)
+                .collect()
+        };
+        assert_eq!(commands(10, Duplicates::Keep), ["a", "b", "b", "a", "c"]);
+        assert_eq!(commands(10, Duplicates::Ignore), ["a", "b", "a", "c"]);
+        assert_eq!(commands(10, Duplicates::Erase), ["b", "a", "c"]);
+        assert_eq!(commands(2, Duplicates::Keep), ["a", "c"]);
+
+        // Two shells writing to the same file
+        let path = std::env::temp_dir().join(format!("zash-history-{}", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        std::fs::write(&path, "#V2\na\nb\na\n").unwrap();
+        let mut first = HistoryFile::new(path.clone(), 2, Duplicates::Erase);
+        let mut second = HistoryFile::new(path.clone(), 10, Duplicates::Keep);
+        let commands = |entries: Vec<Entry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.command).collect()
+        };
+        assert_eq!(commands(first.load().unwrap()), ["b", "a"]);
+        assert_eq!(commands(second.load().unwrap()), ["b", "a"]);
+        assert!(first.append("c").unwrap().is_empty());
+        assert_eq!(commands(second.append("d").unwrap()), ["c"]);
+        assert_eq!(commands(first.added().unwrap()), ["d"]);
+        assert!(first.added().unwrap().is_empty());
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(text.starts_with("#V2\nb\na\n#"), "{}", text);
+        assert_eq!(parse(&text, true).len(), 4);
+    }
+}
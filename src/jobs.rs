@@ -0,0 +1,297 @@
+// The job table, every pipeline the shell starts is a job until all of its
+// processes have finished. Background jobs stay here so "jobs", "fg" and
+// "wait" can find them.
+use crate::terminal::Modes;
+use crate::utils;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ProcessState {
+    Running,
+    // Stopped by a signal, Ctrl-Z is SIGTSTP
+    Stopped(i32),
+    // Exit status, 128 + signal number if it was killed
+    Done(i32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: i32,
+    pub state: ProcessState,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum JobState {
+    Running,
+    Stopped(i32),
+    Done(i32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: usize,
+    // The pid of the first process in the pipeline
+    pub pgid: i32,
+    pub processes: Vec<Process>,
+    // The command line, for "jobs"
+    pub command: String,
+    pub background: bool,
+    // Signal that killed the last process, for the "Done" message
+    pub signal: Option<i32>,
+    // Terminal modes when the job was stopped, given back to it by "fg"
+    pub modes: Option<Modes>,
+    // The user has been told that the job stopped
+    pub notified: bool,
+}
+
+impl Job {
+    pub fn state(&self) -> JobState {
+        if self
+            .processes
+            .iter()
+            .any(|p| p.state == ProcessState::Running)
+        {
+            return JobState::Running;
+        }
+        let stopped = self.processes.iter().find_map(|p| match p.state {
+            ProcessState::Stopped(signal) => Some(signal),
+            _ => None,
+        });
+        if let Some(signal) = stopped {
+            return JobState::Stopped(signal);
+        }
+        // The status of a pipeline is the status of the last command in it
+        match self.processes.last().map(|p| p.state) {
+            Some(ProcessState::Done(status)) => JobState::Done(status),
+            _ => JobState::Done(0),
+        }
+    }
+
+    fn state_text(&self) -> String {
+        match self.state() {
+            JobState::Running => "Running".to_string(),
+            JobState::Stopped(signal) => utils::signal_description(signal),
+            JobState::Done(0) => "Done".to_string(),
+            JobState::Done(_) if self.signal.is_some() => {
+                utils::signal_description(self.signal.unwrap())
+            }
+            JobState::Done(status) => format!("Exit {}", status),
+        }
+    }
+}
+
+// Converts a status from waitpid to an exit status and the signal that killed it
+pub fn decode_status(status: i32) -> (i32, Option<i32>) {
+    if libc::WIFEXITED(status) {
+        (libc::WEXITSTATUS(status), None)
+    } else if libc::WIFSIGNALED(status) {
+        let signal = libc::WTERMSIG(status);
+        (128 + signal, Some(signal))
+    } else {
+        (0, None)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Jobs {
+    list: Vec<Job>,
+    // Job ids from least to most recently used, the last is the current job (%+)
+    // and the one before it the previous job (%-)
+    recent: Vec<usize>,
+}
+
+impl Jobs {
+    pub fn add(&mut self, pgid: i32, pids: Vec<i32>, command: String, background: bool) -> usize {
+        let id = self.list.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.list.push(Job {
+            id,
+            pgid,
+            processes: pids
+                .into_iter()
+                .map(|pid| Process {
+                    pid,
+                    state: ProcessState::Running,
+                })
+                .collect(),
+            command,
+            background,
+            signal: None,
+            modes: None,
+            notified: false,
+        });
+        if background {
+            self.touch(id);
+        }
+        id
+    }
+
+    // Makes the job the current job
+    pub fn touch(&mut self, id: usize) {
+        self.recent.retain(|recent| *recent != id);
+        self.recent.push(id);
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.list.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.list.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        self.recent.retain(|recent| *recent != id);
+        let index = self.list.iter().position(|job| job.id == id)?;
+        Some(self.list.remove(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.list.iter().filter(|job| job.background)
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.recent.last().copied()
+    }
+
+    pub fn previous(&self) -> Option<usize> {
+        self.recent.iter().rev().nth(1).copied()
+    }
+
+    // "+" for the current job, "-" for the previous one
+    pub fn marker(&self, id: usize) -> char {
+        if self.current() == Some(id) {
+            '+'
+        } else if self.previous() == Some(id) {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    // "[1]+  Running                 sleep 10", with_pid adds the process group
+    pub fn format(&self, job: &Job, with_pid: bool) -> String {
+        let pid = if with_pid {
+            format!("{} ", job.pgid)
+        } else {
+            String::new()
+        };
+        format!(
+            "[{}]{}  {}{:<24}{}",
+            job.id,
+            self.marker(job.id),
+            pid,
+            job.state_text(),
+            job.command
+        )
+    }
+
+    // Records the new status of a process, returns false if it is not one of ours
+    pub fn update(&mut self, pid: i32, status: i32) -> bool {
+        for job in self.list.iter_mut() {
+            let is_last = job.processes.last().map(|p| p.pid) == Some(pid);
+            if let Some(process) = job.processes.iter_mut().find(|p| p.pid == pid) {
+                if libc::WIFSTOPPED(status) {
+                    process.state = ProcessState::Stopped(libc::WSTOPSIG(status));
+                    job.notified = false;
+                } else if libc::WIFCONTINUED(status) {
+                    process.state = ProcessState::Running;
+                } else {
+                    let (code, signal) = decode_status(status);
+                    process.state = ProcessState::Done(code);
+                    if is_last {
+                        job.signal = signal;
+                    }
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    // Marks the job as running again after it got SIGCONT
+    pub fn continued(&mut self, id: usize) {
+        if let Some(job) = self.get_mut(id) {
+            for process in job.processes.iter_mut() {
+                if let ProcessState::Stopped(_) = process.state {
+                    process.state = ProcessState::Running;
+                }
+            }
+        }
+    }
+
+    // Collects the status of every child that has changed, without blocking
+    pub fn reap(&mut self) {
+        loop {
+            let mut status = 0;
+            let pid = unsafe {
+                libc::waitpid(
+                    -1,
+                    &mut status,
+                    libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED,
+                )
+            };
+            if pid <= 0 {
+                break;
+            }
+            self.update(pid, status);
+        }
+    }
+
+    // Prints "[1]+  Done  sleep 1" for finished background jobs and forgets them,
+    // and "[1]+  Stopped  vim" once for jobs that were stopped
+    pub fn notify(&mut self) {
+        self.reap();
+        let changed: Vec<(usize, JobState)> = self
+            .iter()
+            .filter(|job| match job.state() {
+                JobState::Running => false,
+                JobState::Stopped(_) => !job.notified,
+                JobState::Done(_) => true,
+            })
+            .map(|job| (job.id, job.state()))
+            .collect();
+        for (id, state) in changed {
+            if let Some(job) = self.get(id) {
+                eprintln!("{}", self.format(job, false));
+            }
+            if let JobState::Done(_) = state {
+                self.remove(id);
+            } else if let Some(job) = self.get_mut(id) {
+                job.notified = true;
+            }
+        }
+    }
+
+    // Finds a job from a job spec
+    // %1 job 1, %% %+ and % the current job, %- the previous job,
+    // %ls a job whose command starts with ls, %?ls a job whose command contains ls
+    pub fn find(&self, spec: &str) -> Result<usize, String> {
+        let spec_body = spec.strip_prefix('%').unwrap_or(spec);
+        let found = match spec_body {
+            "" | "%" | "+" => self.current(),
+            "-" => self.previous(),
+            body if body.chars().all(|c| c.is_ascii_digit()) => body
+                .parse()
+                .ok()
+                .filter(|id| self.iter().any(|job| job.id == *id)),
+            body => {
+                let matches: Vec<usize> = match body.strip_prefix('?') {
+                    Some(text) => self
+                        .iter()
+                        .filter(|job| job.command.contains(text))
+                        .map(|job| job.id)
+                        .collect(),
+                    None => self
+                        .iter()
+                        .filter(|job| job.command.starts_with(body))
+                        .map(|job| job.id)
+                        .collect(),
+                };
+                if matches.len() > 1 {
+                    return Err(format!("{}: ambiguous job spec", spec));
+                }
+                matches.first().copied()
+            }
+        };
+        found.ok_or_else(|| format!("{}: no such job", spec))
+    }
+}
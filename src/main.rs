@@ -2,37 +2,72 @@
  * License: GPL-3.0
  * https://github.com/robiot/zash
  */
-use signal_hook::{consts, iterator::Signals};
 use structopt::StructOpt;
 
+mod arithmetic;
 mod builtins;
+mod completion;
+mod config;
+mod exec;
+mod expand;
+mod git;
+mod highlight;
+mod history;
+mod jobs;
 mod opts;
 mod parsers;
+mod prompt;
+mod redirect;
 mod scripting;
 mod shell;
+mod terminal;
 mod utils;
-
-fn signal_handler() {
-    Signals::new(&[consts::SIGINT]).unwrap();
-}
+mod variables;
 
 fn main() {
     let opts = opts::Opts::from_args();
 
+    if opts.init_config {
+        match scripting::init_config() {
+            Ok(path) => println!("Wrote {}", path),
+            Err(err) => {
+                utils::zash_error(err);
+                utils::exit(1);
+            }
+        }
+        utils::exit(0);
+    }
+
+    let mut shell = shell::Shell::new();
+    // zash -c 'echo $1' name one, or zash script one
+    if let Some(name) = &opts.script_file {
+        shell.arg0 = name.clone();
+    }
+    shell.positional = opts.args.clone();
+    shell.interactive = opts.is_interactive();
+    if shell.interactive {
+        shell.init_job_control();
+    }
+    scripting::load_startup_files(&mut shell, opts.is_login(), !opts.noprofile, !opts.norc);
+
     if let Some(command) = opts.command {
-        let mut shell = shell::Shell::new();
         shell.run_line(command);
-        utils::exit(0);
+        utils::exit(shell.status);
     };
 
     if let Some(script_file) = opts.script_file {
-        if let Err(err) = scripting::run_file(script_file) {
+        if let Err(err) = scripting::run_file(&mut shell, script_file) {
             utils::zash_error(err);
             utils::exit(1);
         }
-        utils::exit(0);
+        utils::exit(shell.status);
     };
 
-    signal_handler();
-    shell::shell();
+    if shell.interactive {
+        shell::shell(shell);
+    } else {
+        // "zash < script", the commands come from a pipe or file
+        scripting::run_stdin(&mut shell);
+        utils::exit(shell.status);
+    }
 }
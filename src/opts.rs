@@ -1,18 +1,54 @@
+use std::io::{self, IsTerminal};
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-#[structopt(name = "zash")]
+#[structopt(name = "zash", setting = AppSettings::TrailingVarArg)]
 pub struct Opts {
-    // These ops are just here to prevent errors with applications
+    // Interactive even when stdin is not a terminal
     #[structopt(short, long)]
     pub interactive: bool,
 
+    // Reads the profile files
     #[structopt(short, long)]
     pub login: bool,
 
+    // Don't read /etc/zash/zashrc and ~/.zashrc
+    #[structopt(long)]
+    pub norc: bool,
+
+    // Don't read /etc/zash/zprofile and ~/.zash_profile
+    #[structopt(long)]
+    pub noprofile: bool,
+
+    // Writes a template ~/.zashrc and exits
+    #[structopt(long)]
+    pub init_config: bool,
+
     #[structopt(short, long)]
     pub command: Option<String>,
 
-    #[structopt(max_values = 1)]
-    pub script_file: Option<String>
-}
\ No newline at end of file
+    pub script_file: Option<String>,
+
+    // $1, $2... for the script
+    pub args: Vec<String>,
+}
+
+impl Opts {
+    // login(1) starts shells as "-zash"
+    pub fn is_login(&self) -> bool {
+        self.login
+            || std::env::args()
+                .next()
+                .is_some_and(|arg0| arg0.starts_with('-'))
+    }
+
+    // Without a command or script the shell is interactive if it has a terminal
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+            || (self.command.is_none()
+                && self.script_file.is_none()
+                && io::stdin().is_terminal()
+                && io::stderr().is_terminal())
+    }
+}
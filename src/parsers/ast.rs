@@ -0,0 +1,551 @@
+// The syntax tree produced by parser::parse_line and executed by shell::Shell
+//
+// "a=1 ls -l | wc -l && echo ok; echo done" becomes
+// List [
+//   AndOr { Pipeline [Simple(a=1 ls -l), Simple(wc -l)], && Pipeline [Simple(echo ok)] },
+//   AndOr { Pipeline [Simple(echo done)] },
+// ]
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct List {
+    pub items: Vec<ListItem>,
+}
+
+// An and-or list ended by ";", "&" or a newline
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ListItem {
+    pub and_or: AndOr,
+    // Ended by "&", runs as a background job
+    pub background: bool,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Connector {
+    And, // &&
+    Or,  // ||
+}
+
+// "a && b || c"
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+// "! a | b | c"
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Command {
+    Simple(SimpleCommand),
+    // (( expr )), the status is 0 if the value is not 0
+    Arithmetic(Word),
+    // if, while, for, case, { } or ( ) with the redirections after it
+    Compound(CompoundCommand, Vec<Redirect>),
+    // "name() { body; }", the body is shared with the function table of the shell
+    Function { name: String, body: Rc<Command> },
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum CompoundCommand {
+    // "if a; then b; elif c; then d; else e; fi" has the branches (a, b) and (c, d)
+    If {
+        branches: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    // "while a; do b; done", until runs while the condition fails
+    While {
+        until: bool,
+        condition: List,
+        body: List,
+    },
+    // "for name in words; do body; done", without "in" the words are None
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+    // "case word in a | b) list;; esac"
+    Case {
+        word: Word,
+        items: Vec<CaseItem>,
+    },
+    // { list; }
+    BraceGroup(List),
+    // ( list ), runs in a forked shell
+    Subshell(List),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: List,
+}
+
+// "FOO=bar cmd arg > file"
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Redirect {
+    pub fd: Option<i32>,
+    pub kind: RedirectKind,
+    pub target: Word,
+    // The body of a here-document, the target is then the delimiter
+    pub here_doc: Option<Word>,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum RedirectKind {
+    Input,        // <
+    Output,       // >
+    Append,       // >>
+    Clobber,      // >|
+    ReadWrite,    // <>
+    DupInput,     // <&
+    DupOutput,    // >&
+    OutputAll,    // &>
+    AppendAll,    // &>>
+    HereDoc,      // <<
+    HereDocStrip, // <<-
+    HereString,   // <<<
+}
+
+// A single shell word, kept in parts so the expander knows what was quoted.
+// echo "$HOME"/a'*'
+// [DoubleQuoted([Variable("HOME")]), Literal("/a"), Quoted("*")]
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum WordPart {
+    // Unquoted text, subject to globbing and tilde expansion
+    Literal(String),
+    // Single quoted or escaped text, used as is
+    Quoted(String),
+    DoubleQuoted(Vec<WordPart>),
+    // $NAME, $?, ${NAME}
+    Variable(String),
+    // ${NAME:-default}, ${#NAME} etc
+    Parameter(Box<Parameter>),
+    // $(command) or `command`
+    CommandSubstitution(List),
+    // $((expr)), the expression is read like a double quoted string
+    Arithmetic(Word),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub op: ParameterOp,
+}
+
+// colon is true for the forms with ":", where an empty value counts as unset
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ParameterOp {
+    Length, // ${#name}
+    Default {
+        colon: bool,
+        word: Word,
+    }, // ${name:-word}
+    Assign {
+        colon: bool,
+        word: Word,
+    }, // ${name:=word}
+    Error {
+        colon: bool,
+        word: Word,
+    }, // ${name:?word}
+    Alternative {
+        colon: bool,
+        word: Word,
+    }, // ${name:+word}
+    RemovePrefix {
+        longest: bool,
+        pattern: Word,
+    }, // ${name#pattern} ${name##pattern}
+    RemoveSuffix {
+        longest: bool,
+        pattern: Word,
+    }, // ${name%pattern} ${name%%pattern}
+    // ${name/pattern/replacement}
+    Replace {
+        mode: ReplaceMode,
+        pattern: Word,
+        replacement: Word,
+    },
+    // ${name:offset:length}
+    Substring {
+        offset: Word,
+        length: Option<Word>,
+    },
+    // ${name^^} ${name^} ${name,,} ${name,}, only the chars matching the pattern are changed
+    Case {
+        upper: bool,
+        all: bool,
+        pattern: Word,
+    },
+    // Not a valid expansion, it is an error when it gets expanded. Holds the text between the braces
+    Bad(String),
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ReplaceMode {
+    First,  // ${name/pattern/replacement}
+    All,    // ${name//pattern/replacement}
+    Prefix, // ${name/#pattern/replacement}
+    Suffix, // ${name/%pattern/replacement}
+}
+
+impl Word {
+    // Returns the text of the word if it has no quotes or expansions in it
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(text)] => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl RedirectKind {
+    // The fd that is redirected when none is given, "> file" is "1> file"
+    pub fn default_fd(&self) -> i32 {
+        match self {
+            RedirectKind::Input
+            | RedirectKind::ReadWrite
+            | RedirectKind::DupInput
+            | RedirectKind::HereDoc
+            | RedirectKind::HereDocStrip
+            | RedirectKind::HereString => 0,
+            _ => 1,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RedirectKind::Input => "<",
+            RedirectKind::Output => ">",
+            RedirectKind::Append => ">>",
+            RedirectKind::Clobber => ">|",
+            RedirectKind::ReadWrite => "<>",
+            RedirectKind::DupInput => "<&",
+            RedirectKind::DupOutput => ">&",
+            RedirectKind::OutputAll => "&>",
+            RedirectKind::AppendAll => "&>>",
+            RedirectKind::HereDoc => "<<",
+            RedirectKind::HereDocStrip => "<<-",
+            RedirectKind::HereString => "<<<",
+        }
+    }
+}
+
+// The Display impls print the tree back as shell source, used for the
+// job table and error messages.
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 && !self.items[i - 1].background {
+                write!(f, ";")?;
+            }
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ListItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.and_or)?;
+        if self.background {
+            write!(f, " &")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AndOr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            match connector {
+                Connector::And => write!(f, " && {}", pipeline)?,
+                Connector::Or => write!(f, " || {}", pipeline)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negated {
+            write!(f, "! ")?;
+        }
+        for (i, command) in self.commands.iter().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", command)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Simple(simple) => write!(f, "{}", simple),
+            Command::Arithmetic(expr) => write!(f, "(({}))", Expression(expr)),
+            Command::Compound(compound, redirects) => {
+                write!(f, "{}", compound)?;
+                for redirect in redirects {
+                    write!(f, " {}", redirect)?;
+                }
+                Ok(())
+            }
+            Command::Function { name, body } => write!(f, "{}() {}", name, body),
+        }
+    }
+}
+
+// A list followed by a keyword, "a;" but "a &" without the ;
+struct Terminated<'a>(&'a List);
+
+impl fmt::Display for Terminated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+        match self.0.items.last() {
+            Some(item) if item.background => Ok(()),
+            _ => write!(f, ";"),
+        }
+    }
+}
+
+impl fmt::Display for CompoundCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompoundCommand::If {
+                branches,
+                otherwise,
+            } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { "elif" };
+                    write!(
+                        f,
+                        "{} {} then {} ",
+                        keyword,
+                        Terminated(condition),
+                        Terminated(body)
+                    )?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, "else {} ", Terminated(otherwise))?;
+                }
+                write!(f, "fi")
+            }
+            CompoundCommand::While {
+                until,
+                condition,
+                body,
+            } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(
+                    f,
+                    "{} {} do {} done",
+                    keyword,
+                    Terminated(condition),
+                    Terminated(body)
+                )
+            }
+            CompoundCommand::For { name, words, body } => {
+                write!(f, "for {}", name)?;
+                if let Some(words) = words {
+                    write!(f, " in")?;
+                    for word in words {
+                        write!(f, " {}", word)?;
+                    }
+                }
+                write!(f, "; do {} done", Terminated(body))
+            }
+            CompoundCommand::Case { word, items } => {
+                write!(f, "case {} in", word)?;
+                for item in items {
+                    let patterns: Vec<String> =
+                        item.patterns.iter().map(|word| word.to_string()).collect();
+                    write!(f, " {})", patterns.join(" | "))?;
+                    if !item.body.items.is_empty() {
+                        write!(f, " {}", item.body)?;
+                    }
+                    write!(f, " ;;")?;
+                }
+                write!(f, " esac")
+            }
+            CompoundCommand::BraceGroup(list) => write!(f, "{{ {} }}", Terminated(list)),
+            CompoundCommand::Subshell(list) => write!(f, "({})", list),
+        }
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        for assignment in &self.assignments {
+            parts.push(format!("{}={}", assignment.name, assignment.value));
+        }
+        for word in &self.words {
+            parts.push(word.to_string());
+        }
+        for redirect in &self.redirects {
+            parts.push(redirect.to_string());
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(fd) = self.fd {
+            write!(f, "{}", fd)?;
+        }
+        write!(f, "{}{}", self.kind.as_str(), self.target)
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.parts {
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for WordPart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WordPart::Literal(text) => write!(f, "{}", text),
+            WordPart::Quoted(text) => write!(f, "'{}'", text.replace('\'', r"'\''")),
+            WordPart::DoubleQuoted(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        WordPart::Quoted(text) => {
+                            for c in text.chars() {
+                                if matches!(c, '"' | '\\' | '$' | '`') {
+                                    write!(f, "\\")?;
+                                }
+                                write!(f, "{}", c)?;
+                            }
+                        }
+                        part => write!(f, "{}", part)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            WordPart::Variable(name) => write!(f, "${{{}}}", name),
+            WordPart::Parameter(parameter) => write!(f, "{}", parameter),
+            WordPart::CommandSubstitution(list) => write!(f, "$({})", list),
+            WordPart::Arithmetic(expr) => write!(f, "$(({}))", Expression(expr)),
+        }
+    }
+}
+
+// The text of an arithmetic expression is printed without quotes
+struct Expression<'a>(&'a Word);
+
+impl fmt::Display for Expression<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.0.parts {
+            match part {
+                WordPart::Quoted(text) => write!(f, "{}", text)?,
+                part => write!(f, "{}", part)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let colon = |colon: &bool| if *colon { ":" } else { "" };
+        let name = &self.name;
+        match &self.op {
+            ParameterOp::Length => write!(f, "${{#{}}}", name),
+            ParameterOp::Default { colon: c, word } => {
+                write!(f, "${{{}{}-{}}}", name, colon(c), word)
+            }
+            ParameterOp::Assign { colon: c, word } => {
+                write!(f, "${{{}{}={}}}", name, colon(c), word)
+            }
+            ParameterOp::Error { colon: c, word } => {
+                write!(f, "${{{}{}?{}}}", name, colon(c), word)
+            }
+            ParameterOp::Alternative { colon: c, word } => {
+                write!(f, "${{{}{}+{}}}", name, colon(c), word)
+            }
+            ParameterOp::RemovePrefix { longest, pattern } => {
+                let op = if *longest { "##" } else { "#" };
+                write!(f, "${{{}{}{}}}", name, op, pattern)
+            }
+            ParameterOp::RemoveSuffix { longest, pattern } => {
+                let op = if *longest { "%%" } else { "%" };
+                write!(f, "${{{}{}{}}}", name, op, pattern)
+            }
+            ParameterOp::Replace {
+                mode,
+                pattern,
+                replacement,
+            } => {
+                let op = match mode {
+                    ReplaceMode::First => "/",
+                    ReplaceMode::All => "//",
+                    ReplaceMode::Prefix => "/#",
+                    ReplaceMode::Suffix => "/%",
+                };
+                write!(f, "${{{}{}{}/{}}}", name, op, pattern, replacement)
+            }
+            ParameterOp::Substring { offset, length } => {
+                write!(f, "${{{}:{}", name, offset)?;
+                if let Some(length) = length {
+                    write!(f, ":{}", length)?;
+                }
+                write!(f, "}}")
+            }
+            ParameterOp::Case {
+                upper,
+                all,
+                pattern,
+            } => {
+                let op = match (upper, all) {
+                    (true, true) => "^^",
+                    (true, false) => "^",
+                    (false, true) => ",,",
+                    (false, false) => ",",
+                };
+                write!(f, "${{{}{}{}}}", name, op, pattern)
+            }
+            ParameterOp::Bad(text) => write!(f, "${{{}}}", text),
+        }
+    }
+}
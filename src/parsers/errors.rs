@@ -1,10 +1,174 @@
+use unicode_width::UnicodeWidthStr;
+
+use super::tokens::Span;
+
 pub type Result<T> = std::result::Result<T, SyntaxError>;
 
-#[derive(Debug, Clone)]
-pub struct SyntaxError;
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SyntaxError {
+    // The input ended in the middle of a command, ex "echo 'hi" or "ls |"
+    // Reading more lines can complete it. expected is what would close it, like
+    // "`fi`", opened where the part that is not closed starts, like the "if"
+    Incomplete {
+        expected: String,
+        opened: Option<Span>,
+    },
+    // A token that can't be there, ex the second "|" of "a | | b".
+    // found is what it is, like "`|`" or "newline", expected what could have been there
+    Unexpected {
+        span: Span,
+        found: String,
+        expected: Option<String>,
+    },
+}
+
+impl SyntaxError {
+    pub fn incomplete(expected: impl Into<String>) -> Self {
+        SyntaxError::Incomplete {
+            expected: expected.into(),
+            opened: None,
+        }
+    }
+
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, SyntaxError::Incomplete { .. })
+    }
+
+    // Sets where the unfinished part starts, unless a part inside it was not
+    // finished either, in "if a; then while b" it is the "while"
+    pub fn opened_at(self, span: Span) -> Self {
+        match self {
+            SyntaxError::Incomplete {
+                expected,
+                opened: None,
+            } => SyntaxError::Incomplete {
+                expected,
+                opened: Some(span),
+            },
+            err => err,
+        }
+    }
+
+    // Where the error is in the input, the end of it for an Incomplete
+    // that doesn't know where it was opened
+    pub fn span(&self, input: &str) -> Span {
+        let (start, end) = match self {
+            SyntaxError::Incomplete { opened: None, .. } => {
+                let end = input.trim_end_matches('\n').len();
+                (end, end)
+            }
+            SyntaxError::Incomplete {
+                opened: Some(span), ..
+            } => *span,
+            SyntaxError::Unexpected { span, .. } => *span,
+        };
+        let start = start.min(input.len());
+        (start, end.clamp(start, input.len()))
+    }
+
+    // For errors in a part of the input, like the command of a substitution
+    pub fn shifted(self, offset: usize) -> Self {
+        match self {
+            SyntaxError::Unexpected {
+                span: (start, end),
+                found,
+                expected,
+            } => SyntaxError::Unexpected {
+                span: (start + offset, end + offset),
+                found,
+                expected,
+            },
+            SyntaxError::Incomplete {
+                expected,
+                opened: Some((start, end)),
+            } => SyntaxError::Incomplete {
+                expected,
+                opened: Some((start + offset, end + offset)),
+            },
+            err => err,
+        }
+    }
+
+    // For errors in text that is not in the input as it is, like an alias or
+    // the body of a here-document, they are shown at the span it came from
+    pub fn moved_to(self, span: Span) -> Self {
+        match self {
+            SyntaxError::Unexpected {
+                found, expected, ..
+            } => SyntaxError::Unexpected {
+                span,
+                found,
+                expected,
+            },
+            SyntaxError::Incomplete { expected, .. } => SyntaxError::Incomplete {
+                expected,
+                opened: Some(span),
+            },
+        }
+    }
+
+    // The line number of the error in the input, from 1
+    pub fn line(&self, input: &str) -> usize {
+        let (start, _) = self.span(input);
+        input.as_bytes()[..start]
+            .iter()
+            .filter(|&&byte| byte == b'\n')
+            .count()
+            + 1
+    }
+
+    // The message, the line of the input with the error and carets under it
+    //   syntax error: unexpected `|`, expected a command
+    //   a | | b
+    //       ^
+    pub fn show(&self, input: &str) -> String {
+        let (start, end) = self.span(input);
+        let line_start = input.as_bytes()[..start]
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |i| i + 1);
+        let line_end = input.as_bytes()[start..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(input.len(), |i| start + i);
+        let text = String::from_utf8_lossy(&input.as_bytes()[line_start..line_end]);
+        let column = |from: usize, to: usize| {
+            String::from_utf8_lossy(&input.as_bytes()[from..to]).into_owned()
+        };
+        // Tabs are kept so the carets line up, wide chars take two columns
+        let mut carets = String::new();
+        for c in column(line_start, start).chars() {
+            match c {
+                '\t' => carets.push('\t'),
+                c => carets.push_str(&" ".repeat(c.to_string().width())),
+            }
+        }
+        let width = column(start, end.min(line_end)).width();
+        carets.push_str(&"^".repeat(width.max(1)));
+        format!("{}\n{}\n{}", self, text, carets)
+    }
+}
 
 impl std::fmt::Display for SyntaxError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "SyntaxError: Unexpected end of input")
+        match self {
+            SyntaxError::Incomplete { expected, .. } => write!(
+                f,
+                "syntax error: unexpected end of input, expected {}",
+                expected
+            ),
+            SyntaxError::Unexpected {
+                found,
+                expected: Some(expected),
+                ..
+            } => write!(
+                f,
+                "syntax error: unexpected {}, expected {}",
+                found, expected
+            ),
+            SyntaxError::Unexpected { found, .. } => {
+                write!(f, "syntax error: unexpected {}", found)
+            }
+        }
     }
-}
\ No newline at end of file
+}
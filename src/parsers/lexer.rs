@@ -1,302 +1,1069 @@
-// Not really a lexer lexer but it tokenizes the input.
+// Turns the input into words and operators for the parser.
+// Words keep track of which parts were quoted so expansion can be done later.
+use super::ast::{Parameter, ParameterOp, ReplaceMode, Word, WordPart};
 use super::errors::*;
-use super::tokens;
-
-// Line to commands
-#[derive(Clone, Copy, Eq, PartialEq, Debug)]
-enum LineTCmdState {
-    Normal,
-    Escaped,
-    SingleQuoted,
-    DoubleQuoted,
-    DoubleQuotedEscaped,
-    WaitingSeparator,
+use super::parser::parse_line;
+use super::tokens::*;
+
+// Special parameters that are a single character, $? $$ etc
+fn is_special_param(c: char) -> bool {
+    matches!(c, '?' | '$' | '!' | '#' | '@' | '*' | '-') || c.is_ascii_digit()
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+pub fn is_valid_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if is_name_start(c) => chars.all(is_name_char),
+        _ => false,
+    }
+}
+
+// A here-document whose body has not been read yet, the body starts on the next line
+struct PendingHereDoc {
+    delimiter: String,
+    strip_tabs: bool,
+    quoted: bool,
+    // Of the delimiter after <<
+    span: Span,
+}
+
+impl PendingHereDoc {
+    fn unfinished(&self) -> SyntaxError {
+        SyntaxError::incomplete(format!("`{}`", self.delimiter)).opened_at(self.span)
+    }
 }
 
-// Inspired by github.com/mitnk/cicada/blob/master/src/parsers/parser_line.rs
-// Splits input into multiple commands
-// line_to_cmds("echo hello; echo goodbye")
-// [(Command, "echo hello"), (Separator, ";"), (Command, "echo goodbye")]
-pub fn line_to_cmds(line: &str) -> Vec<(tokens::LineToCmdTokens, std::string::String)> {
-    use tokens::LineToCmdTokens::*;
-    use LineTCmdState::*;
-    let mut result = Vec::new();
-    let mut token = String::new();
-    let mut sep_before: char = '\0';
-    let mut state: LineTCmdState = Normal;
-    for (_, c) in line.chars().enumerate() {
-        state = match (state, c) {
-            (Normal, '#') => break,
-            (Normal, '\\') => {
-                token.push(c);
-                Escaped
+// The delimiter of a here-document is used as written without expansions.
+// Returns true if any part of it was quoted.
+fn delimiter_text(parts: &[WordPart], delimiter: &mut String) -> bool {
+    let mut quoted = false;
+    for part in parts {
+        match part {
+            WordPart::Literal(text) => delimiter.push_str(text),
+            WordPart::Quoted(text) => {
+                quoted = true;
+                delimiter.push_str(text);
             }
-            (Normal, '\'') => {
-                token.push(c);
-                SingleQuoted
+            WordPart::DoubleQuoted(parts) => {
+                quoted = true;
+                delimiter_text(parts, delimiter);
+            }
+            WordPart::Variable(name) => {
+                delimiter.push('$');
+                delimiter.push_str(name);
+            }
+            part => delimiter.push_str(&part.to_string()),
+        }
+    }
+    quoted
+}
+
+struct Lexer {
+    chars: Vec<(usize, char)>,
+    pos: usize,
+    len: usize,
+    pending_here_docs: Vec<PendingHereDoc>,
+    // Bodies of the here-documents in the order they appear
+    here_docs: Vec<Word>,
+    // Lexing the expression of $((...)), where double quotes are removed
+    arithmetic: bool,
+}
+
+impl Lexer {
+    fn new(input: &str) -> Self {
+        Self {
+            chars: input.char_indices().collect(),
+            pos: 0,
+            len: input.len(),
+            pending_here_docs: Vec::new(),
+            here_docs: Vec::new(),
+            arithmetic: false,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|(_, c)| *c)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).map(|(_, c)| *c)
+    }
+
+    // Byte offset of the current char
+    fn offset(&self) -> usize {
+        self.chars.get(self.pos).map_or(self.len, |(i, _)| *i)
+    }
+
+    // The char that was just consumed
+    fn previous(&self) -> Option<char> {
+        self.previous_at(0)
+    }
+
+    fn previous_at(&self, offset: usize) -> Option<char> {
+        self.chars
+            .get(self.pos.checked_sub(offset + 1)?)
+            .map(|(_, c)| *c)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    // The byte span of len chars from the char at index start
+    fn span_of(&self, start: usize, len: usize) -> Span {
+        let end = self.chars.get(start + len).map_or(self.len, |(i, _)| *i);
+        (self.chars[start].0, end)
+    }
+
+    // The chars from index start to end
+    fn text(&self, start: usize, end: usize) -> String {
+        self.chars[start..end].iter().map(|(_, c)| c).collect()
+    }
+
+    // Backslash newline is removed everywhere except in single quotes
+    fn skip_line_continuation(&mut self) -> bool {
+        if self.peek() == Some('\\') && self.peek_at(1) == Some('\n') {
+            self.pos += 2;
+            return true;
+        }
+        false
+    }
+
+    // The tokens go in result as they are read, so the ones before an error are kept
+    fn tokens(&mut self, result: &mut Vec<Token>) -> Result<()> {
+        loop {
+            if self.skip_line_continuation() {
+                continue;
             }
-            (Normal, '"') => {
-                token.push(c);
-                DoubleQuoted
+            if matches!(self.peek(), Some(' ') | Some('\t')) {
+                self.pos += 1;
+                continue;
             }
-            (Normal, ';') => {
-                if !token.is_empty() {
-                    result.push((Command, token.trim().to_string()));
+            let start = self.offset();
+            let c = match self.peek() {
+                Some(c) => c,
+                None => break,
+            };
+            let kind = match c {
+                '#' => {
+                    while !matches!(self.peek(), Some('\n') | None) {
+                        self.pos += 1;
+                    }
+                    continue;
+                }
+                '\n' => {
+                    self.pos += 1;
+                    self.read_here_docs()?;
+                    TokenKind::Newline
+                }
+                '(' if self.peek_at(1) == Some('(') => match self.arithmetic()? {
+                    Some(expr) => TokenKind::Arithmetic(expr),
+                    // "((a) | b)", a subshell in a subshell
+                    None => {
+                        self.pos += 1;
+                        TokenKind::Operator(Operator::LParen)
+                    }
+                },
+                _ => match self.operator() {
+                    Some(op) if matches!(op, Operator::DLess | Operator::DLessDash) => {
+                        result.push(Token {
+                            kind: TokenKind::Operator(op),
+                            span: (start, self.offset()),
+                        });
+                        self.here_doc_delimiter(op == Operator::DLessDash, result)?;
+                        continue;
+                    }
+                    Some(op) => TokenKind::Operator(op),
+                    None => match self.io_number() {
+                        Some(fd) => TokenKind::IoNumber(fd),
+                        None => TokenKind::Word(self.word()?),
+                    },
+                },
+            };
+            result.push(Token {
+                kind,
+                span: (start, self.offset()),
+            });
+        }
+        if let Some(here_doc) = self.pending_here_docs.first() {
+            return Err(here_doc.unfinished());
+        }
+        Ok(())
+    }
+
+    // Reads the word after << and remembers it until the end of the line
+    fn here_doc_delimiter(&mut self, strip_tabs: bool, result: &mut Vec<Token>) -> Result<()> {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.pos += 1;
+        }
+        let start = self.offset();
+        if self.at_word_end() {
+            // Ex "cat <<" the parser reports it
+            return Ok(());
+        }
+        let word = self.word()?;
+        let mut delimiter = String::new();
+        let quoted = delimiter_text(&word.parts, &mut delimiter);
+        self.pending_here_docs.push(PendingHereDoc {
+            delimiter,
+            strip_tabs,
+            quoted,
+            span: (start, self.offset()),
+        });
+        result.push(Token {
+            kind: TokenKind::Word(word),
+            span: (start, self.offset()),
+        });
+        Ok(())
+    }
+
+    // Called after a newline, reads the bodies of the here-documents on the line before
+    fn read_here_docs(&mut self) -> Result<()> {
+        for here_doc in std::mem::take(&mut self.pending_here_docs) {
+            let mut body = String::new();
+            loop {
+                if self.peek().is_none() {
+                    return Err(here_doc.unfinished());
+                }
+                let mut line = String::new();
+                while let Some(c) = self.bump() {
+                    if c == '\n' {
+                        break;
+                    }
+                    line.push(c);
+                }
+                if here_doc.strip_tabs {
+                    line = line.trim_start_matches('\t').to_string();
                 }
-                result.push((Separator, c.to_string()));
-                token.clear();
-                Normal
+                if line == here_doc.delimiter {
+                    break;
+                }
+                body.push_str(&line);
+                body.push('\n');
+            }
+            let parts = if here_doc.quoted {
+                vec![WordPart::Quoted(body)]
+            } else {
+                vec![WordPart::DoubleQuoted(
+                    Lexer::new(&body)
+                        .double_quoted(true)
+                        .map_err(|err| err.moved_to(here_doc.span))?,
+                )]
+            };
+            self.here_docs.push(Word { parts });
+        }
+        Ok(())
+    }
+
+    fn operator(&mut self) -> Option<Operator> {
+        use Operator::*;
+        let (op, len) = match (self.peek()?, self.peek_at(1), self.peek_at(2)) {
+            ('<', Some('<'), Some('<')) => (TLess, 3),
+            ('<', Some('<'), Some('-')) => (DLessDash, 3),
+            ('<', Some('<'), _) => (DLess, 2),
+            ('&', Some('&'), _) => (AndIf, 2),
+            ('&', Some('>'), Some('>')) => (AndDGreat, 3),
+            ('&', Some('>'), _) => (AndGreat, 2),
+            ('&', _, _) => (Amp, 1),
+            ('|', Some('|'), _) => (OrIf, 2),
+            ('|', _, _) => (Pipe, 1),
+            (';', Some(';'), _) => (DSemi, 2),
+            (';', _, _) => (Semi, 1),
+            ('>', Some('>'), _) => (DGreat, 2),
+            ('>', Some('&'), _) => (GreatAnd, 2),
+            ('>', Some('|'), _) => (Clobber, 2),
+            ('>', _, _) => (Great, 1),
+            ('<', Some('&'), _) => (LessAnd, 2),
+            ('<', Some('>'), _) => (LessGreat, 2),
+            ('<', _, _) => (Less, 1),
+            ('(', _, _) => (LParen, 1),
+            (')', _, _) => (RParen, 1),
+            _ => return None,
+        };
+        self.pos += len;
+        Some(op)
+    }
+
+    // Digits directly followed by a redirection, the 2 in "2>file"
+    fn io_number(&mut self) -> Option<i32> {
+        let mut len = 0;
+        while self.peek_at(len).filter(|c| c.is_ascii_digit()).is_some() {
+            len += 1;
+        }
+        if len == 0 || !matches!(self.peek_at(len), Some('<') | Some('>')) {
+            return None;
+        }
+        let digits: String = (0..len).filter_map(|i| self.peek_at(i)).collect();
+        let fd = digits.parse().ok()?;
+        self.pos += len;
+        Some(fd)
+    }
+
+    fn at_word_end(&self) -> bool {
+        match self.peek() {
+            None => true,
+            Some(c) => matches!(
+                c,
+                ' ' | '\t' | '\n' | ';' | '&' | '|' | '<' | '>' | '(' | ')'
+            ),
+        }
+    }
+
+    fn word(&mut self) -> Result<Word> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        loop {
+            if self.skip_line_continuation() {
+                continue;
             }
-            (Normal, _) if c == '&' || c == '|' => {
-                sep_before = c;
-                token.push(c); // If its eoi
-                WaitingSeparator
+            if self.at_word_end() {
+                break;
             }
-            (WaitingSeparator, _) => {
-                if sep_before == c {
-                    token.pop();
-                    if !token.is_empty() {
-                        result.push((Command, token.trim().to_string()));
+            let start = self.pos;
+            let c = self.bump().unwrap();
+            let part = match c {
+                '\\' => match self.bump() {
+                    Some(escaped) => WordPart::Quoted(escaped.to_string()),
+                    None => {
+                        return Err(SyntaxError::incomplete("another line")
+                            .opened_at(self.span_of(start, 1)))
                     }
-                    result.push((Separator, format!("{}{}", c.to_string(), c.to_string())));
-                    token.clear();
-                } else {
-                    token.push(c);
+                },
+                '\'' => WordPart::Quoted(self.single_quoted()?),
+                '"' => WordPart::DoubleQuoted(self.double_quoted(false)?),
+                '`' => self.backquoted()?,
+                '$' => match self.dollar(false)? {
+                    Some(part) => part,
+                    None => {
+                        literal.push('$');
+                        continue;
+                    }
+                },
+                c => {
+                    literal.push(c);
+                    continue;
                 }
-                Normal
+            };
+            if !literal.is_empty() {
+                parts.push(WordPart::Literal(std::mem::take(&mut literal)));
             }
-            (Normal, _) | (Escaped, _) => {
-                token.push(c);
-                Normal
+            parts.push(part);
+        }
+        if !literal.is_empty() {
+            parts.push(WordPart::Literal(literal));
+        }
+        Ok(Word { parts })
+    }
+
+    // Called after the quote
+    fn single_quoted(&mut self) -> Result<String> {
+        let start = self.pos - 1;
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(text),
+                Some(c) => text.push(c),
+                None => {
+                    return Err(
+                        SyntaxError::incomplete("a closing `'`").opened_at(self.span_of(start, 1))
+                    )
+                }
             }
-            (SingleQuoted, '\'') => {
-                token.push(c);
-                Normal
+        }
+    }
+
+    // The body of a here-document is read like a double quoted string,
+    // except that it ends at the end of the input and " has no meaning
+    fn double_quoted(&mut self, here_doc: bool) -> Result<Vec<WordPart>> {
+        let start = self.pos.saturating_sub(1);
+        let unclosed = |lexer: &Self| {
+            SyntaxError::incomplete("a closing `\"`").opened_at(lexer.span_of(start, 1))
+        };
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        loop {
+            if self.skip_line_continuation() {
+                continue;
             }
-            (SingleQuoted, _) => {
-                token.push(c);
-                SingleQuoted
+            let part = match self.bump() {
+                Some('"') if !here_doc => break,
+                // $(( "4" + 1 )) is 5
+                Some('"') if self.arithmetic => WordPart::DoubleQuoted(self.double_quoted(false)?),
+                None if here_doc => break,
+                Some('\\') => {
+                    match self.bump() {
+                        Some(c) if matches!(c, '\\' | '$' | '`') || (c == '"' && !here_doc) => {
+                            text.push(c)
+                        }
+                        Some(c) => {
+                            text.push('\\');
+                            text.push(c);
+                        }
+                        None => return Err(unclosed(self)),
+                    }
+                    continue;
+                }
+                Some('`') => self.backquoted()?,
+                Some('$') => match self.dollar(true)? {
+                    Some(part) => part,
+                    None => {
+                        text.push('$');
+                        continue;
+                    }
+                },
+                Some(c) => {
+                    text.push(c);
+                    continue;
+                }
+                None => return Err(unclosed(self)),
+            };
+            if !text.is_empty() {
+                parts.push(WordPart::Quoted(std::mem::take(&mut text)));
             }
-            (DoubleQuoted, '"') => {
-                token.push(c);
-                Normal
+            parts.push(part);
+        }
+        if !text.is_empty() {
+            parts.push(WordPart::Quoted(text));
+        }
+        Ok(parts)
+    }
+
+    // Called after a $, returns None if it is just a dollar sign.
+    // quoted is true inside double quotes.
+    fn dollar(&mut self, quoted: bool) -> Result<Option<WordPart>> {
+        let part = match self.peek() {
+            Some('{') => {
+                let start = self.pos - 1;
+                self.pos += 1;
+                self.parameter(quoted)
+                    .map_err(|err| err.opened_at(self.span_of(start, 2)))?
             }
-            (DoubleQuoted, '\\') => DoubleQuotedEscaped,
-            (DoubleQuoted, _) | (DoubleQuotedEscaped, '"') | (DoubleQuotedEscaped, '\\') => {
-                token.push(c);
-                DoubleQuoted
+            Some('(') if self.peek_at(1) == Some('(') => match self.arithmetic()? {
+                Some(expr) => WordPart::Arithmetic(expr),
+                // $( (a) ), a subshell in a command substitution
+                None => {
+                    self.pos += 1;
+                    self.command_substitution()?
+                }
+            },
+            Some('(') => {
+                self.pos += 1;
+                self.command_substitution()?
             }
-            (DoubleQuotedEscaped, _) => {
-                token.push('\\');
-                token.push(c);
-                DoubleQuoted
+            Some(c) if is_name_start(c) => WordPart::Variable(self.name()),
+            Some(c) if is_special_param(c) => {
+                self.pos += 1;
+                WordPart::Variable(c.to_string())
             }
+            _ => return Ok(None),
         };
+        Ok(Some(part))
     }
-    if !token.is_empty() {
-        result.push((Command, token.trim().to_string()));
-    }
-    result
-}
 
-#[derive(Clone, Copy, Eq, PartialEq, Debug)]
-enum CmdTTokenState {
-    Normal,
-    Escaped,
-    SingleQuoted,
-    DoubleQuoted,
-    DoubleQuotedEscaped,
-    DollarVariable,
-}
-
-fn valid_name_check(c: char) -> bool {
-    (c.is_alphanumeric() || c == '_' || c == '?') && c != '$'
-}
-
-pub fn is_valid_variable_name(name: String) -> bool {
-    for char in name.chars() {
-        if !valid_name_check(char) {
-            return false;
+    // Called after $(, finds the closing paren and parses what is in between
+    fn command_substitution(&mut self) -> Result<WordPart> {
+        let start = self.pos;
+        loop {
+            let c = match self.bump() {
+                Some(c) => c,
+                // At the $ of $(
+                None => {
+                    return Err(SyntaxError::incomplete("`)`").opened_at(self.span_of(start - 2, 2)))
+                }
+            };
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '\'' => {
+                    self.single_quoted()?;
+                }
+                '"' => {
+                    self.double_quoted(false)?;
+                }
+                '`' => {
+                    self.backquoted()?;
+                }
+                '$' => {
+                    self.dollar(false)?;
+                }
+                // A comment could have a ) in it
+                '#' if matches!(self.previous_at(1), Some(' ') | Some('\t') | Some('\n')) => {
+                    while !matches!(self.peek(), Some('\n') | None) {
+                        self.pos += 1;
+                    }
+                }
+                // It is the end if the command can't go on with it. The ) of
+                // "(ls)" or of the pattern in "case x in a) ..." is part of it
+                ')' => {
+                    let text = self.text(start, self.pos);
+                    if parse_line(&text).is_err_and(|err| !err.is_incomplete()) {
+                        break;
+                    }
+                }
+                _ => {}
+            }
         }
+        let text = self.text(start, self.pos - 1);
+        self.substitution(&text, start, true)
     }
-    true
-}
 
-type CmdToTokensReturn = (tokens::CmdTokens, std::string::String, bool);
-
-pub fn cmd_to_tokens(line: &str) -> Result<Vec<CmdToTokensReturn>> {
-    use tokens::CmdTokens;
-    use CmdTTokenState::*;
-    let mut result: Vec<CmdToTokensReturn> = Vec::new();
-    let mut token = String::new();
-    let mut state: CmdTTokenState = Normal;
-    let mut is_definition: bool = false;
-    for (_, c) in line.chars().enumerate() {
-        state = match (state, c) {
-            (Normal, '\\') | (DollarVariable, '\\') => Escaped,
-            (Normal, '\'') | (DollarVariable, '\'') => SingleQuoted,
-            (Normal, '"') | (DollarVariable, '"') => DoubleQuoted,
-            (Normal, c) if c == '>' || c == '<' || c == '|' => {
-                if !token.is_empty() {
-                    result.push((CmdTokens::Normal, token.trim().to_string(), false));
+    // Called at (( of $((expr)) or ((expr)), reads up to the matching )). Returns None without moving if the
+    // parens are closed some other way, like "((a) (b))"
+    fn arithmetic(&mut self) -> Result<Option<Word>> {
+        let start = self.pos;
+        self.pos += 2;
+        let mut depth = 0;
+        loop {
+            match self.bump() {
+                Some('(') => depth += 1,
+                Some(')') if depth > 0 => depth -= 1,
+                Some(')') if self.peek() == Some(')') => break,
+                Some(')') => {
+                    self.pos = start;
+                    return Ok(None);
                 }
-                result.push((CmdTokens::Pipe, c.to_string(), false));
-                token.clear();
-                Normal
-            }
-            (Normal, '=') => {
-                // The value stored in token, should now be the variable name
-                if is_valid_variable_name(token.clone()) {
-                    // ex TEST? is not valid because of the question mark
-                    is_definition = true;
-                    token.push(c);
+                Some(_) => {}
+                None => {
+                    return Err(SyntaxError::incomplete("`))`").opened_at(self.span_of(start, 2)))
                 }
-                Normal
             }
-            // Todo: Variables should be supported everywhere
-            // echo ${PATH}a
-            // echo $USER$PWD
-            // (Normal, "echo", false)
-            // (Variable, "USER", true)
-            // (Normal, "$PWD", false)
-            (Normal, ' ') | (Normal, '$') => {
-                if !token.is_empty() {
-                    let token_type: tokens::CmdTokens;
-                    if is_definition {
-                        is_definition = false;
-                        token_type = CmdTokens::Definition;
-                    } else {
-                        token_type = CmdTokens::Normal;
+        }
+        let text = self.text(start + 2, self.pos - 1);
+        self.pos += 1;
+        let offset = self.chars[start + 2].0;
+        let mut lexer = Lexer::new(&text);
+        lexer.arithmetic = true;
+        let parts = lexer
+            .double_quoted(true)
+            .map_err(|err| err.shifted(offset))?;
+        Ok(Some(Word { parts }))
+    }
+
+    // `command`, a backslash only escapes $ ` and \
+    fn backquoted(&mut self) -> Result<WordPart> {
+        let start = self.pos;
+        let unclosed = |lexer: &Self| {
+            SyntaxError::incomplete("a closing backquote").opened_at(lexer.span_of(start - 1, 1))
+        };
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                Some('`') => break,
+                Some('\\') => match self.bump() {
+                    Some(c) if matches!(c, '$' | '`' | '\\') => text.push(c),
+                    Some(c) => {
+                        text.push('\\');
+                        text.push(c);
                     }
-                    result.push((token_type, token.trim().to_string(), c == '$'));
-                }
-                token.clear();
+                    None => return Err(unclosed(self)),
+                },
+                Some(c) => text.push(c),
+                None => return Err(unclosed(self)),
+            }
+        }
+        self.substitution(&text, start, false)
+    }
 
-                if c == ' ' {
-                    Normal
-                } else {
-                    DollarVariable
-                }
+    // The command of a command substitution is parsed right away, so errors in it
+    // are syntax errors of the whole line. The text starts at the char start and
+    // the closing paren or backquote was just read. Without escapes the text is
+    // as in the input and errors can point in it, else at the whole substitution.
+    fn substitution(&self, text: &str, start: usize, verbatim: bool) -> Result<WordPart> {
+        let offset = self.chars[start].0;
+        let (close, closing) = self.chars[self.pos - 1];
+        match parse_line(text) {
+            Ok(list) => Ok(WordPart::CommandSubstitution(list)),
+            Err(err) if verbatim => Err(match err {
+                // The closing paren was found, so more input can't fix it
+                SyntaxError::Incomplete { expected, .. } => SyntaxError::Unexpected {
+                    span: (close, close + 1),
+                    found: format!("`{}`", closing),
+                    expected: Some(expected),
+                },
+                err => err.shifted(offset),
+            }),
+            Err(err) => {
+                let (found, expected) = match err {
+                    SyntaxError::Unexpected {
+                        found, expected, ..
+                    } => (found, expected),
+                    SyntaxError::Incomplete { expected, .. } => {
+                        ("end of the substitution".to_string(), Some(expected))
+                    }
+                };
+                Err(SyntaxError::Unexpected {
+                    span: (self.chars[start - 1].0, close + 1),
+                    found,
+                    expected,
+                })
             }
-            (Normal, _) | (Escaped, _) => {
-                token.push(c);
-                Normal
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|c| is_name_char(*c)) {
+            name.push(c);
+            self.pos += 1;
+        }
+        name
+    }
+
+    // The name in ${...}, ${10} can have more than one digit
+    fn parameter_name(&mut self) -> String {
+        match self.peek() {
+            Some(c) if is_name_start(c) => self.name(),
+            Some(c) if c.is_ascii_digit() => {
+                let mut name = String::new();
+                while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+                    name.push(c);
+                    self.pos += 1;
+                }
+                name
             }
-            (DollarVariable, ' ') => {
-                token.push(c);
-                result.push((CmdTokens::Variable, token.trim().to_string(), false));
-                token.clear();
-                Normal
+            Some(c) if is_special_param(c) => {
+                self.pos += 1;
+                c.to_string()
             }
-            
-            (DollarVariable, c) if !valid_name_check(c) => {
-                result.push((CmdTokens::Variable, token.trim().to_string(), true));
-                token.clear();
-                token.push(c); // Todo: See if variable
-                Normal
+            _ => String::new(),
+        }
+    }
+
+    // Called after ${
+    fn parameter(&mut self, quoted: bool) -> Result<WordPart> {
+        let start = self.pos;
+        // ${#name} is the length, but ${#} is the number of arguments
+        if self.peek() == Some('#') && !matches!(self.peek_at(1), Some('}') | None) {
+            self.pos += 1;
+            let name = self.parameter_name();
+            if !name.is_empty() && self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(WordPart::Parameter(Box::new(Parameter {
+                    name,
+                    op: ParameterOp::Length,
+                })));
             }
-            (DollarVariable, _) => {
-                token.push(c);
-                DollarVariable
+            return self.bad_parameter(start);
+        }
+        let name = self.parameter_name();
+        if name.is_empty() {
+            return self.bad_parameter(start);
+        }
+        let colon = self.peek() == Some(':')
+            && matches!(
+                self.peek_at(1),
+                Some('-') | Some('=') | Some('?') | Some('+')
+            );
+        if colon {
+            self.pos += 1;
+        }
+        let op = match self.bump() {
+            Some('}') => return Ok(WordPart::Variable(name)),
+            Some('-') => ParameterOp::Default {
+                colon,
+                word: self.brace_word(&[], quoted)?,
+            },
+            Some('=') => ParameterOp::Assign {
+                colon,
+                word: self.brace_word(&[], quoted)?,
+            },
+            Some('?') => ParameterOp::Error {
+                colon,
+                word: self.brace_word(&[], quoted)?,
+            },
+            Some('+') => ParameterOp::Alternative {
+                colon,
+                word: self.brace_word(&[], quoted)?,
+            },
+            Some(c @ '#') | Some(c @ '%') => {
+                let longest = self.peek() == Some(c);
+                if longest {
+                    self.pos += 1;
+                }
+                let pattern = self.brace_word(&[], quoted)?;
+                if c == '#' {
+                    ParameterOp::RemovePrefix { longest, pattern }
+                } else {
+                    ParameterOp::RemoveSuffix { longest, pattern }
+                }
             }
-            (SingleQuoted, '\'') => Normal,
-            (SingleQuoted, _) => {
-                token.push(c);
-                SingleQuoted
+            Some('/') => {
+                let mode = match self.peek() {
+                    Some('/') => ReplaceMode::All,
+                    Some('#') => ReplaceMode::Prefix,
+                    Some('%') => ReplaceMode::Suffix,
+                    _ => ReplaceMode::First,
+                };
+                if mode != ReplaceMode::First {
+                    self.pos += 1;
+                }
+                let pattern = self.brace_word(&['/'], quoted)?;
+                let replacement = if self.previous() == Some('/') {
+                    self.brace_word(&[], quoted)?
+                } else {
+                    Word::default()
+                };
+                ParameterOp::Replace {
+                    mode,
+                    pattern,
+                    replacement,
+                }
             }
-            (DoubleQuoted, '"') => Normal,
-            (DoubleQuoted, '\\') => DoubleQuotedEscaped,
-            (DoubleQuoted, _) | (DoubleQuotedEscaped, '"') | (DoubleQuotedEscaped, '\\') => {
-                token.push(c);
-                DoubleQuoted
+            Some(':') => {
+                let offset = self.brace_word(&[':'], quoted)?;
+                let length = if self.previous() == Some(':') {
+                    Some(self.brace_word(&[], quoted)?)
+                } else {
+                    None
+                };
+                ParameterOp::Substring { offset, length }
             }
-            (DoubleQuotedEscaped, _) => {
-                token.push('\\');
-                token.push(c);
-                DoubleQuoted
+            Some(c @ '^') | Some(c @ ',') => {
+                let all = self.peek() == Some(c);
+                if all {
+                    self.pos += 1;
+                }
+                ParameterOp::Case {
+                    upper: c == '^',
+                    all,
+                    pattern: self.brace_word(&[], quoted)?,
+                }
             }
+            None => return Err(SyntaxError::incomplete("`}`")),
+            Some(_) => return self.bad_parameter(start),
         };
+        Ok(WordPart::Parameter(Box::new(Parameter { name, op })))
     }
 
-    match state {
-        Normal => {
-            if !token.is_empty() {
-                let token_type: tokens::CmdTokens;
-                if is_definition {
-                    token_type = CmdTokens::Definition;
-                } else {
-                    token_type = CmdTokens::Normal;
+    // Skips to the closing brace, the expansion is reported when it is used
+    fn bad_parameter(&mut self, start: usize) -> Result<WordPart> {
+        self.pos = start;
+        self.brace_word(&[], true)?;
+        let text = self.text(start, self.pos - 1);
+        Ok(WordPart::Parameter(Box::new(Parameter {
+            name: String::new(),
+            op: ParameterOp::Bad(text),
+        })))
+    }
+
+    // The word inside ${...} after the operator, it can contain spaces and ends at
+    // the closing brace or one of the stop chars, which is consumed.
+    // Inside double quotes single quotes are plain chars.
+    fn brace_word(&mut self, stops: &[char], quoted: bool) -> Result<Word> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        // Braces in the word have to be balanced, ${a:-{b}} is "{b}"
+        let mut depth = 0;
+        loop {
+            if self.skip_line_continuation() {
+                continue;
+            }
+            let c = match self.bump() {
+                Some(c) => c,
+                None => return Err(SyntaxError::incomplete("`}`")),
+            };
+            let part = match c {
+                '}' if depth == 0 => break,
+                c if depth == 0 && stops.contains(&c) => break,
+                '\\' => match self.bump() {
+                    Some(escaped) => WordPart::Quoted(escaped.to_string()),
+                    None => return Err(SyntaxError::incomplete("`}`")),
+                },
+                '\'' if !quoted => WordPart::Quoted(self.single_quoted()?),
+                '"' => WordPart::DoubleQuoted(self.double_quoted(false)?),
+                '`' => self.backquoted()?,
+                '$' => match self.dollar(quoted)? {
+                    Some(part) => part,
+                    None => {
+                        literal.push('$');
+                        continue;
+                    }
+                },
+                c => {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    literal.push(c);
+                    continue;
                 }
-                result.push((token_type, token.trim().to_string(), false));
+            };
+            if !literal.is_empty() {
+                parts.push(brace_literal(std::mem::take(&mut literal), quoted));
             }
+            parts.push(part);
         }
-        DollarVariable => {
-            if !token.is_empty() {
-                result.push((CmdTokens::Variable, token.trim().to_string(), false));
-            }
+        if !literal.is_empty() {
+            parts.push(brace_literal(literal, quoted));
         }
-        _ => {
-            // println!("{:?}", state);
-            // Todo: Add more information on error, SyntaxError near token Pipe
-            return Err(SyntaxError);
+        Ok(Word { parts })
+    }
+}
+
+// Literal text in ${...} inside double quotes is not globbed
+fn brace_literal(text: String, quoted: bool) -> WordPart {
+    if quoted {
+        WordPart::Quoted(text)
+    } else {
+        WordPart::Literal(text)
+    }
+}
+
+// tokenize("echo hello && echo 'good bye'")
+// [Word(echo), Word(hello), Operator(AndIf), Word(echo), Word('good bye')]
+// The bodies of here-documents are returned separately, in the order they appear
+pub fn tokenize(line: &str) -> Result<(Vec<Token>, Vec<Word>)> {
+    let mut lexer = Lexer::new(line);
+    let mut tokens = Vec::new();
+    lexer.tokens(&mut tokens)?;
+    Ok((tokens, lexer.here_docs))
+}
+
+// For a line that is still being typed, the tokens before the first error
+// and where the text that could not be read starts, ex at the quote of "echo 'hi"
+pub fn tokenize_partial(line: &str) -> (Vec<Token>, Option<usize>) {
+    let mut tokens = Vec::new();
+    match Lexer::new(line).tokens(&mut tokens) {
+        Ok(()) => (tokens, None),
+        Err(_) => {
+            let end = tokens.last().map_or(0, |token| token.span.1);
+            let rest = &line[end..];
+            (tokens, Some(end + rest.len() - rest.trim_start().len()))
         }
     }
-    Ok(result)
 }
 
 #[cfg(test)]
 mod tests {
-    #[test]
-    fn test_line_to_cmds() {
-        fn s(input: &str) -> String {
-            input.to_string()
-        }
-        use super::line_to_cmds;
-        use super::tokens::LineToCmdTokens::*;
+    use super::super::ast::{Word, WordPart::*};
+    use super::super::tokens::{Operator::*, TokenKind};
+    use super::tokenize;
+
+    fn word(parts: Vec<super::WordPart>) -> TokenKind {
+        TokenKind::Word(Word { parts })
+    }
+
+    fn lit(text: &str) -> TokenKind {
+        word(vec![Literal(text.to_string())])
+    }
+
+    fn kinds(line: &str) -> Vec<TokenKind> {
+        tokenize(line)
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|t| t.kind)
+            .collect()
+    }
 
+    #[test]
+    fn test_tokenize() {
+        let op = TokenKind::Operator;
         let v = vec![
-            ("   ls  ", vec![(Command, s("ls"))]), // Trim input
-            ("ls", vec![(Command, s("ls"))]),
+            ("   ls  ", vec![lit("ls")]), // Trim input
+            ("ls", vec![lit("ls")]),
             (
                 "echo morning & echo night",
-                vec![(Command, s("echo morning & echo night"))],
+                vec![
+                    lit("echo"),
+                    lit("morning"),
+                    op(Amp),
+                    lit("echo"),
+                    lit("night"),
+                ],
             ),
             (
                 "echo morning && echo night",
                 vec![
-                    (Command, s("echo morning")),
-                    (Separator, s("&&")),
-                    (Command, s("echo night")),
+                    lit("echo"),
+                    lit("morning"),
+                    op(AndIf),
+                    lit("echo"),
+                    lit("night"),
                 ],
             ),
-            ("ls | grep .bashrc", vec![(Command, s("ls | grep .bashrc"))]),
+            (
+                "ls | grep .bashrc",
+                vec![lit("ls"), op(Pipe), lit("grep"), lit(".bashrc")],
+            ),
             // Quotes
             (
                 r#"echo "What an awesome day && nice weather""#,
-                vec![(Command, s(r#"echo "What an awesome day && nice weather""#))],
+                vec![
+                    lit("echo"),
+                    word(vec![DoubleQuoted(vec![Quoted(
+                        "What an awesome day && nice weather".to_string(),
+                    )])]),
+                ],
             ),
             (
                 r#"echo 'What an awesome day && nice weather'"#,
-                vec![(Command, s(r#"echo 'What an awesome day && nice weather'"#))],
+                vec![
+                    lit("echo"),
+                    word(vec![Quoted(
+                        "What an awesome day && nice weather".to_string(),
+                    )]),
+                ],
             ),
             // Escape
             (
                 r#"echo \"What an awesome day && nice weather\""#,
                 vec![
-                    (Command, s(r#"echo \"What an awesome day"#)),
-                    (Separator, s("&&")),
-                    (Command, s(r#"nice weather\""#)),
+                    lit("echo"),
+                    word(vec![Quoted("\"".to_string()), Literal("What".to_string())]),
+                    lit("an"),
+                    lit("awesome"),
+                    lit("day"),
+                    op(AndIf),
+                    lit("nice"),
+                    word(vec![
+                        Literal("weather".to_string()),
+                        Quoted("\"".to_string()),
+                    ]),
                 ],
             ),
             (
                 r#"echo What an awesome day \&\& nice weather"#,
-                vec![(Command, s(r#"echo What an awesome day \&\& nice weather"#))],
+                vec![
+                    lit("echo"),
+                    lit("What"),
+                    lit("an"),
+                    lit("awesome"),
+                    lit("day"),
+                    word(vec![Quoted("&".to_string()), Quoted("&".to_string())]),
+                    lit("nice"),
+                    lit("weather"),
+                ],
+            ),
+            (";", vec![op(Semi)]),
+            // Variables
+            (
+                "echo $HOME/.config$?",
+                vec![
+                    lit("echo"),
+                    word(vec![
+                        Variable("HOME".to_string()),
+                        Literal("/.config".to_string()),
+                        Variable("?".to_string()),
+                    ]),
+                ],
+            ),
+            ("echo a # comment", vec![lit("echo"), lit("a")]),
+            // Not arithmetic
+            (
+                "((a) | b)",
+                vec![
+                    op(LParen),
+                    op(LParen),
+                    lit("a"),
+                    op(RParen),
+                    op(Pipe),
+                    lit("b"),
+                    op(RParen),
+                ],
             ),
-            (";", vec![(Separator, s(";"))]),
         ];
 
         for (l, r) in v {
-            assert_eq!(line_to_cmds(l), r);
+            assert_eq!(kinds(l), r);
+        }
+    }
+
+    #[test]
+    fn test_command_substitution() {
+        use super::super::parser::parse_line;
+        // (line, the command in $(...))
+        let v = vec![
+            ("echo $(echo case) a", "echo case"),
+            ("echo $(grep case f) a", "grep case f"),
+            ("echo $(echo esac case) a", "echo esac case"),
+            (
+                "echo $(case x in x) echo y;; esac) a",
+                "case x in x) echo y;; esac",
+            ),
+            (
+                "echo $(case x in (x) echo ')';; esac) a",
+                "case x in (x) echo ')';; esac",
+            ),
+            ("echo $( (ls) | (cat) ) a", " (ls) | (cat) "),
+            ("echo $(echo a # ) b\n) a", "echo a # ) b\n"),
+        ];
+        for (l, r) in v {
+            let substitution = word(vec![CommandSubstitution(parse_line(r).unwrap())]);
+            assert_eq!(kinds(l), vec![lit("echo"), substitution, lit("a")], "{}", l);
         }
     }
+
+    #[test]
+    fn test_tokenize_unterminated() {
+        use super::super::errors::SyntaxError;
+        let unclosed = |line: &str| match tokenize(line) {
+            Err(SyntaxError::Incomplete {
+                expected,
+                opened: Some(span),
+            }) => (expected, span),
+            other => panic!("{}: {:?}", line, other),
+        };
+        assert_eq!(
+            unclosed("echo 'hello"),
+            ("a closing `'`".to_string(), (5, 6))
+        );
+        assert_eq!(
+            unclosed("echo \"hello"),
+            ("a closing `\"`".to_string(), (5, 6))
+        );
+        assert_eq!(
+            unclosed("cat <<EOF\nhello\n"),
+            ("`EOF`".to_string(), (6, 9))
+        );
+        assert_eq!(unclosed("((a)$((b"), ("`))`".to_string(), (5, 7)));
+        assert_eq!(unclosed("echo \"${a:-$(ls"), ("`)`".to_string(), (11, 13)));
+        assert_eq!(unclosed("echo ${a:-b"), ("`}`".to_string(), (5, 7)));
+        assert_eq!(unclosed("echo a \\"), ("another line".to_string(), (7, 8)));
+    }
+
+    #[test]
+    fn test_here_docs() {
+        let here_docs = |line: &str| tokenize(line).unwrap().1;
+
+        assert_eq!(
+            here_docs("cat <<EOF\nhello $USER\nEOF\n"),
+            vec![Word {
+                parts: vec![DoubleQuoted(vec![
+                    Quoted("hello ".to_string()),
+                    Variable("USER".to_string()),
+                    Quoted("\n".to_string()),
+                ])]
+            }]
+        );
+        assert_eq!(
+            here_docs("cat <<'EOF'\nhello $USER\nEOF"),
+            vec![Word {
+                parts: vec![Quoted("hello $USER\n".to_string())]
+            }]
+        );
+        assert_eq!(
+            here_docs("cat <<-\"A\" <<B\n\tone\n\tA\ntwo\nB\n"),
+            vec![
+                Word {
+                    parts: vec![Quoted("one\n".to_string())]
+                },
+                Word {
+                    parts: vec![DoubleQuoted(vec![Quoted("two\n".to_string())])]
+                },
+            ]
+        );
+    }
 }
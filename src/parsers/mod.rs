@@ -1,3 +1,4 @@
+pub mod ast;
 pub mod errors;
 pub mod lexer;
 pub mod parser;
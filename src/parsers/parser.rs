@@ -1,102 +1,631 @@
-use std::path::PathBuf;
-
+use super::ast::*;
 use super::errors::*;
-use super::*;
-use crate::utils;
-use glob::glob;
-
-// Todo: rustyline escape star character in filenames
-// -> (Command: ["echo", "wow"]), (Separator: [">"]), (Command: ["echo", "goodbye"])
-pub fn parse_cmd(token: String, status: i32) -> Result<Vec<(tokens::ParseCmdTokens, Vec<String>)>> {
-    let mut combine_value = "".to_string();
-    let mut result = Vec::new();
-    let mut result_part: Vec<String> = Vec::new();
-    let mut before_token: Option<tokens::CmdTokens> = None;
-    let mut is_definition: bool = false;
-    // Todo: part should give boolean if escaped/quoted or not, for wildcards, variables and ~
-    for part in lexer::cmd_to_tokens(&token)?.iter().peekable() {
-        // println!("{:?}", part);
-        before_token = match part.0 {
-            tokens::CmdTokens::Pipe => {
-                if before_token.is_none() || before_token == Some(tokens::CmdTokens::Pipe) {
-                    return Err(SyntaxError); // Ex "> echo lol"
+use super::lexer;
+use super::tokens::{Operator, Token, TokenKind};
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    here_docs: VecDeque<Word>,
+    aliases: &'a BTreeMap<String, String>,
+    // The aliases being expanded and where their tokens end
+    expanding: Vec<(String, usize)>,
+    // The word after an alias that ends with a blank is checked for an alias too
+    check_alias: Option<usize>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn peek_operator(&self) -> Option<Operator> {
+        match self.peek() {
+            Some(TokenKind::Operator(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    // The text of the next token if it is a plain word, reserved words like "if"
+    // only count when they are not quoted
+    fn peek_keyword(&self) -> Option<&str> {
+        match self.peek() {
+            Some(TokenKind::Word(word)) => word.as_literal(),
+            _ => None,
+        }
+    }
+
+    // A reserved word or ";;" that ends the list being parsed
+    fn at_terminator(&self, terminators: &[&str]) -> bool {
+        match self.peek() {
+            Some(TokenKind::Word(word)) => word
+                .as_literal()
+                .is_some_and(|word| terminators.contains(&word)),
+            Some(TokenKind::Operator(op)) => terminators.contains(&op.as_str()),
+            _ => false,
+        }
+    }
+
+    // The token at pos is not what was expected, at the end of the input
+    // it is the newline after the last token
+    fn unexpected_at(&self, pos: usize, expected: &str) -> SyntaxError {
+        let (span, found) = match self.tokens.get(pos) {
+            Some(Token {
+                kind: TokenKind::Newline,
+                span,
+            }) => (*span, "newline".to_string()),
+            Some(token) => (token.span, format!("`{}`", token.kind)),
+            None => {
+                let end = self.tokens.last().map_or(0, |token| token.span.1);
+                ((end, end), "newline".to_string())
+            }
+        };
+        SyntaxError::Unexpected {
+            span,
+            found,
+            expected: Some(expected.to_string()),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> SyntaxError {
+        self.unexpected_at(self.pos, expected)
+    }
+
+    // More lines can bring what is missing at the end of the input
+    fn end_of_input(&self, expected: &str) -> SyntaxError {
+        if self.peek().is_none() {
+            SyntaxError::incomplete(expected)
+        } else {
+            self.unexpected(expected)
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.peek_keyword() != Some(keyword) {
+            return Err(self.end_of_input(&format!("`{}`", keyword)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn operator(&mut self, op: Operator) -> Result<()> {
+        if self.peek_operator() != Some(op) {
+            return Err(self.end_of_input(&format!("`{}`", op.as_str())));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn word(&mut self) -> Result<Word> {
+        match self.peek() {
+            Some(TokenKind::Word(word)) => {
+                let word = word.clone();
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => Err(self.end_of_input("a word")),
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&TokenKind::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    // and_or ((";" | "&" | newline) and_or)*
+    // Inside compound commands the list ends at one of the terminators, like "fi"
+    fn list(&mut self, terminators: &[&str]) -> Result<List> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None => break,
+                Some(TokenKind::Newline) | Some(TokenKind::Operator(Operator::Semi)) => {
+                    self.pos += 1;
+                    continue;
                 }
-                if !result_part.is_empty() {
-                    result.push((tokens::ParseCmdTokens::Command, result_part.clone()));
-                    result_part.clear();
+                Some(_) if self.at_terminator(terminators) => break,
+                Some(_) => {}
+            }
+            let and_or = self.and_or()?;
+            let background = self.peek_operator() == Some(Operator::Amp);
+            match self.peek() {
+                None | Some(TokenKind::Newline) => {}
+                Some(TokenKind::Operator(Operator::Semi))
+                | Some(TokenKind::Operator(Operator::Amp)) => self.pos += 1,
+                // "a) echo;; esac"
+                Some(_) if self.at_terminator(terminators) => {}
+                Some(_) => return Err(self.unexpected("`;`, `&` or a newline")),
+            }
+            items.push(ListItem { and_or, background });
+        }
+        Ok(List { items })
+    }
+
+    // pipeline (("&&" | "||") newline* pipeline)*
+    fn and_or(&mut self) -> Result<AndOr> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = match self.peek_operator() {
+                Some(Operator::AndIf) => Connector::And,
+                Some(Operator::OrIf) => Connector::Or,
+                _ => break,
+            };
+            // "a &&" is shown at the &&
+            let span = self.tokens[self.pos].span;
+            self.pos += 1;
+            self.skip_newlines();
+            rest.push((
+                connector,
+                self.pipeline().map_err(|err| err.opened_at(span))?,
+            ));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    // "!"? command ("|" newline* command)*
+    fn pipeline(&mut self) -> Result<Pipeline> {
+        let mut negated = false;
+        if let Some(TokenKind::Word(word)) = self.peek() {
+            if word.as_literal() == Some("!") {
+                negated = true;
+                self.pos += 1;
+            }
+        }
+        let mut commands = vec![self.command()?];
+        while self.peek_operator() == Some(Operator::Pipe) {
+            let span = self.tokens[self.pos].span;
+            self.pos += 1;
+            self.skip_newlines();
+            commands.push(self.command().map_err(|err| err.opened_at(span))?);
+        }
+        Ok(Pipeline { negated, commands })
+    }
+
+    // Replaces the word at the current position with the value of its alias, again
+    // while the result starts with another alias. An alias is not expanded inside
+    // itself, so "alias ls='ls -F'" works.
+    fn expand_alias(&mut self) -> Result<()> {
+        let aliases = self.aliases;
+        loop {
+            let pos = self.pos;
+            self.expanding.retain(|(_, end)| *end > pos);
+            let name = match self.peek_keyword() {
+                Some(name) if !self.expanding.iter().any(|(active, _)| active == name) => name,
+                _ => return Ok(()),
+            };
+            let (name, value) = match aliases.get_key_value(name) {
+                Some(alias) => alias,
+                None => return Ok(()),
+            };
+            let span = self.tokens[pos].span;
+            // The tokens of the alias have the span of its name
+            let (tokens, _) = lexer::tokenize(value).map_err(|err| match err {
+                SyntaxError::Incomplete { expected, .. } => SyntaxError::Unexpected {
+                    span,
+                    found: format!("end of alias `{}`", name),
+                    expected: Some(expected),
+                },
+                SyntaxError::Unexpected {
+                    found, expected, ..
+                } => SyntaxError::Unexpected {
+                    span,
+                    found,
+                    expected,
+                },
+            })?;
+            let added = tokens.len();
+            self.tokens.splice(
+                pos..pos + 1,
+                tokens.into_iter().map(|token| Token {
+                    kind: token.kind,
+                    span,
+                }),
+            );
+            // The tokens after the alias moved
+            let shift = |index: &mut usize| {
+                if *index > pos {
+                    *index = *index + added - 1;
                 }
-                // The separator has to be put in a vec
-                result.push((tokens::ParseCmdTokens::Separator, vec![part.1.clone()]));
-                Some(part.0)
+            };
+            for (_, end) in &mut self.expanding {
+                shift(end);
             }
-            _ => {
-                let mut str_part = part.1.clone();
-                if part.0 == tokens::CmdTokens::Definition {
-                    is_definition = true;
+            if let Some(check_alias) = &mut self.check_alias {
+                shift(check_alias);
+            }
+            self.expanding.push((name.clone(), pos + added));
+            if value.ends_with([' ', '\t']) {
+                self.check_alias = Some(pos + added);
+            }
+        }
+    }
+
+    fn command(&mut self) -> Result<Command> {
+        self.expand_alias()?;
+        // A command that is not finished is shown from its first word, the "if"
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                let span = token.span;
+                self.command_body().map_err(|err| err.opened_at(span))
+            }
+            None => self.command_body(),
+        }
+    }
+
+    fn command_body(&mut self) -> Result<Command> {
+        if let Some(TokenKind::Arithmetic(expr)) = self.peek() {
+            let expr = expr.clone();
+            self.pos += 1;
+            return Ok(Command::Arithmetic(expr));
+        }
+        if self.peek_operator() == Some(Operator::LParen) {
+            self.pos += 1;
+            let list = self.compound_list(&[")"])?;
+            self.operator(Operator::RParen)?;
+            return self.with_redirects(CompoundCommand::Subshell(list));
+        }
+        let compound = match self.peek_keyword() {
+            Some("if") => self.if_clause()?,
+            Some("while") | Some("until") => self.while_loop()?,
+            Some("for") => self.for_loop()?,
+            Some("case") => self.case_clause()?,
+            Some("{") => {
+                self.pos += 1;
+                let list = self.compound_list(&["}"])?;
+                self.keyword("}")?;
+                CompoundCommand::BraceGroup(list)
+            }
+            Some("function") => {
+                self.pos += 1;
+                return self.function_definition();
+            }
+            Some(word) if is_reserved_word(word) => return Err(self.unexpected("a command")),
+            Some(_) if self.at_function_definition() => return self.function_definition(),
+            _ => return Ok(Command::Simple(self.simple_command()?)),
+        };
+        self.with_redirects(compound)
+    }
+
+    // Reads the redirections after a compound command, "done < file"
+    fn with_redirects(&mut self, compound: CompoundCommand) -> Result<Command> {
+        let mut redirects = Vec::new();
+        loop {
+            let fd = match self.peek() {
+                Some(TokenKind::IoNumber(fd)) => Some(*fd),
+                _ => None,
+            };
+            if fd.is_some() {
+                self.pos += 1;
+            }
+            match self.redirect(fd)? {
+                Some(redirect) => redirects.push(redirect),
+                None if fd.is_some() => return Err(self.unexpected("a redirection")),
+                None => break,
+            }
+        }
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    // "name ()", the parens are separate tokens so "name ( )" works too
+    fn at_function_definition(&self) -> bool {
+        let kind = |offset: usize| self.tokens.get(self.pos + offset).map(|t| &t.kind);
+        kind(1) == Some(&TokenKind::Operator(Operator::LParen))
+            && kind(2) == Some(&TokenKind::Operator(Operator::RParen))
+    }
+
+    // name "(" ")" newline* compound_command, "function" is already read and
+    // the parens are optional after it
+    fn function_definition(&mut self) -> Result<Command> {
+        let name = match self.peek_keyword() {
+            Some(name) if is_function_name(name) => name.to_string(),
+            _ => return Err(self.end_of_input("a function name")),
+        };
+        self.pos += 1;
+        if self.peek_operator() == Some(Operator::LParen) {
+            self.pos += 1;
+            self.operator(Operator::RParen)?;
+        }
+        self.skip_newlines();
+        let start = self.pos;
+        match self.command()? {
+            Command::Simple(_) | Command::Function { .. } => {
+                Err(self.unexpected_at(start, "a compound command like `{`"))
+            }
+            body => Ok(Command::Function {
+                name,
+                body: Rc::new(body),
+            }),
+        }
+    }
+
+    // The list inside a compound command, it can't be empty
+    fn compound_list(&mut self, terminators: &[&str]) -> Result<List> {
+        let list = self.list(terminators)?;
+        if list.items.is_empty() {
+            return Err(self.end_of_input("a command"));
+        }
+        Ok(list)
+    }
+
+    // "if" list "then" list ("elif" list "then" list)* ("else" list)? "fi"
+    fn if_clause(&mut self) -> Result<CompoundCommand> {
+        self.pos += 1;
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        loop {
+            let condition = self.compound_list(&["then"])?;
+            self.keyword("then")?;
+            let body = self.compound_list(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            match self.peek_keyword() {
+                Some("elif") => self.pos += 1,
+                Some("else") => {
+                    self.pos += 1;
+                    otherwise = Some(self.compound_list(&["fi"])?);
+                    break;
                 }
+                _ => break,
+            }
+        }
+        self.keyword("fi")?;
+        Ok(CompoundCommand::If {
+            branches,
+            otherwise,
+        })
+    }
 
-                // Replace with enviroment variable
-                if part.0 == tokens::CmdTokens::Variable {
-                    if part.1.clone() == "?" {
-                        str_part = status.to_string();
-                    } else {
-                        str_part = std::env::var(part.1.clone()).unwrap_or_else(|_| "".to_string());
-                    }
-                // Replace ~ with home dir
-                } else if part.0 == tokens::CmdTokens::Normal && str_part.starts_with('~') {
-                    str_part = str_part.split_at(1).1.to_string();
-                    str_part = format!("{}{}", utils::get_home_dir(), str_part);
+    // ("while" | "until") list "do" list "done"
+    fn while_loop(&mut self) -> Result<CompoundCommand> {
+        let until = self.peek_keyword() == Some("until");
+        self.pos += 1;
+        let condition = self.compound_list(&["do"])?;
+        let body = self.do_group()?;
+        Ok(CompoundCommand::While {
+            until,
+            condition,
+            body,
+        })
+    }
+
+    // "do" list "done"
+    fn do_group(&mut self) -> Result<List> {
+        self.keyword("do")?;
+        let body = self.compound_list(&["done"])?;
+        self.keyword("done")?;
+        Ok(body)
+    }
+
+    // "for" name ("in" word* (";" | newline))? newline* "do" list "done"
+    fn for_loop(&mut self) -> Result<CompoundCommand> {
+        self.pos += 1;
+        let name = match self.peek_keyword() {
+            Some(name) if lexer::is_valid_variable_name(name) => name.to_string(),
+            _ => return Err(self.end_of_input("a variable name")),
+        };
+        self.pos += 1;
+        self.skip_newlines();
+        let mut words = None;
+        if self.peek_keyword() == Some("in") {
+            self.pos += 1;
+            let mut list = Vec::new();
+            while let Some(TokenKind::Word(word)) = self.peek() {
+                list.push(word.clone());
+                self.pos += 1;
+            }
+            words = Some(list);
+            match self.peek() {
+                Some(TokenKind::Newline) | Some(TokenKind::Operator(Operator::Semi)) => {
+                    self.pos += 1
                 }
+                _ => return Err(self.end_of_input("`;` or a newline")),
+            }
+        } else if self.peek_operator() == Some(Operator::Semi) {
+            self.pos += 1;
+        }
+        self.skip_newlines();
+        let body = self.do_group()?;
+        Ok(CompoundCommand::For { name, words, body })
+    }
 
-                if part.2 {
-                    combine_value += &str_part;
-                } else {
-                    let val = format!("{}{}", combine_value, str_part);
-
-                    if is_definition {
-                        is_definition = false;
-                        // For now all variables are exported / enviroment variables
-                        // Todo: Add shell variables
-                        let definition_parts: Vec<&str> = val.split('=').collect();
-                        // Could maybe happen? thread 'main' panicked at 'index out of bounds: the len is 1 but the index is 1'
-                        std::env::set_var(definition_parts[0], definition_parts[1]);
-                    } else {
-                        // Glob paths. ex ./*.md
-                        if let Ok(globs) = glob(&val) {
-                            let globs_vec: Vec<PathBuf> = globs.flatten().collect();
-
-                            // If there is none
-                            if !globs_vec.is_empty() && globs_vec[0].display().to_string() != "." {
-                                for entry in globs_vec {
-                                    let entry_string = entry.display().to_string();
-                                    if !entry_string.starts_with('/') {
-                                        result_part
-                                            .push(format!("./{}", entry.display().to_string()));
-                                    } else {
-                                        result_part.push(entry.display().to_string());
-                                    }
-                                }
-                            } else {
-                                result_part.push(val);
-                            }
+    // "case" word "in" ("("? word ("|" word)* ")" list (";;" | "esac"))* "esac"
+    fn case_clause(&mut self) -> Result<CompoundCommand> {
+        self.pos += 1;
+        let word = self.word()?;
+        self.skip_newlines();
+        self.keyword("in")?;
+        self.skip_newlines();
+        let mut items = Vec::new();
+        while self.peek_keyword() != Some("esac") {
+            if self.peek_operator() == Some(Operator::LParen) {
+                self.pos += 1;
+            }
+            let mut patterns = vec![self.word()?];
+            while self.peek_operator() == Some(Operator::Pipe) {
+                self.pos += 1;
+                patterns.push(self.word()?);
+            }
+            self.operator(Operator::RParen)?;
+            let body = self.list(&[";;", "esac"])?;
+            items.push(CaseItem { patterns, body });
+            if self.peek_operator() != Some(Operator::DSemi) {
+                break;
+            }
+            self.pos += 1;
+            self.skip_newlines();
+        }
+        self.keyword("esac")?;
+        Ok(CompoundCommand::Case { word, items })
+    }
+
+    // (assignment | redirect)* word (word | redirect)*
+    fn simple_command(&mut self) -> Result<SimpleCommand> {
+        let mut command = SimpleCommand::default();
+        loop {
+            // "FOO=bar ll" and "sudo ll" with "alias sudo='sudo '"
+            if command.words.is_empty() || self.check_alias == Some(self.pos) {
+                self.expand_alias()?;
+            }
+            match self.peek() {
+                Some(TokenKind::Word(word)) => {
+                    let word = word.clone();
+                    self.pos += 1;
+                    if command.words.is_empty() {
+                        if let Some(assignment) = to_assignment(&word) {
+                            command.assignments.push(assignment);
+                            continue;
                         }
                     }
-                    combine_value.clear();
+                    command.words.push(word);
+                }
+                Some(TokenKind::IoNumber(fd)) => {
+                    let fd = *fd;
+                    self.pos += 1;
+                    match self.redirect(Some(fd))? {
+                        Some(redirect) => command.redirects.push(redirect),
+                        None => return Err(self.unexpected("a redirection")),
+                    }
                 }
-                Some(part.0)
+                Some(TokenKind::Operator(_)) => match self.redirect(None)? {
+                    Some(redirect) => command.redirects.push(redirect),
+                    None => break,
+                },
+                _ => break,
             }
-        };
+        }
+        if command == SimpleCommand::default() {
+            // Ex "ls |", more lines can finish it
+            if self.peek().is_none() {
+                return Err(SyntaxError::incomplete("a command"));
+            }
+            // Ex "| grep", "ls && && ls"
+            return Err(self.unexpected("a command"));
+        }
+        Ok(command)
     }
-    // Ex "hello |"
-    if before_token == Some(tokens::CmdTokens::Pipe) {
-        return Err(SyntaxError);
+
+    // ">file", "2>&1", returns None if the next token is not a redirection
+    fn redirect(&mut self, fd: Option<i32>) -> Result<Option<Redirect>> {
+        let kind = match self.peek_operator() {
+            Some(Operator::Less) => RedirectKind::Input,
+            Some(Operator::Great) => RedirectKind::Output,
+            Some(Operator::DGreat) => RedirectKind::Append,
+            Some(Operator::Clobber) => RedirectKind::Clobber,
+            Some(Operator::LessGreat) => RedirectKind::ReadWrite,
+            Some(Operator::LessAnd) => RedirectKind::DupInput,
+            Some(Operator::GreatAnd) => RedirectKind::DupOutput,
+            Some(Operator::AndGreat) => RedirectKind::OutputAll,
+            Some(Operator::AndDGreat) => RedirectKind::AppendAll,
+            Some(Operator::DLess) => RedirectKind::HereDoc,
+            Some(Operator::DLessDash) => RedirectKind::HereDocStrip,
+            Some(Operator::TLess) => RedirectKind::HereString,
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        let target = match self.peek() {
+            Some(TokenKind::Word(word)) => word.clone(),
+            // Ex "echo >", a newline can't finish it
+            _ => {
+                return Err(self.unexpected(match kind {
+                    RedirectKind::HereDoc | RedirectKind::HereDocStrip => "a delimiter",
+                    RedirectKind::HereString => "a word",
+                    _ => "a file name",
+                }))
+            }
+        };
+        self.pos += 1;
+        let here_doc = match kind {
+            RedirectKind::HereDoc | RedirectKind::HereDocStrip => Some(
+                self.here_docs
+                    .pop_front()
+                    .ok_or_else(|| SyntaxError::incomplete("a here-document"))?,
+            ),
+            _ => None,
+        };
+        Ok(Some(Redirect {
+            fd,
+            kind,
+            target,
+            here_doc,
+        }))
     }
+}
+
+pub fn is_reserved_word(word: &str) -> bool {
+    matches!(
+        word,
+        "if" | "then"
+            | "elif"
+            | "else"
+            | "fi"
+            | "while"
+            | "until"
+            | "for"
+            | "do"
+            | "done"
+            | "case"
+            | "esac"
+            | "{"
+            | "}"
+            | "function"
+    )
+}
 
-    if !result_part.is_empty() {
-        result.push((tokens::ParseCmdTokens::Command, result_part.clone()));
+// Functions can have names that are not valid variable names, like "git-log"
+fn is_function_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('=') && !is_reserved_word(name)
+}
+
+// "FOO=bar" -> Assignment { name: "FOO", value: "bar" }
+fn to_assignment(word: &Word) -> Option<Assignment> {
+    let text = match word.parts.first() {
+        Some(WordPart::Literal(text)) => text,
+        _ => return None,
+    };
+    let eq = text.find('=')?;
+    let name = &text[..eq];
+    // ex TEST?=1 is not valid because of the question mark
+    if !lexer::is_valid_variable_name(name) {
+        return None;
+    }
+    let mut parts = Vec::new();
+    if eq + 1 < text.len() {
+        parts.push(WordPart::Literal(text[eq + 1..].to_string()));
     }
+    parts.extend(word.parts[1..].iter().cloned());
+    Some(Assignment {
+        name: name.to_string(),
+        value: Word { parts },
+    })
+}
+
+// parse_line("echo wow > file; echo goodbye")
+// List [AndOr(Simple(echo wow, redirect > file)), AndOr(Simple(echo goodbye))]
+pub fn parse_line(line: &str) -> Result<List> {
+    parse_line_with_aliases(line, &BTreeMap::new())
+}
+
+// The first word of each simple command is replaced if it is one of the aliases
+pub fn parse_line_with_aliases(line: &str, aliases: &BTreeMap<String, String>) -> Result<List> {
+    let (tokens, here_docs) = lexer::tokenize(line)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        here_docs: here_docs.into(),
+        aliases,
+        expanding: Vec::new(),
+        check_alias: None,
+    };
+    parser.list(&[])
+}
 
-    Ok(result)
+// Returns true if the input ends in the middle of a command, like an open quote,
+// a trailing pipe or a here-document without its delimiter
+pub fn is_incomplete(line: &str) -> bool {
+    parse_line(line).is_err_and(|err| err.is_incomplete())
 }
 
 #[cfg(test)]
@@ -105,50 +634,349 @@ mod tests {
         ($($x:expr),*) => (vec![$($x.to_string()),*]);
     }
 
+    fn commands(list: &super::List) -> Vec<String> {
+        list.items.iter().map(|item| item.to_string()).collect()
+    }
+
     #[test]
-    fn test_parser() {
-        use super::parse_cmd;
-        use super::tokens::ParseCmdTokens::*;
+    fn test_parse_line() {
+        use super::parse_line;
 
         let v = vec![
+            ("   ls  ", string_vec!["ls"]), // Trim input
+            (
+                "echo morning & echo night",
+                string_vec!["echo morning &", "echo night"],
+            ),
+            ("sleep 1&", string_vec!["sleep 1 &"]),
+            (
+                "echo morning && echo night",
+                string_vec!["echo morning && echo night"],
+            ),
+            ("ls; ls -a\nls -l", string_vec!["ls", "ls -a", "ls -l"]),
+            ("ls | grep .bashrc", string_vec!["ls | grep .bashrc"]),
+            ("a || b && ! c", string_vec!["a || b && ! c"]),
+            ("echo hi >> file < in", string_vec!["echo hi >>file <in"]),
+            (
+                "cmd 2>&1 >out 3<&0 4>&- &>all",
+                string_vec!["cmd 2>&1 >out 3<&0 4>&- &>all"],
+            ),
+            ("cmd 2 > file", string_vec!["cmd 2 >file"]),
+            (";", string_vec![]),
+            // Parameter expansion
+            (
+                "echo ${a:-b c} ${#a} ${a##*/} ${a//x/y} ${a:1:2} ${a^^}",
+                string_vec!["echo ${a:-b c} ${#a} ${a##*/} ${a//x/y} ${a:1:2} ${a^^}"],
+            ),
+            ("echo ${a:-${b}}x", string_vec!["echo ${a:-${b}}x"]),
+            // Command substitution
+            (
+                "echo $(ls | wc -l) `pwd` \"$(echo $(echo ')'))\"",
+                string_vec!["echo $(ls | wc -l) $(pwd) \"$(echo $(echo ')'))\""],
+            ),
+            // Arithmetic
             (
-                "echo hello world",
-                vec![(Command, string_vec!["echo", "hello", "world"])],
-            ), // Trim input
+                "echo $((1 + (2 * 3))) \"$(($x))\"; ((x++)) && echo",
+                string_vec!["echo $((1 + (2 * 3))) \"$((${x}))\"", "((x++)) && echo"],
+            ),
+            // Compound commands
             (
-                "echo $tesrakijds",
-                vec![(Command, string_vec!["echo", "hello"])],
-            ), // Test enviroment variables
+                "if a; then b; elif c\nthen d; else e; fi",
+                string_vec!["if a; then b; elif c; then d; else e; fi"],
+            ),
             (
-                "echo /home/$tesrakijds",
-                vec![(Command, string_vec!["echo", "/home/hello"])],
-            ), // Test combine with one before
+                "while a\ndo b; done > out | c",
+                string_vec!["while a; do b; done >out | c"],
+            ),
+            ("until a; do b & done", string_vec!["until a; do b & done"]),
+            (
+                "for x in 1 \"2 3\"; do echo $x; done; for y\ndo if a; then b; fi; done",
+                string_vec![
+                    "for x in 1 \"2 3\"; do echo ${x}; done",
+                    "for y; do if a; then b; fi; done"
+                ],
+            ),
+            (
+                "case $a in\n(x|y) a;;\n*) ;;\nesac",
+                string_vec!["case ${a} in x | y) a ;; *) ;; esac"],
+            ),
+            (
+                "case a in x) b; esac",
+                string_vec!["case a in x) b ;; esac"],
+            ),
+            ("echo if then fi", string_vec!["echo if then fi"]),
+            // Functions
+            (
+                "f() {\n  echo $1\n}\nfunction g { a & } > log; function h() (b)",
+                string_vec!["f() { echo ${1}; }", "g() { a & } >log", "h() (b)"],
+            ),
+            (
+                "git-log ( ) if a; then b; fi",
+                string_vec!["git-log() if a; then b; fi"],
+            ),
+            ("(a; b) | { c; }", string_vec!["(a; b) | { c; }"]),
+            ("((a) | b)", string_vec!["((a) | b)"]),
+        ];
+        for (l, r) in v {
+            assert_eq!(commands(&parse_line(l).unwrap()), r);
+        }
+
+        for l in &[
+            "| ls",
+            "ls &&",
+            "ls | | ls",
+            "echo >",
+            "echo 'a",
+            "echo 2>",
+            "& ls",
+            "ls & &",
+            "echo ${a",
+            "echo ${a:-b",
+            "echo $(ls",
+            "echo `ls",
+            "echo $(ls |)",
+            "echo $((1 + 2",
+            "((1",
+            "echo ((1))",
+            "fi",
+            "if a; fi",
+            "if a; then fi",
+            "while a; do done",
+            "for 1x in a; do b; done",
+            "case a in x) b;; esac c",
+            "echo a;; echo b",
+            "echo )",
+            "f() echo",
+            "if() { a; }",
+            "{ a; } }",
+            "{ }",
+            "( )",
+        ] {
+            assert!(parse_line(l).is_err(), "{}", l);
+        }
+    }
+
+    #[test]
+    fn test_aliases() {
+        use super::parse_line_with_aliases;
+
+        let aliases = [
+            ("ll", "ls -l"),
+            ("ls", "ls -F"),
+            ("sudo", "sudo "),
+            ("a", "b"),
+            ("b", "a x"),
+            ("two", "echo 1; echo 2"),
+            ("cond", "if true; then"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let v = vec![
+            ("ll /tmp", string_vec!["ls -F -l /tmp"]),
+            ("echo ll; ll", string_vec!["echo ll", "ls -F -l"]),
+            ("sudo ll", string_vec!["sudo ls -F -l"]),
+            ("sudo \\ll", string_vec!["sudo 'l'l"]),
+            ("a", string_vec!["a x"]),
+            ("FOO=1 ll | ll", string_vec!["FOO=1 ls -F -l | ls -F -l"]),
+            ("two 3", string_vec!["echo 1", "echo 2 3"]),
+            ("cond ll; fi", string_vec!["if true; then ls -F -l; fi"]),
+        ];
+        for (l, r) in v {
+            assert_eq!(commands(&parse_line_with_aliases(l, &aliases).unwrap()), r);
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        use super::parse_line;
+        use super::SyntaxError::*;
+
+        let error = |line: &str| match parse_line(line) {
+            Err(Unexpected {
+                span,
+                found,
+                expected,
+            }) => (span, found, expected.unwrap_or_default()),
+            other => panic!("{}: {:?}", line, other),
+        };
+        assert_eq!(
+            error("a | | b"),
+            ((4, 5), "`|`".to_string(), "a command".to_string())
+        );
+        assert_eq!(
+            error("if a; then fi"),
+            ((11, 13), "`fi`".to_string(), "a command".to_string())
+        );
+        assert_eq!(
+            error("echo >\nls"),
+            ((6, 7), "newline".to_string(), "a file name".to_string())
+        );
+        assert_eq!(error("echo $(a && ;)").0, (12, 13));
+        assert_eq!(error("echo $((1 + $(a | | b)))").0, (18, 19));
+        assert_eq!(error("echo `a | | b`").0, (5, 14));
+        assert_eq!(error("case a in x) b;; esac )").1, "`)`");
+
+        let input = "echo one\n\techo | | two\nfi";
+        let err = parse_line(input).unwrap_err();
+        assert_eq!(err.line(input), 2);
+        assert_eq!(
+            err.show(input),
+            "syntax error: unexpected `|`, expected a command\n\techo | | two\n\t       ^"
+        );
+        // Wide chars take two columns
+        let input = "echo é日本 | | b";
+        assert!(parse_line(input)
+            .unwrap_err()
+            .show(input)
+            .ends_with("\n             ^"));
+
+        // Unfinished commands are shown where they start
+        let incomplete = |line: &str| parse_line(line).unwrap_err().show(line);
+        let input = "echo a\nif true; then\n  echo x";
+        assert_eq!(parse_line(input).unwrap_err().line(input), 2);
+        assert_eq!(
+            incomplete(input),
+            "syntax error: unexpected end of input, expected `fi`\nif true; then\n^^"
+        );
+        assert_eq!(
+            incomplete("if a; then while b; do c; done | d &&"),
+            "syntax error: unexpected end of input, expected a command\n\
+             if a; then while b; do c; done | d &&\n                                   ^^"
+        );
+        assert_eq!(
+            incomplete("for x in a; do\n(ls"),
+            "syntax error: unexpected end of input, expected `)`\n(ls\n^"
+        );
+        assert_eq!(
+            incomplete("echo $(if a)"),
+            "syntax error: unexpected `)`, expected `then`\necho $(if a)\n           ^"
+        );
+        assert!(incomplete("cat <<EOF\nhi").starts_with(
+            "syntax error: unexpected end of input, expected `EOF`\ncat <<EOF\n      ^^^"
+        ));
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        use super::is_incomplete;
+
+        for l in &[
+            "if a; then",
+            "if a; then b\nelse",
+            "while a\ndo",
+            "for x in a b",
+            "case a in",
+            "case a in x) b;;",
+            "for i in 1; do while b; do c; done",
+            "f() {",
+            "f()\n",
+            "(a\n",
+            "echo 'a",
+            "echo \"a\nb",
+            "ls |",
+            "a &&",
+            "echo a \\",
+            "cat <<EOF\nhello",
+            "{ echo",
+            "echo $(ls",
+            "((a)$((b",
+        ] {
+            assert!(is_incomplete(l), "{}", l);
+        }
+        for l in &[
+            "if a; then b; fi",
+            "case a in esac",
+            "for x; do b; done",
+            "fi",
+            "echo 'a\nb'",
+            "cat <<EOF\nhello\nEOF",
+        ] {
+            assert!(!is_incomplete(l), "{}", l);
+        }
+    }
+
+    #[test]
+    fn test_parser() {
+        use super::parse_line;
+        use super::Command;
+        use crate::expand;
+        use crate::shell::Shell;
+
+        let v = vec![
+            ("echo hello world", string_vec!["echo", "hello", "world"]), // Trim input
+            ("echo $tesrakijds", string_vec!["echo", "hello"]), // Test enviroment variables
+            ("echo /home/$tesrakijds", string_vec!["echo", "/home/hello"]), // Test combine with one before
             (
                 "echo $tesrakijds/.config",
-                vec![(Command, string_vec!["echo", "hello/.config"])],
+                string_vec!["echo", "hello/.config"],
             ), // Test combine with one after
             (
                 "echo /home/$tesrakijds/.config",
-                vec![(Command, string_vec!["echo", "/home/hello/.config"])],
+                string_vec!["echo", "/home/hello/.config"],
             ), // Test combine with one before & one after
+            ("echo 'hello world'", string_vec!["echo", "hello world"]),     // Single quotes
+            ("echo \"hello world\"", string_vec!["echo", "hello world"]),   // Double Quotes
+            ("echo hello\\ world", string_vec!["echo", "hello world"]),     // Escaped space
+            ("TEST=$tesrakijds:/root/.config", string_vec![]), // Define variable with another variable
+            ("echo ${tesrakijds}s", string_vec!["echo", "hellos"]),
+            (
+                "echo ${tesrakijds:-x} ${unsetrakijds:-def} ${unsetrakijds:+alt}",
+                string_vec!["echo", "hello", "def"],
+            ),
+            ("echo \"${unsetrakijds:-a b}\"", string_vec!["echo", "a b"]),
+            ("echo ${#tesrakijds}", string_vec!["echo", "5"]),
+            (
+                "echo ${tesrakijds#he} ${tesrakijds%l*} ${tesrakijds%%l*}",
+                string_vec!["echo", "llo", "hel", "he"],
+            ),
+            (
+                "echo ${tesrakijds/l/L} ${tesrakijds//l/L} ${tesrakijds/#h/H} ${tesrakijds/%o/O}",
+                string_vec!["echo", "heLlo", "heLLo", "Hello", "hellO"],
+            ),
             (
-                "echo 'hello world'",
-                vec![(Command, string_vec!["echo", "hello world"])],
-            ), // Single quotes
+                "echo ${tesrakijds:1:3} ${tesrakijds: -2} ${tesrakijds^^} ${tesrakijds^}",
+                string_vec!["echo", "ell", "lo", "HELLO", "Hello"],
+            ),
             (
-                "echo \"hello world\"",
-                vec![(Command, string_vec!["echo", "hello world"])],
-            ), // Double Quotes
+                "echo $(echo a  b) \"$(printf 'a  b\\n\\n')\" x$(echo)y",
+                string_vec!["echo", "a", "b", "a  b", "xy"],
+            ),
             (
-                "echo hello\\ world",
-                vec![(Command, string_vec!["echo", "hello world"])],
-            ), // Escaped space
-            ("TEST=$tesrakijds:/root/.config", vec![]), // Define variable with another variable
+                "echo $((1 + 2 * 3)) $((2 ** 10)) $((7 > 3 ? 4 : 5)) ${tesrakijds:1+1:4/2}",
+                string_vec!["echo", "7", "1024", "4", "ll"],
+            ),
+            // Double quotes are removed from the expression
+            (
+                "echo $(( \"4\" + 1 )) $(( \"4 + 1\" * 2 )) $(( \"$tesrakijds\" == \"$tesrakijds\" ))",
+                string_vec!["echo", "5", "6", "1"],
+            ),
         ];
 
         std::env::set_var("tesrakijds", "hello"); // Random name, for enviroment variables test
+        let mut shell = Shell::new();
         for (l, r) in v {
-            assert_eq!(parse_cmd(l.to_string(), 0).unwrap(), r);
+            let list = parse_line(l).unwrap();
+            let Command::Simple(simple) = &list.items[0].and_or.first.commands[0] else {
+                unreachable!()
+            };
+            assert_eq!(expand::expand_words(&mut shell, &simple.words).unwrap(), r);
         }
+
+        let list = parse_line("TEST=$tesrakijds:/root/.config").unwrap();
+        let Command::Simple(simple) = &list.items[0].and_or.first.commands[0] else {
+            unreachable!()
+        };
+        assert_eq!(simple.assignments[0].name, "TEST");
+        assert_eq!(
+            expand::expand_word_to_string(&mut shell, &simple.assignments[0].value).unwrap(),
+            "hello:/root/.config"
+        );
+
+        let list = parse_line("echo $((1 / 0))").unwrap();
+        let Command::Simple(simple) = &list.items[0].and_or.first.commands[0] else {
+            unreachable!()
+        };
+        assert!(expand::expand_words(&mut shell, &simple.words).is_err());
     }
 }
@@ -1,19 +1,85 @@
-#[derive(Clone, Copy, Eq, PartialEq, Debug)]
-pub enum LineToCmdTokens {
-    Command,
-    Separator,
+use super::ast::Word;
+
+// Byte offsets into the input, start..end
+pub type Span = (usize, usize);
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
 }
 
-#[derive(Clone, Copy, Eq, PartialEq, Debug)]
-pub enum CmdTokens {
-    Normal,
-    Pipe,
-    Definition,
-    Variable,
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum TokenKind {
+    Word(Word),
+    Operator(Operator),
+    // The fd before a redirection, 2 in "2>&1"
+    IoNumber(i32),
+    // (( expr )) at the start of a command
+    Arithmetic(Word),
+    Newline,
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
-pub enum ParseCmdTokens {
-    Command,
-    Separator,
-}
\ No newline at end of file
+pub enum Operator {
+    AndIf,     // &&
+    OrIf,      // ||
+    Pipe,      // |
+    Semi,      // ;
+    DSemi,     // ;;
+    Amp,       // &
+    Less,      // <
+    Great,     // >
+    DGreat,    // >>
+    LessAnd,   // <&
+    GreatAnd,  // >&
+    LessGreat, // <>
+    Clobber,   // >|
+    AndGreat,  // &>
+    AndDGreat, // &>>
+    DLess,     // <<
+    DLessDash, // <<-
+    TLess,     // <<<
+    LParen,    // (
+    RParen,    // )
+}
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        use Operator::*;
+        match self {
+            AndIf => "&&",
+            OrIf => "||",
+            Pipe => "|",
+            Semi => ";",
+            DSemi => ";;",
+            Amp => "&",
+            Less => "<",
+            Great => ">",
+            DGreat => ">>",
+            LessAnd => "<&",
+            GreatAnd => ">&",
+            LessGreat => "<>",
+            Clobber => ">|",
+            AndGreat => "&>",
+            AndDGreat => "&>>",
+            DLess => "<<",
+            DLessDash => "<<-",
+            TLess => "<<<",
+            LParen => "(",
+            RParen => ")",
+        }
+    }
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TokenKind::Word(word) => write!(f, "{}", word),
+            TokenKind::Operator(op) => write!(f, "{}", op.as_str()),
+            TokenKind::IoNumber(fd) => write!(f, "{}", fd),
+            TokenKind::Arithmetic(expr) => write!(f, "(({}))", expr),
+            TokenKind::Newline => write!(f, "newline"),
+        }
+    }
+}
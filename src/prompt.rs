@@ -0,0 +1,362 @@
+// The prompts. PS1 and RPROMPT are shown before a command, PS2 on the lines
+// after the first one and PS4 before each command "set -x" prints.
+// A zash_prompt function replaces PS1, whatever it prints is the prompt.
+//
+// Escapes:
+//   %n user            %m host          %M full host name
+//   %~ directory, with ~ for home       %/ or %d full directory
+//   %c or %. last part of the directory
+//   %? exit status     %s exit status, or the signal like SIGSEGV
+//   %E how long the last command took   %j number of jobs
+//   %T 14:05           %* 14:05:09      %D 21-11-30, %D{%A} any strftime format
+//   %g git branch      %# # for root, % for everyone else
+//   %G git status, like "main ↑1↓2 +3 !4 REBASE 2/5" for the branch, commits
+//      ahead and behind upstream, staged and changed files, and a rebase or
+//      merge in progress
+//   %(?.yes.no) "yes" if the last command succeeded, otherwise "no". Also
+//      %(j..) if there are jobs, %(#..) for root, %(g..) in a git repository
+//      and %(E..) if the last command took at least duration_threshold
+//   %F{red} %F{208} foreground color, %f back to the default
+//   %B bold, %b not bold anymore        %% a %, %) a )
+use std::ffi::CString;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::time::Duration;
+
+use crate::git;
+use crate::parsers;
+use crate::shell::Shell;
+use crate::utils;
+
+const DEFAULT_PS1: &str = concat!(
+    "%F{blue}%n%f@%F{blue}%m%f %F{cyan}%~%f %(g.%F{magenta}%G%f .)",
+    "%(E.%F{yellow}%E%f .)%(?..%F{red}✘ %s%f )",
+    "%F{blue}•%f%F{red}•%f%F{yellow}•%f ",
+);
+const DEFAULT_PS2: &str = "> ";
+const DEFAULT_PS4: &str = "+ ";
+
+const COLORS: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+// fallback is the prompt from the config file, for when PS1 is not set
+pub fn ps1(shell: &mut Shell, fallback: Option<&str>) -> String {
+    if shell.functions.contains_key("zash_prompt") {
+        if let Some(prompt) = prompt_function(shell) {
+            return prompt;
+        }
+    }
+    let format = match shell.variables.get("PS1") {
+        Some(ps1) => ps1.to_string(),
+        None => fallback.unwrap_or(DEFAULT_PS1).to_string(),
+    };
+    expand(shell, &format)
+}
+
+pub fn ps2(shell: &Shell) -> String {
+    expand(shell, shell.variables.get("PS2").unwrap_or(DEFAULT_PS2))
+}
+
+pub fn ps4(shell: &Shell) -> String {
+    expand(shell, shell.variables.get("PS4").unwrap_or(DEFAULT_PS4))
+}
+
+pub fn rprompt(shell: &Shell) -> String {
+    expand(shell, shell.variables.get("RPROMPT").unwrap_or(""))
+}
+
+// Runs zash_prompt in a subshell, like $(zash_prompt). $? is left alone.
+fn prompt_function(shell: &mut Shell) -> Option<String> {
+    let list = parsers::parser::parse_line("zash_prompt").ok()?;
+    let status = shell.status;
+    let output = shell.capture(&list);
+    shell.status = status;
+    shell.substitution_status = None;
+    let output = output.ok()?;
+    Some(output.strip_suffix('\n').unwrap_or(&output).to_string())
+}
+
+pub fn expand(shell: &Shell, format: &str) -> String {
+    let mut prompt = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            prompt.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => prompt.push_str(&whoami::username()),
+            Some('m') => prompt.push_str(hostname().split('.').next().unwrap_or_default()),
+            Some('M') => prompt.push_str(&hostname()),
+            Some('~') => prompt.push_str(&current_dir(shell, true)),
+            Some('/') | Some('d') => prompt.push_str(&current_dir(shell, false)),
+            Some('c') | Some('.') => {
+                let dir = current_dir(shell, true);
+                match dir.rsplit_once('/') {
+                    Some((_, last)) if !last.is_empty() => prompt.push_str(last),
+                    _ => prompt.push_str(&dir),
+                }
+            }
+            Some('?') => prompt.push_str(&shell.status.to_string()),
+            Some('s') => prompt.push_str(&status_text(shell.status)),
+            Some('E') => {
+                if let Some(duration) = shell.last_duration {
+                    prompt.push_str(&format_duration(duration));
+                }
+            }
+            Some('j') => prompt.push_str(&shell.jobs.iter().count().to_string()),
+            Some('T') => prompt.push_str(&strftime("%H:%M")),
+            Some('*') => prompt.push_str(&strftime("%H:%M:%S")),
+            Some('D') => {
+                let format = argument(&mut chars);
+                prompt.push_str(&strftime(format.as_deref().unwrap_or("%y-%m-%d")));
+            }
+            Some('g') => {
+                let head = std::env::current_dir().ok().and_then(|dir| git::head(&dir));
+                prompt.push_str(&head.unwrap_or_default());
+            }
+            Some('G') => {
+                let status = std::env::current_dir()
+                    .ok()
+                    .and_then(|dir| git::status(&dir));
+                if let Some(status) = status {
+                    prompt.push_str(&status.to_string());
+                }
+            }
+            Some('(') => {
+                // Left as it is when there is no closing paren
+                let mut rest = chars.clone();
+                match conditional(&mut rest) {
+                    Some((condition, yes, no)) => {
+                        let text = if test(shell, condition) { yes } else { no };
+                        prompt.push_str(&expand(shell, &text));
+                        chars = rest;
+                    }
+                    None => prompt.push_str("%("),
+                }
+            }
+            Some('#') if unsafe { libc::geteuid() } == 0 => prompt.push('#'),
+            Some('#') => prompt.push('%'),
+            Some('F') => {
+                if let Some(code) = argument(&mut chars).as_deref().and_then(color) {
+                    prompt.push_str(&format!("\x1b[{}m", code));
+                }
+            }
+            Some('f') => prompt.push_str("\x1b[39m"),
+            Some('B') => prompt.push_str("\x1b[1m"),
+            Some('b') => prompt.push_str("\x1b[22m"),
+            Some('%') => prompt.push('%'),
+            Some(')') => prompt.push(')'),
+            Some(other) => {
+                prompt.push('%');
+                prompt.push(other);
+            }
+            None => prompt.push('%'),
+        }
+    }
+    prompt
+}
+
+// "%(?.yes.no)" is split into '?', "yes" and "no". Any character can
+// separate them instead of the dot, and nested conditionals are skipped over.
+fn conditional(chars: &mut Peekable<Chars>) -> Option<(char, String, String)> {
+    let condition = chars.next()?;
+    let separator = chars.next()?;
+    let mut parts = vec![String::new()];
+    let mut depth = 0;
+    let mut escaped = false;
+    for c in chars.by_ref() {
+        if escaped {
+            escaped = false;
+            depth += (c == '(') as usize;
+        } else if c == '%' {
+            escaped = true;
+        } else if depth == 0 && c == separator && parts.len() == 1 {
+            parts.push(String::new());
+            continue;
+        } else if c == ')' {
+            if depth == 0 {
+                let no = parts.pop()?;
+                let yes = parts.pop().unwrap_or_default();
+                return Some((condition, yes, no));
+            }
+            depth -= 1;
+        }
+        parts.last_mut()?.push(c);
+    }
+    None
+}
+
+fn test(shell: &Shell, condition: char) -> bool {
+    match condition {
+        '?' => shell.status == 0,
+        'E' => shell
+            .last_duration
+            .is_some_and(|duration| duration >= shell.duration_threshold),
+        'j' => shell.jobs.iter().next().is_some(),
+        '#' => (unsafe { libc::geteuid() }) == 0,
+        'g' => std::env::current_dir()
+            .ok()
+            .and_then(|dir| git::head(&dir))
+            .is_some(),
+        _ => false,
+    }
+}
+
+// "SIGSEGV" for a command killed by that signal, the number otherwise
+fn status_text(status: i32) -> String {
+    match utils::signal_name(status - 128) {
+        Some(name) if status > 128 => name.to_string(),
+        _ => status.to_string(),
+    }
+}
+
+// "850ms", "12s", "3m05s", "1h02m"
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds == 0 {
+        format!("{}ms", duration.as_millis())
+    } else if seconds < 60 {
+        format!("{}s", seconds)
+    } else if seconds < 3600 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60)
+    }
+}
+
+// The "red" in "%F{red}"
+fn argument(chars: &mut Peekable<Chars>) -> Option<String> {
+    if chars.peek() != Some(&'{') {
+        return None;
+    }
+    chars.next();
+    Some(chars.by_ref().take_while(|&c| c != '}').collect())
+}
+
+// "red" is 31, "208" is 38;5;208
+pub fn color(name: &str) -> Option<String> {
+    if let Some(index) = COLORS.iter().position(|&color| color == name) {
+        return Some((30 + index).to_string());
+    }
+    name.parse::<u8>()
+        .ok()
+        .map(|number| format!("38;5;{}", number))
+}
+
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } < 0 {
+        return String::new();
+    }
+    let len = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+fn current_dir(shell: &Shell, tilde: bool) -> String {
+    let dir = match std::env::current_dir() {
+        Ok(dir) => dir.display().to_string(),
+        Err(_) => return shell.variables.get("PWD").unwrap_or("?").to_string(),
+    };
+    let home = match shell.variables.get("HOME") {
+        Some(home) if tilde && !home.is_empty() => home.trim_end_matches('/'),
+        _ => return dir,
+    };
+    match dir.strip_prefix(home) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{}", rest),
+        _ => dir,
+    }
+}
+
+fn strftime(format: &str) -> String {
+    let format = match CString::new(format) {
+        Ok(format) => format,
+        Err(_) => return String::new(),
+    };
+    let mut buffer = [0u8; 256];
+    let len = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        libc::strftime(
+            buffer.as_mut_ptr() as *mut libc::c_char,
+            buffer.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+// The prompt with rprompt at the right edge of the terminal. Moving the cursor
+// there and back keeps it out of the width the line editor counts.
+pub fn with_rprompt(prompt: &str, rprompt: &str) -> String {
+    let columns = terminal_columns();
+    let width = visible_width(rprompt);
+    if rprompt.is_empty() || width + visible_width(prompt) >= columns {
+        return prompt.to_string();
+    }
+    format!(
+        "{}\x1b7\x1b[{}G{}\x1b8",
+        prompt,
+        columns - width + 1,
+        rprompt
+    )
+}
+
+fn terminal_columns() -> usize {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } < 0 {
+        return 80;
+    }
+    size.ws_col as usize
+}
+
+// Characters without the color escapes
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // "\x1b[31m", up to the letter
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let mut shell = Shell::new();
+        shell.status = 3;
+        assert_eq!(expand(&shell, "%? %% 100%"), "3 % 100%");
+        assert_eq!(
+            expand(&shell, "%F{red}x%f%B%b"),
+            "\x1b[31mx\x1b[39m\x1b[1m\x1b[22m"
+        );
+        assert_eq!(expand(&shell, "%F{208}%F{nope}"), "\x1b[38;5;208m");
+        assert_eq!(expand(&shell, "%j"), "0");
+        assert_eq!(expand(&shell, "%D{[%%]}"), "[%]");
+        assert_eq!(expand(&shell, "%(?.ok.%F{red}%?%f)"), "\x1b[31m3\x1b[39m");
+        assert_eq!(expand(&shell, "%(j.jobs.)|%(?.a.(%(j.x.y)%))"), "|(y)");
+        assert_eq!(expand(&shell, "%(?.unterminated"), "%(?.unterminated");
+
+        shell.status = 139;
+        assert_eq!(expand(&shell, "%? %s"), "139 SIGSEGV");
+        shell.status = 200;
+        assert_eq!(expand(&shell, "%s"), "200");
+        assert_eq!(expand(&shell, "[%E]%(E.long.)"), "[]");
+        shell.last_duration = Some(Duration::from_millis(65_500));
+        assert_eq!(expand(&shell, "[%E]%(E.long.)"), "[1m05s]long");
+        shell.last_duration = Some(Duration::from_millis(20));
+        assert_eq!(expand(&shell, "[%E]%(E.long.)"), "[20ms]");
+        assert_eq!(visible_width(&expand(&shell, "%F{red}abc%f")), 3);
+    }
+}
@@ -0,0 +1,194 @@
+// File descriptor redirections, "> file", "2>&1", "3<&-" etc.
+// External commands get them applied in the child before exec, builtins get
+// them applied to the shell itself and restored afterwards, except for exec
+// which keeps them.
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::expand;
+use crate::parsers::ast::{Redirect, RedirectKind};
+use crate::shell::Shell;
+use crate::utils;
+
+// Fds the shell opens itself are moved up here, so "3>a 4>b" can't clash with them
+const SHELL_FD_BASE: RawFd = 10;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+    // dup2(source, fd)
+    Dup(RawFd),
+    Close,
+}
+
+#[derive(Default)]
+pub struct Redirections {
+    pub actions: Vec<(RawFd, Action)>,
+    // The opened files have to live until the actions are applied
+    files: Vec<File>,
+}
+
+fn os_error(err: io::Error) -> String {
+    match err.raw_os_error() {
+        Some(errno) => utils::error_string(errno),
+        None => err.to_string(),
+    }
+}
+
+fn move_fd_up(fd: RawFd) -> io::Result<RawFd> {
+    let new_fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, SHELL_FD_BASE) };
+    if new_fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(new_fd)
+}
+
+fn open(path: &str, kind: RedirectKind) -> Result<File, String> {
+    let mut options = OpenOptions::new();
+    match kind {
+        RedirectKind::Input => options.read(true),
+        RedirectKind::ReadWrite => options.read(true).write(true).create(true),
+        RedirectKind::Append | RedirectKind::AppendAll => options.append(true).create(true),
+        _ => options.write(true).create(true).truncate(true),
+    };
+    let file = options
+        .open(path)
+        .map_err(|err| format!("{}: {}", path, os_error(err)))?;
+    let fd = move_fd_up(file.as_raw_fd()).map_err(os_error)?;
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+static HERE_DOC_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// The content of a here-document or here-string is read from a temporary file
+// that is deleted right after it is created
+fn here_doc_file(content: &str) -> Result<File, String> {
+    let path = std::env::temp_dir().join(format!(
+        "zash-{}-{}",
+        std::process::id(),
+        HERE_DOC_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .map_err(|err| format!("here-document: {}", os_error(err)))?;
+    std::fs::remove_file(&path).ok();
+    file.write_all(content.as_bytes())
+        .and_then(|_| file.seek(SeekFrom::Start(0)))
+        .map_err(|err| format!("here-document: {}", os_error(err)))?;
+    let fd = move_fd_up(file.as_raw_fd()).map_err(os_error)?;
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+// Expands the targets and opens the files, nothing is changed in the shell yet
+pub fn prepare(shell: &mut Shell, redirects: &[Redirect]) -> Result<Redirections, String> {
+    let mut result = Redirections {
+        actions: Vec::new(),
+        files: Vec::new(),
+    };
+    for redirect in redirects {
+        let fd = redirect.fd.unwrap_or_else(|| redirect.kind.default_fd());
+        let content = match (redirect.kind, &redirect.here_doc) {
+            (RedirectKind::HereString, _) => {
+                Some(expand::expand_word_to_string(shell, &redirect.target)? + "\n")
+            }
+            (_, Some(body)) => Some(expand::expand_word_to_string(shell, body)?),
+            _ => None,
+        };
+        if let Some(content) = content {
+            let file = here_doc_file(&content)?;
+            result.actions.push((fd, Action::Dup(file.as_raw_fd())));
+            result.files.push(file);
+            continue;
+        }
+        let target =
+            match expand::expand_words(shell, std::slice::from_ref(&redirect.target))?.as_slice() {
+                [target] => target.clone(),
+                _ => return Err(format!("{}: ambiguous redirect", redirect.target)),
+            };
+        let mut kind = redirect.kind;
+        if matches!(kind, RedirectKind::DupInput | RedirectKind::DupOutput) {
+            if target == "-" {
+                result.actions.push((fd, Action::Close));
+                continue;
+            }
+            if let Ok(source) = target.parse::<RawFd>() {
+                result.actions.push((fd, Action::Dup(source)));
+                continue;
+            }
+            // ">&file" is the same as "&>file"
+            if kind == RedirectKind::DupInput || redirect.fd.is_some() {
+                return Err(format!("{}: ambiguous redirect", target));
+            }
+            kind = RedirectKind::OutputAll;
+        }
+        let file = open(&target, kind)?;
+        result.actions.push((fd, Action::Dup(file.as_raw_fd())));
+        if matches!(kind, RedirectKind::OutputAll | RedirectKind::AppendAll) {
+            result.actions.push((2, Action::Dup(fd)));
+        }
+        result.files.push(file);
+    }
+    Ok(result)
+}
+
+// Only calls dup2 and close so it is safe to use between fork and exec
+pub fn apply_actions(actions: &[(RawFd, Action)]) -> io::Result<()> {
+    for (fd, action) in actions {
+        let ret = match action {
+            Action::Dup(source) if source == fd => 0,
+            Action::Dup(source) => unsafe { libc::dup2(*source, *fd) },
+            Action::Close => unsafe { libc::close(*fd) },
+        };
+        if ret < 0 && !matches!(action, Action::Close) {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// The fds a builtin had before its redirections, put back when dropped
+pub struct SavedFds(Vec<(RawFd, Option<RawFd>)>);
+
+impl Redirections {
+    // Applies the redirections to the shell itself, for builtins
+    pub fn apply(&self) -> Result<SavedFds, String> {
+        io::stdout().flush().ok();
+        let mut saved = SavedFds(Vec::new());
+        for (fd, action) in &self.actions {
+            saved.0.push((*fd, move_fd_up(*fd).ok()));
+            apply_actions(&[(*fd, *action)]).map_err(|err| format!("{}: {}", fd, os_error(err)))?;
+        }
+        Ok(saved)
+    }
+
+    // Applies them to the shell for good, for "exec 3>file"
+    pub fn apply_permanently(&self) -> Result<(), String> {
+        io::stdout().flush().ok();
+        apply_actions(&self.actions).map_err(os_error)
+    }
+}
+
+impl Drop for SavedFds {
+    fn drop(&mut self) {
+        io::stdout().flush().ok();
+        for (fd, saved) in self.0.iter().rev() {
+            unsafe {
+                match saved {
+                    Some(saved) => {
+                        libc::dup2(*saved, *fd);
+                        libc::close(*saved);
+                    }
+                    None => {
+                        libc::close(*fd);
+                    }
+                }
+            }
+        }
+    }
+}
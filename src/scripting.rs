@@ -1,3 +1,5 @@
+use crate::exec::Flow;
+use crate::parsers;
 use crate::shell;
 use crate::utils;
 use std::fs::File;
@@ -6,35 +8,130 @@ use std::io::prelude::*;
 use std::io::{self, BufRead};
 use std::path::Path;
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where
-    P: AsRef<Path>,
-{
-    Ok(io::BufReader::new(File::open(filename)?).lines())
+pub fn run_file(shell: &mut shell::Shell, filename: String) -> io::Result<()> {
+    run_file_in(shell, filename)?;
+    Ok(())
+}
+
+// For "zash < script" and "cmd | zash"
+pub fn run_stdin(shell: &mut shell::Shell) {
+    run_lines(shell, io::stdin().lock(), "stdin");
 }
 
-pub fn run_file(filename: String) -> std::io::Result<()> {
-    let mut shell = shell::Shell::new();
-    for line in (read_lines(filename)?).flatten() {
-        shell.run_line(line);
+fn run_file_in(shell: &mut shell::Shell, filename: String) -> io::Result<i32> {
+    let file = File::open(&filename)?;
+    Ok(run_lines(shell, io::BufReader::new(file), &filename))
+}
+
+// Runs the commands in an existing shell, a command is run before the next
+// line is parsed so aliases work. Returns the status of the last command.
+// The name is shown in syntax errors with the line number.
+fn run_lines(shell: &mut shell::Shell, reader: impl BufRead, name: &str) -> i32 {
+    let mut status = 0;
+    let mut buffer = String::new();
+    // The line of the file the buffer starts at
+    let mut first_line = 1;
+    for (number, line) in reader.lines().map_while(Result::ok).enumerate() {
+        if buffer.is_empty() {
+            first_line = number + 1;
+        }
+        buffer.push_str(&line);
+        buffer.push('\n');
+        // Keep reading lines for here-documents, open quotes and such
+        if parsers::parser::is_incomplete(&buffer) {
+            continue;
+        }
+        shell.run_text(std::mem::take(&mut buffer), Some((name, first_line)));
+        status = shell.status;
+        // "return" in a sourced file, or Ctrl-C
+        if shell.flow.is_some() {
+            return status;
+        }
     }
-    Ok(())
+    if !buffer.is_empty() {
+        shell.run_text(buffer, Some((name, first_line)));
+        status = shell.status;
+    }
+    status
+}
+
+// Runs the file in the current shell, for "source" and the rc file.
+// "return" stops the file.
+pub fn source(shell: &mut shell::Shell, filename: String) -> std::io::Result<i32> {
+    shell.source_depth += 1;
+    let status = run_file_in(shell, filename);
+    shell.source_depth -= 1;
+    if shell.flow == Some(Flow::Return) {
+        shell.flow = None;
+    }
+    status
 }
 
-pub fn load_rc(homedir: String) {
-    let rcpath = format!("{}/.zashrc", homedir);
-    if !Path::new(&rcpath).exists() {
-        let welcometext = "Welcome to zash";
-        println!("{}", welcometext);
-        let mut file = match OpenOptions::new().create_new(true).write(true).open(rcpath) {
-            Ok(m) => m,
-            Err(err) => {
-                utils::zash_error(err);
-                return;
-            }
-        };
-        writeln!(file, "echo {}", welcometext).unwrap();
-    } else if let Err(err) = run_file(rcpath.to_string()) {
-        utils::zash_error(format!("{}: {}", rcpath, err));
-    };
+// Login shells read the profile files, interactive shells the rc files and
+// the other ones the file in $ZASH_ENV. Missing files are skipped.
+pub fn load_startup_files(shell: &mut shell::Shell, login: bool, profile: bool, rc: bool) {
+    let homedir = utils::get_home_dir();
+    let mut files = Vec::new();
+    if login && profile {
+        files.push("/etc/zash/zprofile".to_string());
+        files.push(format!("{}/.zash_profile", homedir));
+    }
+    if shell.interactive && rc {
+        files.push("/etc/zash/zashrc".to_string());
+        files.push(format!("{}/.zashrc", homedir));
+    }
+    if !shell.interactive {
+        if let Some(env) = std::env::var("ZASH_ENV").ok().filter(|env| !env.is_empty()) {
+            files.push(env);
+        }
+    }
+    for file in files {
+        if !Path::new(&file).exists() {
+            continue;
+        }
+        if let Err(err) = source(shell, file.clone()) {
+            utils::zash_error(format!("{}: {}", file, err));
+        }
+    }
+}
+
+// What "zash --init-config" writes to ~/.zashrc
+const RC_TEMPLATE: &str = "\
+# ~/.zashrc, read by interactive shells
+
+# A welcome message
+# echo Welcome to zash
+
+# Prompt
+# PS1='%n@%m %~ %# '
+# RPROMPT='%T'
+# Or a function that prints the whole prompt
+# zash_prompt() { echo \"$PWD > \"; }
+# Runs after commands that took longer than duration_threshold in config.toml
+# zash_notify() { notify-send \"Done ($2): $1\"; }
+
+# History
+# HISTSIZE=1000
+# HISTFILE=~/.zash_history
+
+# Key bindings, emacs or vi,
+# are set with edit_mode in ~/.config/zash/config.toml
+
+# Aliases
+# alias ll='ls -l'
+
+# Completions, more can go in ~/.config/zash/completions, fish ones too
+# complete -W 'start stop status' -f svc
+";
+
+// Writes a template rc file, but never over an existing one
+pub fn init_config() -> io::Result<String> {
+    let rcpath = format!("{}/.zashrc", utils::get_home_dir());
+    let mut file = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(&rcpath)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", rcpath, err)))?;
+    file.write_all(RC_TEMPLATE.as_bytes())?;
+    Ok(rcpath)
 }
@@ -1,139 +1,141 @@
 use colored::Colorize;
-use parsers::tokens::*;
-use rustyline::completion::{Completer, Pair, ShellCompleter};
-use rustyline::config::OutputStreamType;
+use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
-use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
+use rustyline::highlight::Highlighter;
 use rustyline::hint::{Hinter, HistoryHinter};
-use rustyline::validate::{MatchingBracketValidator, Validator};
-use rustyline::{CompletionType, Config, Context, EditMode, Editor};
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Config, Context, Editor};
 use rustyline_derive::Helper;
 use std::borrow::Cow::{self, Borrowed, Owned};
-use std::process::{Child, Command, Stdio};
-// use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use crate::builtins;
-use crate::parsers;
-use crate::scripting;
+use crate::completion::{self, CompletionSpec, ShellCompleter};
+use crate::config::{self, Duplicates};
+use crate::exec::Flow;
+use crate::highlight::ShellHighlighter;
+use crate::history::{self, Entry, HistoryFile};
+use crate::jobs::Jobs;
+use crate::parsers::{self, ast};
+use crate::prompt;
+use crate::terminal::{self, Modes};
 use crate::utils;
+use crate::variables::Variables;
 
 #[derive(Debug, Clone)]
 pub struct Shell {
-    // pub variables: HashMap<String, String>,
+    pub variables: Variables,
     pub status: i32,
+    pub jobs: Jobs,
+    // $!
+    pub last_background_pid: Option<i32>,
+    // Prints job numbers and notifications, only for the prompt
+    pub interactive: bool,
+    // Every pipeline gets its own process group and the terminal when in the foreground
+    pub job_control: bool,
+    // The process group of the shell itself
+    pub pgid: i32,
+    // Given back to the terminal when a job is stopped or killed
+    pub terminal_modes: Option<Modes>,
+    // Status of the last $(...) in the current command
+    pub substitution_status: Option<i32>,
+    // Number of loops the current command is in, for break and continue
+    pub loop_depth: usize,
+    pub flow: Option<Flow>,
+    pub functions: HashMap<String, Rc<ast::Command>>,
+    // Number of function calls the current command is in, for return
+    pub function_depth: usize,
+    // Number of files being run with "source"
+    pub source_depth: usize,
+    // $0 and $1, $2...
+    pub arg0: String,
+    pub positional: Vec<String>,
+    pub aliases: BTreeMap<String, String>,
+    // What to complete for the arguments of commands, from the complete builtin
+    pub completions: BTreeMap<String, CompletionSpec>,
+    // set -x, prints commands before running them
+    pub xtrace: bool,
+    // How long the last command from the prompt took
+    pub last_duration: Option<Duration>,
+    // Longer commands show their duration in the prompt and run zash_notify
+    pub duration_threshold: Duration,
 }
 
 impl Shell {
     pub fn new() -> Self {
         Self {
-            // variables: HashMap::new()
+            variables: Variables::from_env(),
             status: 0,
+            jobs: Jobs::default(),
+            last_background_pid: None,
+            interactive: false,
+            job_control: false,
+            pgid: 0,
+            terminal_modes: None,
+            substitution_status: None,
+            loop_depth: 0,
+            flow: None,
+            functions: HashMap::new(),
+            function_depth: 0,
+            source_depth: 0,
+            arg0: "zash".to_string(),
+            positional: Vec::new(),
+            aliases: BTreeMap::new(),
+            completions: BTreeMap::new(),
+            xtrace: false,
+            last_duration: None,
+            duration_threshold: Duration::from_secs(5),
         }
     }
 
-    pub fn run_line(&mut self, line: String) {
-        let mut sep = String::new();
-        for token in parsers::lexer::line_to_cmds(line.trim()) {
-            if token.0 == LineToCmdTokens::Separator {
-                sep = token.1.clone();
-                continue;
-            }
-            // "&& "Don't run the other commands if the one before failed
-            //
-            // "||" = "Or"
-            // "ls || dir"
-            // If ls does not succed it runs "dir"
-            // If ls succed it does not run dir
-            if (sep == "&&" && self.status != 0) || (sep == "||" && self.status == 0) {
-                break;
-            }
-            self.status = Self::exec_command(self, token);
+    // Takes the terminal, after this Ctrl-C and Ctrl-Z only reach the foreground job
+    pub fn init_job_control(&mut self) {
+        if let Some(pgid) = terminal::init() {
+            self.job_control = true;
+            self.pgid = pgid;
+            self.terminal_modes = terminal::get_modes();
         }
     }
-    fn exec_command(&mut self, token: (LineToCmdTokens, std::string::String)) -> i32 {
-        let parts = match parsers::parser::parse_cmd(token.1, self.status) {
-            Ok(m) => m,
-            Err(err) => {
-                utils::zash_error(err);
-                return 0;
+
+    pub fn run_line(&mut self, line: String) {
+        self.run_text(line, None);
+    }
+
+    // With the file and the line of it the text starts at, syntax errors say
+    // where they are, "script.sh:3: syntax error: ..."
+    pub fn run_text(&mut self, text: String, location: Option<(&str, usize)>) {
+        match parsers::parser::parse_line_with_aliases(&text, &self.aliases) {
+            Ok(list) => {
+                self.exec_list(&list);
             }
-        };
-        // println!("{:?}", parts);
-        let mut prev_command = None;
-        for (i, part) in parts.iter().enumerate() {
-            match part.0 {
-                ParseCmdTokens::Command => {
-                    let mut args = part.1.clone();
-                    // Probably a very hacky way of getting first arg then removing it
-                    let clon = args.clone(); // So it lives longer
-                    let command = match clon.get(0) {
-                        Some(m) => m,
-                        None => return 1,
-                    };
-                    args.remove(0);
-                    let status = match command.as_ref() {
-                        // Builtins
-                        "cd" => builtins::cd::cd(args),
-                        "exit" => builtins::exit::exit(args),
-                        command => {
-                            let stdin = prev_command.map_or(Stdio::inherit(), |output: Child| {
-                                Stdio::from(output.stdout.unwrap())
-                            });
-                            let stdout = if parts.get(i + 1).is_some()
-                                && parts.get(i + 1).unwrap().0 == ParseCmdTokens::Separator
-                            {
-                                Stdio::piped()
-                            } else {
-                                Stdio::inherit()
-                            };
-                            // If application does not print something with a new line at end, it would get overwritten by the shell
-                            match Command::new(command)
-                                .args(args.clone())
-                                .stdin(stdin)
-                                .stdout(stdout)
-                                .spawn()
-                            {
-                                Ok(output) => {
-                                    prev_command = Some(output);
-                                }
-                                Err(_) => {
-                                    // prev_command = None;
-                                    utils::zash_error(format!("command not found: {}", command));
-                                    return 1;
-                                }
-                            };
-                            0
-                        }
-                    };
-                    if status != 0 {
-                        return status;
-                    }
-                }
-                ParseCmdTokens::Separator => {
-                    // Maybe do something with redirects
-                    if part.1.clone() != vec!["|"] {
-                        utils::zash_error("this feature is currently not implemented");
-                        return 1;
-                    }
+            Err(err) => {
+                match location {
+                    Some((file, first_line)) => utils::zash_error(format!(
+                        "{}:{}: {}",
+                        file,
+                        first_line + err.line(&text) - 1,
+                        err.show(&text)
+                    )),
+                    None => utils::zash_error(err.show(&text)),
                 }
+                self.status = 2;
             }
         }
-        if let Some(final_command) = prev_command {
-            if let Some(status_code) = final_command.wait_with_output().unwrap().status.code() {
-                return status_code;
-            }
-        }
-        127
     }
 }
 
 #[derive(Helper)]
 struct ShellHelper {
     completer: ShellCompleter,
-    highlighter: MatchingBracketHighlighter,
-    validator: MatchingBracketValidator,
+    highlighter: ShellHighlighter,
+    // Unfinished commands are read with PS2 a line at a time, unless this is
+    // on and they are edited on more lines of the same buffer
+    validate: bool,
     hinter: HistoryHinter,
     prompt: String,
+    // RPROMPT, drawn at the right edge after the prompt
+    rprompt: String,
 }
 
 impl Completer for ShellHelper {
@@ -143,9 +145,9 @@ impl Completer for ShellHelper {
         &self,
         line: &str,
         pos: usize,
-        ctx: &Context<'_>,
+        _ctx: &Context<'_>,
     ) -> Result<(usize, Vec<Pair>), ReadlineError> {
-        self.completer.complete(line, pos, ctx)
+        Ok(self.completer.complete(line, pos))
     }
 }
 
@@ -163,7 +165,9 @@ impl Highlighter for ShellHelper {
         prompt: &'p str,
         default: bool,
     ) -> Cow<'b, str> {
-        if default {
+        if default && !self.rprompt.is_empty() {
+            Owned(prompt::with_rprompt(&self.prompt, &self.rprompt))
+        } else if default {
             Borrowed(&self.prompt)
         } else {
             Borrowed(prompt)
@@ -174,78 +178,168 @@ impl Highlighter for ShellHelper {
         Owned(format!("{}", hint.dimmed()))
     }
 
-    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
-        self.highlighter.highlight(line, pos)
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Owned(self.highlighter.highlight(line))
     }
 
-    fn highlight_char(&self, line: &str, pos: usize) -> bool {
-        self.highlighter.highlight_char(line, pos)
+    // Any key can change the colors, like closing a quote
+    fn highlight_char(&self, line: &str, _pos: usize) -> bool {
+        !line.is_empty()
     }
 }
 
 impl Validator for ShellHelper {
-    // This is commented because of issue (#5)
-    // fn validate(
-    //     &self,
-    //     ctx: &mut validate::ValidationContext,
-    // ) -> rustyline::Result<validate::ValidationResult> {
-    //     self.validator.validate(ctx)
-    // }
+    // Enter after an open quote, a trailing | or && or an if without its fi
+    // starts a new line instead of running the command
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if self.validate && parsers::parser::is_incomplete(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+// Rings the bell and runs "zash_notify command status seconds" if there is
+// such a function, so a desktop notification can say the command is done
+fn long_command_finished(shell: &mut Shell, command: &str, duration: Duration, bell: bool) {
+    if bell {
+        eprint!("\x07");
+    }
+    if shell.functions.contains_key("zash_notify") {
+        let status = shell.status;
+        shell.run_line(format!(
+            "zash_notify {} {} {}",
+            utils::quote(command),
+            status,
+            duration.as_secs()
+        ));
+        shell.status = status;
+    }
+}
 
-    fn validate_while_typing(&self) -> bool {
-        self.validator.validate_while_typing()
+// Adds the commands that other shells ran to the history of this one
+fn import_history(rl: &mut Editor<ShellHelper>, entries: Vec<Entry>, duplicates: Duplicates) {
+    for entry in entries {
+        history::add(rl.history_mut(), &entry.command, duplicates);
     }
 }
 
-pub fn shell() {
+pub fn shell(mut shell: Shell) {
     let homedir = utils::get_home_dir();
+    let settings = config::load(&homedir);
+    shell.duration_threshold = Duration::from_secs_f64(settings.duration_threshold);
+    completion::load_scripts(&mut shell, &homedir);
+    // $HISTSIZE and $HISTFILE from the rc files, an empty HISTFILE saves nothing
+    let history_size = shell
+        .variables
+        .get("HISTSIZE")
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(1000);
+    let history_path = match shell.variables.get("HISTFILE") {
+        Some("") => None,
+        Some(path) => Some(path.to_string()),
+        None => Some(settings.history_path(&homedir)),
+    };
+    let duplicates = settings.history_duplicates;
     let config = Config::builder()
-        .history_ignore_space(true)
-        .completion_type(CompletionType::List)
-        //.complete_path(true)
-        .edit_mode(EditMode::Emacs)
-        .output_stream(OutputStreamType::Stdout)
+        .max_history_size(history_size)
+        .history_ignore_dups(duplicates != Duplicates::Keep)
+        .history_ignore_space(settings.ignore_spaces)
+        .completion_type(settings.completion_type.into())
+        .edit_mode(settings.edit_mode.into())
+        .output_stream(settings.output_stream.into())
         .build();
 
     let helper = ShellHelper {
         completer: ShellCompleter::new(),
-        highlighter: MatchingBracketHighlighter::new(),
+        highlighter: ShellHighlighter::new(&settings.colors),
         hinter: HistoryHinter {},
         prompt: "".to_owned(),
-        validator: MatchingBracketValidator::new(),
+        rprompt: "".to_owned(),
+        validate: settings.validator,
     };
 
     let mut rl = Editor::with_config(config);
     rl.set_helper(Some(helper));
 
-    scripting::load_rc(homedir.clone());
-    let hispath = format!("{}/.zash_history", homedir);
-    if rl.load_history(&hispath).is_err() {
-        utils::zash_error("No previous history");
+    let mut history_file =
+        history_path.map(|path| HistoryFile::new(path, history_size, duplicates));
+    if let Some(file) = &mut history_file {
+        match file.load() {
+            Ok(entries) => import_history(&mut rl, entries, duplicates),
+            Err(err) => {
+                utils::zash_error(format!("{}: {}, the history won't be saved", file.path, err));
+                history_file = None;
+            }
+        }
     }
-    let mut shell = Shell::new();
 
     loop {
-        let mut current_dir = std::env::current_dir().unwrap().display().to_string();
-        if current_dir.starts_with(&homedir.to_string()) {
-            current_dir = current_dir.replace(&homedir.to_string(), "~");
+        // "[1]+  Done  sleep 1" for jobs that finished since the last prompt
+        shell.jobs.notify();
+        if settings.share_history {
+            // Errors are shown when this shell writes to the file
+            if let Some(Ok(entries)) = history_file.as_mut().map(HistoryFile::added) {
+                import_history(&mut rl, entries, duplicates);
+            }
         }
 
-        let p = format!(
-            "{}@{} {} {}{}{} ",
-            whoami::username().blue(),
-            whoami::hostname().blue(),
-            current_dir.cyan(),
-            "•".blue(),
-            "•".red(),
-            "•".yellow()
-        );
-        rl.helper_mut().expect("No helper").prompt = p.to_string();
+        let p = prompt::ps1(&mut shell, settings.prompt.as_deref());
+        let helper = rl.helper_mut().expect("No helper");
+        helper.prompt = p.clone();
+        helper.rprompt = prompt::rprompt(&shell);
+        helper.completer.update(&shell);
+        helper.highlighter.update(&shell);
         let readline = rl.readline(&p);
         match readline {
-            Ok(line) => {
-                rl.add_history_entry(line.as_str());
-                shell.run_line(line);
+            Ok(mut line) => {
+                // Keep reading lines with PS2 while the command is not finished,
+                // the validator already did it when it is on
+                let ps2 = prompt::ps2(&shell);
+                let helper = rl.helper_mut().expect("No helper");
+                helper.prompt = ps2.clone();
+                helper.rprompt.clear();
+                while parsers::parser::is_incomplete(&line) {
+                    match rl.readline(&ps2) {
+                        Ok(next) => {
+                            line.push('\n');
+                            line.push_str(&next);
+                        }
+                        Err(ReadlineError::Interrupted) => {
+                            line.clear();
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                // Written right away, other terminals can get it while it runs
+                if history::add(rl.history_mut(), &line, duplicates) {
+                    if let Some(file) = &mut history_file {
+                        match file.append(&line) {
+                            Ok(entries) if settings.share_history => {
+                                import_history(&mut rl, entries, duplicates)
+                            }
+                            Ok(_) => {}
+                            Err(err) => {
+                                utils::zash_error(format!(
+                                    "{}: {}, the history won't be saved",
+                                    file.path, err
+                                ));
+                                history_file = None;
+                            }
+                        }
+                    }
+                }
+                let started = Instant::now();
+                shell.run_line(line.clone());
+                let duration = started.elapsed();
+                shell.last_duration = Some(duration);
+                if duration >= shell.duration_threshold {
+                    long_command_finished(&mut shell, &line, duration, settings.bell);
+                }
+                // A break or return that had nothing to stop, or Ctrl-C
+                shell.flow = None;
             }
             Err(ReadlineError::Interrupted) => {
                 continue;
@@ -259,6 +353,5 @@ pub fn shell() {
                 break;
             }
         }
-        rl.save_history(&hispath).unwrap();
     }
 }
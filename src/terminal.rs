@@ -0,0 +1,81 @@
+// The controlling terminal, which process group gets to read from it and the
+// modes it is in. Only used when the shell is interactive.
+use std::io;
+
+const TERMINAL: i32 = libc::STDIN_FILENO;
+
+// Signals the shell ignores itself, children get the default handlers back
+const JOB_SIGNALS: &[i32] = &[
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+// Puts the shell in its own process group and takes the terminal.
+// Returns the process group of the shell, or None if stdin is not a terminal.
+pub fn init() -> Option<i32> {
+    if unsafe { libc::isatty(TERMINAL) } == 0 {
+        return None;
+    }
+    unsafe {
+        // Wait until we are in the foreground, if we were started with "zash &"
+        loop {
+            let pgid = libc::getpgrp();
+            if libc::tcgetpgrp(TERMINAL) == pgid {
+                break;
+            }
+            libc::kill(-pgid, libc::SIGTTIN);
+        }
+        ignore_signals();
+        let pid = libc::getpid();
+        if libc::getpgrp() != pid && libc::setpgid(pid, pid) < 0 {
+            return None;
+        }
+        libc::tcsetpgrp(TERMINAL, pid);
+        Some(pid)
+    }
+}
+
+pub fn ignore_signals() {
+    for signal in JOB_SIGNALS {
+        unsafe { libc::signal(*signal, libc::SIG_IGN) };
+    }
+}
+
+// For forked children, before they exec, and for "exec cmd"
+pub fn reset_signals() {
+    for signal in JOB_SIGNALS {
+        unsafe { libc::signal(*signal, libc::SIG_DFL) };
+    }
+}
+
+pub fn give_to(pgid: i32) {
+    unsafe { libc::tcsetpgrp(TERMINAL, pgid) };
+}
+
+pub fn get_modes() -> Option<Modes> {
+    let mut modes: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(TERMINAL, &mut modes) } < 0 {
+        return None;
+    }
+    Some(Modes(modes))
+}
+
+pub fn set_modes(modes: &Modes) -> io::Result<()> {
+    if unsafe { libc::tcsetattr(TERMINAL, libc::TCSADRAIN, &modes.0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// termios does not implement Debug
+#[derive(Clone, Copy)]
+pub struct Modes(pub libc::termios);
+
+impl std::fmt::Debug for Modes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Modes")
+    }
+}
@@ -19,7 +19,7 @@ pub fn error_string(errno: i32) -> String {
 
     let p = buf.as_mut_ptr();
     unsafe {
-        assert!(!(strerror_r(errno as c_int, p, buf.len()) < 0), "strerror_r failure");
+        assert!(strerror_r(errno as c_int, p, buf.len()) >= 0, "strerror_r failure");
 
         let p = p as *const _;
         str::from_utf8(CStr::from_ptr(p).to_bytes())
@@ -28,7 +28,58 @@ pub fn error_string(errno: i32) -> String {
     }
 }
 
-pub fn exit(code: i32) {
+// "Terminated", "Killed" etc.
+pub fn signal_description(signal: i32) -> String {
+    let description = unsafe { libc::strsignal(signal) };
+    if description.is_null() {
+        return format!("Signal {}", signal);
+    }
+    unsafe { CStr::from_ptr(description) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+const SIGNAL_NAMES: &[(i32, &str)] = &[
+    (libc::SIGHUP, "SIGHUP"),
+    (libc::SIGINT, "SIGINT"),
+    (libc::SIGQUIT, "SIGQUIT"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGTRAP, "SIGTRAP"),
+    (libc::SIGABRT, "SIGABRT"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGKILL, "SIGKILL"),
+    (libc::SIGUSR1, "SIGUSR1"),
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGUSR2, "SIGUSR2"),
+    (libc::SIGPIPE, "SIGPIPE"),
+    (libc::SIGALRM, "SIGALRM"),
+    (libc::SIGTERM, "SIGTERM"),
+    (libc::SIGCHLD, "SIGCHLD"),
+    (libc::SIGCONT, "SIGCONT"),
+    (libc::SIGSTOP, "SIGSTOP"),
+    (libc::SIGTSTP, "SIGTSTP"),
+    (libc::SIGTTIN, "SIGTTIN"),
+    (libc::SIGTTOU, "SIGTTOU"),
+    (libc::SIGURG, "SIGURG"),
+    (libc::SIGXCPU, "SIGXCPU"),
+    (libc::SIGXFSZ, "SIGXFSZ"),
+    (libc::SIGVTALRM, "SIGVTALRM"),
+    (libc::SIGPROF, "SIGPROF"),
+    (libc::SIGWINCH, "SIGWINCH"),
+    (libc::SIGIO, "SIGIO"),
+    (libc::SIGSYS, "SIGSYS"),
+];
+
+// "SIGSEGV" for 11
+pub fn signal_name(signal: i32) -> Option<&'static str> {
+    SIGNAL_NAMES
+        .iter()
+        .find(|(number, _)| *number == signal)
+        .map(|(_, name)| *name)
+}
+
+pub fn exit(code: i32) -> ! {
     std::process::exit(code);
 }
 
@@ -44,5 +95,23 @@ pub fn get_home_dir() -> String {
         exit(1);
     }
     let homedir_pathbuf = home_dir().unwrap();
-    return homedir_pathbuf.display().to_string();
+    homedir_pathbuf.display().to_string()
+}
+
+// Quotes text so the shell reads it back as one word
+pub fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+// Like quote, but words that don't need quotes are left alone
+pub fn quote_if_needed(text: &str) -> String {
+    if !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_-+./:=,@%^".contains(c))
+    {
+        text.to_string()
+    } else {
+        quote(text)
+    }
 }
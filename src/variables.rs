@@ -0,0 +1,280 @@
+// Shell variables. Exported variables are also kept in the environment of the
+// shell process, so children get them without anything extra.
+use std::collections::{BTreeMap, HashMap};
+
+use crate::arithmetic;
+use crate::utils;
+
+#[derive(Debug, Clone, Default)]
+pub struct Variable {
+    // None for variables that have attributes but no value, like "export FOO"
+    pub value: Option<String>,
+    pub exported: bool,
+    pub readonly: bool,
+    // "declare -i", values are evaluated as arithmetic when they are set
+    pub integer: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variables {
+    // The global variables first, then one scope for each function call
+    scopes: Vec<HashMap<String, Variable>>,
+}
+
+fn readonly_error(name: &str) -> String {
+    format!("{}: readonly variable", name)
+}
+
+impl Variables {
+    pub fn from_env() -> Self {
+        let globals = std::env::vars()
+            .map(|(name, value)| {
+                let variable = Variable {
+                    value: Some(value),
+                    exported: true,
+                    ..Default::default()
+                };
+                (name, variable)
+            })
+            .collect();
+        Self {
+            scopes: vec![globals],
+        }
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<&Variable> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn get_variable_mut(&mut self, name: &str) -> Option<&mut Variable> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_variable(name)?.value.as_deref()
+    }
+
+    // Puts the variable in the environment if it is exported, or takes it out
+    fn sync_env(&self, name: &str) {
+        match self.get_variable(name) {
+            Some(Variable {
+                value: Some(value),
+                exported: true,
+                ..
+            }) => std::env::set_var(name, value),
+            _ => std::env::remove_var(name),
+        }
+    }
+
+    // Sets the variable where it is visible from, a new one is global
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        // The readonly error comes first, "declare -ir n=1; n=1/0"
+        let integer = self
+            .get_variable(name)
+            .is_some_and(|variable| variable.integer && !variable.readonly);
+        let value = if integer {
+            arithmetic::evaluate(self, value)?.to_string()
+        } else {
+            value.to_string()
+        };
+        match self.get_variable_mut(name) {
+            Some(variable) if variable.readonly => return Err(readonly_error(name)),
+            Some(variable) => variable.value = Some(value),
+            None => {
+                self.scopes[0].insert(
+                    name.to_string(),
+                    Variable {
+                        value: Some(value),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+        self.sync_env(name);
+        Ok(())
+    }
+
+    // Creates the variable in the innermost scope, for "local" and "FOO=bar cmd".
+    // It is exported if the variable it hides was.
+    pub fn set_local(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        let hidden = self.get_variable(name);
+        if hidden.is_some_and(|variable| variable.readonly) {
+            return Err(readonly_error(name));
+        }
+        let variable = Variable {
+            value: value.map(str::to_string),
+            exported: hidden.is_some_and(|variable| variable.exported),
+            ..Default::default()
+        };
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), variable);
+        self.sync_env(name);
+        Ok(())
+    }
+
+    // Changes the attributes of a variable, creating it without a value if needed
+    pub fn set_attributes(
+        &mut self,
+        name: &str,
+        exported: Option<bool>,
+        readonly: bool,
+        integer: Option<bool>,
+    ) {
+        if self.get_variable(name).is_none() {
+            self.scopes[0].insert(name.to_string(), Variable::default());
+        }
+        let variable = self.get_variable_mut(name).unwrap();
+        if let Some(exported) = exported {
+            variable.exported = exported;
+        }
+        variable.readonly |= readonly;
+        if let Some(integer) = integer {
+            variable.integer = integer;
+        }
+        self.sync_env(name);
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<(), String> {
+        let scope = match self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find(|scope| scope.contains_key(name))
+        {
+            Some(scope) => scope,
+            None => return Ok(()),
+        };
+        if scope[name].readonly {
+            return Err(format!("{}: cannot unset: readonly variable", name));
+        }
+        scope.remove(name);
+        self.sync_env(name);
+        Ok(())
+    }
+
+    pub fn in_function(&self) -> bool {
+        self.scopes.len() > 1
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    // Drops the innermost scope, the variables it was hiding come back
+    pub fn pop_scope(&mut self) {
+        if !self.in_function() {
+            return;
+        }
+        let scope = self.scopes.pop().unwrap();
+        for name in scope.keys() {
+            self.sync_env(name);
+        }
+    }
+
+    // Every visible variable, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        let mut visible = BTreeMap::new();
+        for scope in &self.scopes {
+            visible.extend(scope.iter());
+        }
+        visible.into_iter()
+    }
+}
+
+// "declare -irx FOO='bar'", what "declare -p" and "export -p" print
+pub fn declaration(name: &str, variable: &Variable) -> String {
+    let mut flags = String::new();
+    if variable.integer {
+        flags.push('i');
+    }
+    if variable.readonly {
+        flags.push('r');
+    }
+    if variable.exported {
+        flags.push('x');
+    }
+    if flags.is_empty() {
+        flags.push('-');
+    }
+    match &variable.value {
+        Some(value) => format!("declare -{} {}={}", flags, name, utils::quote(value)),
+        None => format!("declare -{} {}", flags, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Variables;
+
+    #[test]
+    fn test_variables() {
+        let mut variables = Variables::from_env();
+        let env = |name| std::env::var(name).ok();
+
+        // Scopes
+        variables.set("zz_var", "global").unwrap();
+        variables.push_scope();
+        assert!(variables.in_function());
+        variables.set_local("zz_var", Some("local")).unwrap();
+        variables.set("zz_var_new", "new").unwrap();
+        assert_eq!(variables.get("zz_var"), Some("local"));
+        variables.pop_scope();
+        assert!(!variables.in_function());
+        assert_eq!(variables.get("zz_var"), Some("global"));
+        // New variables are global even in a function
+        assert_eq!(variables.get("zz_var_new"), Some("new"));
+        // The global scope is never popped
+        variables.pop_scope();
+        assert_eq!(variables.get("zz_var"), Some("global"));
+
+        // A local hiding an exported variable is exported too, the
+        // environment gets the old value back when the scope is popped
+        variables.set("zz_var_exported", "outer").unwrap();
+        variables.set_attributes("zz_var_exported", Some(true), false, None);
+        assert_eq!(env("zz_var_exported").as_deref(), Some("outer"));
+        variables.push_scope();
+        variables
+            .set_local("zz_var_exported", Some("inner"))
+            .unwrap();
+        assert!(variables.get_variable("zz_var_exported").unwrap().exported);
+        assert_eq!(env("zz_var_exported").as_deref(), Some("inner"));
+        // "local zz_var_exported" without a value takes it out of the environment
+        variables.set_local("zz_var_exported", None).unwrap();
+        assert_eq!(env("zz_var_exported"), None);
+        variables.pop_scope();
+        assert_eq!(env("zz_var_exported").as_deref(), Some("outer"));
+        variables.set_attributes("zz_var_exported", Some(false), false, None);
+        assert_eq!(env("zz_var_exported"), None);
+        assert_eq!(variables.get("zz_var_exported"), Some("outer"));
+
+        // Readonly variables
+        variables.set("zz_var_readonly", "1").unwrap();
+        variables.set_attributes("zz_var_readonly", None, true, None);
+        let error = Err("zz_var_readonly: readonly variable".to_string());
+        assert_eq!(variables.set("zz_var_readonly", "2"), error);
+        variables.push_scope();
+        assert_eq!(variables.set_local("zz_var_readonly", Some("2")), error);
+        variables.pop_scope();
+        assert_eq!(
+            variables.unset("zz_var_readonly"),
+            Err("zz_var_readonly: cannot unset: readonly variable".to_string())
+        );
+        assert_eq!(variables.get("zz_var_readonly"), Some("1"));
+
+        // Integer variables
+        variables.set_attributes("zz_var_integer", None, false, Some(true));
+        variables.set("zz_var_integer", "3 + 4").unwrap();
+        assert_eq!(variables.get("zz_var_integer"), Some("7"));
+        variables
+            .set("zz_var_integer", "zz_var_integer * 2")
+            .unwrap();
+        assert_eq!(variables.get("zz_var_integer"), Some("14"));
+        assert!(variables.set("zz_var_integer", "1 +").is_err());
+        assert_eq!(variables.get("zz_var_integer"), Some("14"));
+    }
+}